@@ -0,0 +1,1269 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::Action;
+use crate::domain::{DomainConfig, TAGS_NAMESPACE};
+
+/// A domain as reported by the hypervisor's list call.
+#[derive(Clone)]
+pub struct Domain {
+    /// Empty if the backend could not report it.
+    pub uuid: String,
+    pub id: String,
+    pub name: String,
+    pub state: String,
+    /// `None` if the backend could not report it.
+    pub persistent: Option<bool>,
+    pub autostart: Option<bool>,
+}
+
+/// A domain snapshot as reported by `virsh snapshot-list --parent`.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub name: String,
+    pub parent: Option<String>,
+    /// As displayed, e.g. `2024-05-01 12:30:00 +0200`.
+    pub creation_time: String,
+    /// Domain state captured by the snapshot, e.g. `running` or `disk-snapshot`.
+    pub state: String,
+    /// The snapshot the domain currently runs on top of.
+    pub current: bool,
+}
+
+/// Cumulative resource counters of a running domain, as in `virsh domstats`.
+#[derive(Clone, Default)]
+pub struct DomainStats {
+    pub name: String,
+    /// `cpu.time`, in nanoseconds.
+    pub cpu_time: u64,
+    pub vcpus: u32,
+    /// `balloon.current`, in KiB.
+    pub balloon_kib: Option<u64>,
+    /// `balloon.rss`, in KiB.
+    pub rss_kib: Option<u64>,
+    /// Bytes summed over all disks / interfaces.
+    pub block_read: u64,
+    pub block_write: u64,
+    pub net_rx: u64,
+    pub net_tx: u64,
+}
+
+impl DomainStats {
+    /// Record one `domstats` field; unknown fields are ignored.
+    pub fn apply(&mut self, key: &str, value: u64) {
+        match key {
+            "cpu.time" => self.cpu_time = value,
+            "vcpu.current" => self.vcpus = value as u32,
+            "balloon.current" => self.balloon_kib = Some(value),
+            "balloon.rss" => self.rss_kib = Some(value),
+            _ => match key.split('.').collect::<Vec<_>>().as_slice() {
+                ["block", _, "rd", "bytes"] => self.block_read += value,
+                ["block", _, "wr", "bytes"] => self.block_write += value,
+                ["net", _, "rx", "bytes"] => self.net_rx += value,
+                ["net", _, "tx", "bytes"] => self.net_tx += value,
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Parameters for a new snapshot.
+#[derive(Clone, Default)]
+pub struct SnapshotSpec {
+    pub name: String,
+    pub description: String,
+    /// Snapshot only the disks (external, without memory state) instead of
+    /// a full snapshot that includes memory when the domain is running.
+    pub disk_only: bool,
+}
+
+/// Parameters for a new storage volume.
+#[derive(Clone)]
+pub struct NewVolume {
+    pub pool: String,
+    pub name: String,
+    /// In bytes.
+    pub capacity: u64,
+    /// e.g. `qcow2` or `raw`
+    pub format: String,
+    /// Path and format of the volume this one is a copy-on-write overlay of.
+    pub backing: Option<(String, String)>,
+}
+
+/// Size of a storage volume, as in `virsh vol-info --bytes`.
+#[derive(Clone, Copy)]
+pub struct VolumeInfo {
+    /// In bytes, as seen by the guest.
+    pub capacity: u64,
+    /// Bytes used on the host.
+    pub allocation: u64,
+}
+
+/// An IPv4 address of a domain, as reported by `domifaddr`.
+#[derive(Clone)]
+pub struct IfAddr {
+    /// Host device (`vnet0`) for lease and arp sources, guest interface (`eth0`) for the agent.
+    pub interface: String,
+    pub mac: Option<String>,
+    pub ip: String,
+}
+
+/// Everything yalv-rust needs from a hypervisor connection.
+///
+/// Errors are returned as human-readable messages.
+pub trait Backend: Send + Sync {
+    /// The connection URI this backend talks to, e.g. `qemu:///system`.
+    fn uri(&self) -> &str;
+    fn list_domains(&self, show_all: bool) -> Result<Vec<Domain>, String>;
+    /// The domain's XML; with `inactive`, the configuration it boots with next time.
+    fn dumpxml(&self, name: &str, inactive: bool) -> Result<String, String>;
+    /// Return the IPv4 addresses reported by `source` ("lease", "arp" or "agent").
+    fn domifaddr(&self, name: &str, source: &str) -> Result<Vec<IfAddr>, String>;
+    /// Define a persistent domain from `xml`, or replace the configuration of
+    /// the one with the same name and UUID. The XML is validated against the schema.
+    fn define(&self, xml: &str) -> Result<(), String>;
+    /// Apply a power or snapshot action to the domain.
+    fn lifecycle(&self, name: &str, action: &Action) -> Result<(), String>;
+    fn snapshots(&self, name: &str) -> Result<Vec<Snapshot>, String>;
+    fn create_volume(&self, volume: &NewVolume) -> Result<(), String>;
+    /// Create a raw volume `name` in `pool` holding the contents of the local `file`.
+    fn upload_volume(&self, pool: &str, name: &str, file: &Path) -> Result<(), String>;
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String>;
+    /// Pool and name of the volume with the file or device `path`.
+    fn volume_at(&self, path: &str) -> Result<(String, String), String>;
+    fn volume_path(&self, pool: &str, name: &str) -> Result<String, String>;
+    fn volume_info(&self, pool: &str, name: &str) -> Result<VolumeInfo, String>;
+    /// Copy the volume `source` of `pool` to a new volume `name` in the same pool and format.
+    fn clone_volume(&self, pool: &str, source: &str, name: &str) -> Result<(), String>;
+    /// Sample the counters of every active domain.
+    fn domstats(&self) -> Result<Vec<DomainStats>, String>;
+    /// Command line that attaches to the domain's serial console; it is run in a PTY.
+    fn console_command(&self, name: &str) -> Result<Vec<String>, String>;
+}
+
+/// Backend that shells out to the `virsh` command.
+pub struct VirshBackend {
+    /// URI passed to every `virsh -c`; `None` lets virsh pick its default.
+    connect: Option<String>,
+    /// Canonical URI as reported by `virsh uri`.
+    uri: String,
+}
+
+impl VirshBackend {
+    /// Connect to `uri` (or virsh's default) and resolve the canonical URI.
+    pub fn connect(uri: Option<&str>) -> Result<Self, String> {
+        let mut backend = Self {
+            connect: uri.map(str::to_string),
+            uri: String::new(),
+        };
+        backend.uri = backend.run(&["uri"])?.trim().to_string();
+        info!("Connected to {} via virsh", backend.uri);
+        Ok(backend)
+    }
+
+    fn command(&self) -> Command {
+        let mut cmd = Command::new("virsh");
+        if let Some(uri) = &self.connect {
+            cmd.args(["-c", uri]);
+        }
+        cmd
+    }
+
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let output = self
+            .command()
+            .args(args)
+            .output()
+            .map_err(|e| format!("unable to run virsh {}: {e}", args[0]))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("virsh {} failed: {}", args[0], stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Backend for VirshBackend {
+    fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    fn list_domains(&self, show_all: bool) -> Result<Vec<Domain>, String> {
+        info!("Running virsh list (show_all={})", show_all);
+        // --managed-save reports inactive domains with a saved image as "saved".
+        let args: &[&str] = if show_all {
+            &["list", "--all", "--managed-save"]
+        } else {
+            &["list"]
+        };
+        let stdout = self.run(args)?;
+        let mut domains = parse_virsh_output(&stdout);
+        // The table has no UUIDs; older virsh cannot combine --uuid with --name,
+        // in which case the UUIDs are left empty.
+        let args: &[&str] = if show_all {
+            &["list", "--all", "--uuid", "--name"]
+        } else {
+            &["list", "--uuid", "--name"]
+        };
+        match self.run(args) {
+            Ok(stdout) => {
+                let uuids = parse_uuid_name_output(&stdout);
+                for d in &mut domains {
+                    if let Some(uuid) = uuids.get(&d.name) {
+                        d.uuid = uuid.clone();
+                    }
+                }
+            }
+            Err(e) => warn!("Unable to list domain UUIDs: {e}"),
+        }
+        // Neither flag is in the table either; each needs a filtered name list.
+        for (flag, filter) in [("persistent", "--persistent"), ("autostart", "--autostart")] {
+            match self.run(&["list", "--all", "--name", filter]) {
+                Ok(stdout) => {
+                    let names: Vec<&str> = stdout.lines().map(str::trim).collect();
+                    for d in &mut domains {
+                        let value = Some(names.contains(&d.name.as_str()));
+                        match flag {
+                            "persistent" => d.persistent = value,
+                            _ => d.autostart = value,
+                        }
+                    }
+                }
+                Err(e) => warn!("Unable to list {flag} domains: {e}"),
+            }
+        }
+        Ok(domains)
+    }
+
+    fn dumpxml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        if inactive {
+            self.run(&["dumpxml", "--inactive", name])
+        } else {
+            self.run(&["dumpxml", name])
+        }
+    }
+
+    fn define(&self, xml: &str) -> Result<(), String> {
+        let mut child = self
+            .command()
+            .args(["define", "--validate", "/dev/stdin"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("unable to run virsh define: {e}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(xml.as_bytes())
+                .map_err(|e| format!("unable to pass the XML to virsh define: {e}"))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("unable to run virsh define: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("virsh define failed: {}", stderr.trim()));
+        }
+        Ok(())
+    }
+
+    fn domifaddr(&self, name: &str, source: &str) -> Result<Vec<IfAddr>, String> {
+        let stdout = self.run(&["domifaddr", name, "--source", source])?;
+        Ok(parse_domifaddr_output(&stdout))
+    }
+
+    fn lifecycle(&self, name: &str, action: &Action) -> Result<(), String> {
+        let command = match action {
+            Action::CreateSnapshot(spec) => {
+                let mut args = vec!["snapshot-create-as", name, "--name", &spec.name];
+                if !spec.description.is_empty() {
+                    args.extend(["--description", &spec.description]);
+                }
+                if spec.disk_only {
+                    args.extend(["--disk-only", "--atomic"]);
+                }
+                return self.run(&args).map(|_| ());
+            }
+            Action::RevertSnapshot(snapshot) => {
+                return self.run(&["snapshot-revert", name, snapshot]).map(|_| ());
+            }
+            Action::DeleteSnapshot(snapshot) => {
+                return self.run(&["snapshot-delete", name, snapshot]).map(|_| ());
+            }
+            // `start` resumes from the managed save image when there is one.
+            Action::Start | Action::Restore => "start",
+            Action::Shutdown => "shutdown",
+            Action::Reboot => "reboot",
+            Action::Reset => "reset",
+            Action::Destroy => "destroy",
+            Action::Suspend => "suspend",
+            Action::Resume => "resume",
+            Action::ManagedSave => "managedsave",
+        };
+        self.run(&[command, name]).map(|_| ())
+    }
+
+    fn snapshots(&self, name: &str) -> Result<Vec<Snapshot>, String> {
+        let stdout = self.run(&["snapshot-list", name, "--parent"])?;
+        let mut snapshots = parse_snapshot_list_output(&stdout);
+        // Fails when there is no current snapshot.
+        if let Ok(current) = self.run(&["snapshot-current", name, "--name"]) {
+            let current = current.trim();
+            for s in &mut snapshots {
+                s.current = s.name == current;
+            }
+        }
+        Ok(snapshots)
+    }
+
+    fn create_volume(&self, volume: &NewVolume) -> Result<(), String> {
+        let capacity = volume.capacity.to_string();
+        let mut args = vec![
+            "vol-create-as",
+            &volume.pool,
+            &volume.name,
+            &capacity,
+            "--format",
+            &volume.format,
+        ];
+        if let Some((path, format)) = &volume.backing {
+            args.extend(["--backing-vol", path, "--backing-vol-format", format]);
+        }
+        self.run(&args).map(|_| ())
+    }
+
+    fn upload_volume(&self, pool: &str, name: &str, file: &Path) -> Result<(), String> {
+        let size = fs::metadata(file)
+            .map_err(|e| format!("cannot read {}: {e}", file.display()))?
+            .len();
+        self.create_volume(&NewVolume {
+            pool: pool.to_string(),
+            name: name.to_string(),
+            capacity: size,
+            format: "raw".to_string(),
+            backing: None,
+        })?;
+        let file = file.to_string_lossy();
+        if let Err(e) = self.run(&["vol-upload", "--pool", pool, name, &file]) {
+            if let Err(e) = self.delete_volume(pool, name) {
+                warn!("Cannot remove the incomplete volume {name}: {e}");
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String> {
+        self.run(&["vol-delete", "--pool", pool, name]).map(|_| ())
+    }
+
+    fn volume_at(&self, path: &str) -> Result<(String, String), String> {
+        let pool = self.run(&["vol-pool", path])?;
+        let name = self.run(&["vol-name", path])?;
+        Ok((pool.trim().to_string(), name.trim().to_string()))
+    }
+
+    fn volume_path(&self, pool: &str, name: &str) -> Result<String, String> {
+        Ok(self.run(&["vol-path", "--pool", pool, name])?.trim().to_string())
+    }
+
+    fn volume_info(&self, pool: &str, name: &str) -> Result<VolumeInfo, String> {
+        let stdout = self.run(&["vol-info", "--bytes", "--pool", pool, name])?;
+        parse_vol_info_output(&stdout).ok_or_else(|| format!("unexpected vol-info output for {name}"))
+    }
+
+    fn clone_volume(&self, pool: &str, source: &str, name: &str) -> Result<(), String> {
+        self.run(&["vol-clone", "--pool", pool, source, name]).map(|_| ())
+    }
+
+    fn domstats(&self) -> Result<Vec<DomainStats>, String> {
+        let stdout = self.run(&[
+            "domstats",
+            "--list-active",
+            "--cpu-total",
+            "--balloon",
+            "--vcpu",
+            "--block",
+            "--interface",
+        ])?;
+        Ok(parse_domstats_output(&stdout))
+    }
+
+    fn console_command(&self, name: &str) -> Result<Vec<String>, String> {
+        let mut cmd = vec!["virsh".to_string()];
+        if let Some(uri) = &self.connect {
+            cmd.extend(["-c".to_string(), uri.clone()]);
+        }
+        cmd.extend(["console".to_string(), name.to_string()]);
+        Ok(cmd)
+    }
+}
+
+/// Parse the tabular output of `virsh list --all`.
+///
+/// Example input:
+/// ```text
+///  Id   Name       State
+/// --------------------------
+///  1    vm1        running
+///  -    vm2        shut off
+/// ```
+fn parse_virsh_output(output: &str) -> Vec<Domain> {
+    let mut domains = Vec::new();
+    for line in output.lines().skip(2) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.chars().all(|c| c == '-') {
+            continue;
+        }
+        let parts: Vec<&str> = trimmed.split_whitespace().collect();
+        if parts.len() >= 3 {
+            domains.push(Domain {
+                uuid: String::new(),
+                id: parts[0].to_string(),
+                name: parts[1].to_string(),
+                state: parts[2..].join(" "),
+                persistent: None,
+                autostart: None,
+            });
+        }
+    }
+    domains
+}
+
+/// Parse the output of `virsh list --uuid --name` into a name -> UUID map.
+///
+/// Example input:
+/// ```text
+/// 4dea22b3-1d52-d8f3-2516-782e98ab3fa0  vm1
+/// ```
+fn parse_uuid_name_output(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let uuid = parts.next()?;
+            let name = parts.next()?;
+            Some((name.to_string(), uuid.to_string()))
+        })
+        .collect()
+}
+
+/// Parse the output of `virsh snapshot-list DOMAIN --parent`.
+///
+/// Example input:
+/// ```text
+///  Name        Creation Time               State     Parent
+/// --------------------------------------------------------------
+///  base        2024-05-01 12:30:00 +0200   shutoff
+///  upgraded    2024-05-02 09:00:00 +0200   running   base
+/// ```
+fn parse_snapshot_list_output(output: &str) -> Vec<Snapshot> {
+    output
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 5 {
+                return None;
+            }
+            Some(Snapshot {
+                name: parts[0].to_string(),
+                creation_time: parts[1..4].join(" "),
+                state: parts[4].to_string(),
+                parent: parts.get(5).map(|p| p.to_string()),
+                current: false,
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of `virsh domstats`.
+///
+/// Example input:
+/// ```text
+/// Domain: 'vm1'
+///   cpu.time=4061531410
+///   balloon.current=2097152
+///   balloon.rss=617428
+///   vcpu.current=2
+///   block.0.rd.bytes=226341376
+///   net.0.rx.bytes=9238
+/// ```
+fn parse_domstats_output(output: &str) -> Vec<DomainStats> {
+    let mut stats: Vec<DomainStats> = Vec::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("Domain: ") {
+            stats.push(DomainStats {
+                name: name.trim_matches('\'').to_string(),
+                ..DomainStats::default()
+            });
+            continue;
+        }
+        let (Some(current), Some((key, value))) = (stats.last_mut(), line.split_once('=')) else {
+            continue;
+        };
+        if let Ok(value) = value.parse::<u64>() {
+            current.apply(key, value);
+        }
+    }
+    stats
+}
+
+/// Parse the sizes from `virsh vol-info --bytes`.
+///
+/// Example input:
+/// ```text
+/// Name:           web01.qcow2
+/// Type:           file
+/// Capacity:       21474836480 bytes
+/// Allocation:     3221225472 bytes
+/// ```
+fn parse_vol_info_output(output: &str) -> Option<VolumeInfo> {
+    let bytes = |label: &str| {
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(label))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|n| n.parse().ok())
+    };
+    Some(VolumeInfo {
+        capacity: bytes("Capacity:")?,
+        allocation: bytes("Allocation:")?,
+    })
+}
+
+/// Parse IPv4 addresses from `virsh domifaddr` output.
+///
+/// Output format; further addresses of an interface have `-` as name and MAC:
+///  Name       MAC address          Protocol     Address
+/// -------------------------------------------------------
+///  vnet0      52:54:00:xx:xx:xx    ipv4         192.168.122.x/24
+///  -          -                    ipv4         10.0.0.x/8
+fn parse_domifaddr_output(output: &str) -> Vec<IfAddr> {
+    let mut addrs: Vec<IfAddr> = Vec::new();
+    let (mut interface, mut mac) = (String::new(), None);
+    for line in output.lines().skip(2) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 4 {
+            continue;
+        }
+        if parts[0] != "-" {
+            interface = parts[0].to_string();
+            mac = Some(parts[1].to_string()).filter(|m| m != "-");
+        }
+        if parts[2] == "ipv4"
+            && let Some(ip) = parts[3].split('/').next()
+            && !addrs.iter().any(|a| a.ip == ip)
+        {
+            addrs.push(IfAddr {
+                interface: interface.clone(),
+                mac: mac.clone(),
+                ip: ip.to_string(),
+            });
+        }
+    }
+    addrs
+}
+
+/// A domain held by [`FakeBackend`].
+pub struct FakeDomain {
+    pub uuid: String,
+    pub name: String,
+    pub state: String,
+    pub xml: String,
+    /// Configuration defined while running, applied when the domain shuts off.
+    pub inactive_xml: Option<String>,
+    /// source -> IPv4 addresses
+    pub ips: HashMap<String, Vec<IfAddr>>,
+    pub snapshots: Vec<Snapshot>,
+    pub autostart: bool,
+}
+
+impl FakeDomain {
+    /// A minimal KVM guest with one disk and one NIC on the default network.
+    pub fn new(index: usize, name: &str, state: &str, vcpu: u32, mem_kib: u64) -> Self {
+        let uuid = format!("00000000-0000-4000-8000-{index:012x}");
+        let xml = format!(
+            "<domain type='kvm'><name>{name}</name><uuid>{uuid}</uuid>\
+             <memory unit='KiB'>{mem_kib}</memory><vcpu>{vcpu}</vcpu>\
+             <devices><emulator>/usr/bin/qemu-system-x86_64</emulator>\
+             <disk type='file' device='disk'><driver name='qemu' type='qcow2'/><source file='/var/lib/libvirt/images/{name}.qcow2'/><target dev='vda' bus='virtio'/></disk>\
+             <interface type='network'><mac address='52:54:00:00:{:02x}:{:02x}'/><source network='default'/><model type='virtio'/></interface>\
+             </devices></domain>",
+            (index >> 8) & 0xff,
+            index & 0xff,
+        );
+        Self {
+            uuid,
+            name: name.to_string(),
+            state: state.to_string(),
+            xml,
+            inactive_xml: None,
+            ips: HashMap::new(),
+            snapshots: Vec::new(),
+            autostart: false,
+        }
+    }
+
+    /// A domain described by a full `dumpxml` document, e.g. one of the `fixtures/`.
+    pub fn from_xml(xml: &str, state: &str) -> Self {
+        let config = DomainConfig::parse(xml).unwrap_or_default();
+        Self {
+            uuid: config.uuid.unwrap_or_default(),
+            name: config.name,
+            state: state.to_string(),
+            xml: xml.to_string(),
+            inactive_xml: None,
+            ips: HashMap::new(),
+            snapshots: Vec::new(),
+            autostart: false,
+        }
+    }
+
+    pub fn with_ip(mut self, source: &str, interface: &str, mac: &str, ip: &str) -> Self {
+        self.ips.entry(source.to_string()).or_default().push(IfAddr {
+            interface: interface.to_string(),
+            mac: Some(mac.to_string()),
+            ip: ip.to_string(),
+        });
+        self
+    }
+
+    /// Give the domain a different configuration for its next boot.
+    pub fn with_inactive_xml(mut self, xml: &str) -> Self {
+        self.inactive_xml = Some(xml.to_string());
+        self
+    }
+
+    /// Tag the domain the way `virsh metadata ... urn:yalv-rust:tags` does.
+    pub fn with_tags(mut self, tags: &str) -> Self {
+        let element = format!("<yalv:tags xmlns:yalv=\"{TAGS_NAMESPACE}\">{tags}</yalv:tags>");
+        self.xml = match self.xml.find("</metadata>") {
+            Some(end) => format!("{}  {element}\n  {}", &self.xml[..end], &self.xml[end..]),
+            None => self.xml.replacen("</name>", &format!("</name><metadata>{element}</metadata>"), 1),
+        };
+        self
+    }
+
+    pub fn with_autostart(mut self) -> Self {
+        self.autostart = true;
+        self
+    }
+
+    /// Add a snapshot on top of the current one, which it then replaces.
+    pub fn with_snapshot(mut self, name: &str, creation_time: &str, state: &str) -> Self {
+        self.add_snapshot(name, creation_time, state);
+        self
+    }
+
+    fn add_snapshot(&mut self, name: &str, creation_time: &str, state: &str) {
+        let parent = self.snapshots.iter().find(|s| s.current).map(|s| s.name.clone());
+        for s in &mut self.snapshots {
+            s.current = false;
+        }
+        self.snapshots.push(Snapshot {
+            name: name.to_string(),
+            parent,
+            creation_time: creation_time.to_string(),
+            state: state.to_string(),
+            current: true,
+        });
+    }
+
+    /// Make `name` the current snapshot, so the next one branches off it.
+    pub fn with_current_snapshot(mut self, name: &str) -> Self {
+        for s in &mut self.snapshots {
+            s.current = s.name == name;
+        }
+        self
+    }
+}
+
+/// Directory of the fake backend's only storage pool, `default`.
+const FAKE_POOL_DIR: &str = "/var/lib/libvirt/images";
+
+/// A volume held by [`FakeBackend`].
+struct FakeVolume {
+    pool: String,
+    name: String,
+    info: VolumeInfo,
+}
+
+type DumpxmlHook = Box<dyn Fn(&str) + Send + Sync>;
+
+/// In-memory backend for running the TUI without libvirt installed.
+pub struct FakeBackend {
+    domains: Mutex<Vec<FakeDomain>>,
+    /// The volumes of the `default` pool; disks of the domains in its directory are volumes from the start.
+    volumes: Mutex<Vec<FakeVolume>>,
+    /// Simulated round-trip time added to every call.
+    latency: Duration,
+    /// Time zero for the simulated resource counters.
+    started: Instant,
+    /// Error every define fails with, to test what happens after a failed define.
+    define_error: Option<String>,
+    /// Called with the domain name before every dumpxml, to act in the middle of a list.
+    dumpxml_hook: Option<DumpxmlHook>,
+    uri: String,
+}
+
+impl FakeBackend {
+    pub fn new(domains: Vec<FakeDomain>) -> Self {
+        let volumes = domains
+            .iter()
+            .flat_map(|d| DomainConfig::parse(&d.xml).unwrap_or_default().disks)
+            .filter_map(|disk| {
+                let name = disk.source.as_deref()?.strip_prefix(FAKE_POOL_DIR)?.strip_prefix('/')?.to_string();
+                let (capacity, allocation) = if disk.device == "cdrom" { (372736, 372736) } else { (20 << 30, 3 << 30) };
+                Some(FakeVolume {
+                    pool: "default".to_string(),
+                    name,
+                    info: VolumeInfo { capacity, allocation },
+                })
+            })
+            .collect();
+        Self {
+            domains: Mutex::new(domains),
+            volumes: Mutex::new(volumes),
+            latency: Duration::ZERO,
+            started: Instant::now(),
+            define_error: None,
+            dumpxml_hook: None,
+            uri: "test:///demo".to_string(),
+        }
+    }
+
+    /// A small set of domains in mixed states, used by `--demo`.
+    pub fn demo() -> Self {
+        Self::new(vec![
+            FakeDomain::from_xml(include_str!("../fixtures/web01-uefi.xml"), "running")
+                .with_ip("lease", "vnet0", "52:54:00:6c:2a:01", "192.168.122.10")
+                .with_tags("web prod")
+                .with_snapshot("installed", "2024-03-02 10:15:00 +0000", "shutoff")
+                .with_snapshot("pre-upgrade", "2024-04-20 18:02:11 +0000", "running")
+                .with_current_snapshot("installed")
+                .with_snapshot("tls-test", "2024-05-01 09:30:45 +0000", "running")
+                .with_current_snapshot("pre-upgrade"),
+            FakeDomain::from_xml(include_str!("../fixtures/db01-network-disks.xml"), "running")
+                .with_ip("agent", "lo", "00:00:00:00:00:00", "127.0.0.1")
+                .with_ip("agent", "eth0", "52:54:00:6c:2a:02", "192.168.122.11")
+                .with_ip("agent", "docker0", "02:42:4f:1a:00:01", "172.17.0.1")
+                .with_inactive_xml(include_str!("../fixtures/db01-network-disks-inactive.xml"))
+                .with_autostart(),
+            FakeDomain::from_xml(include_str!("../fixtures/build-bridge-macvtap.xml"), "paused").with_tags("ci build"),
+            FakeDomain::new(4, "template", "shut off", 1, 1048576),
+        ])
+    }
+
+    /// `count` identical running domains, each call delayed by `latency`.
+    #[cfg(test)]
+    pub fn generated(count: usize, latency: Duration) -> Self {
+        let domains = (0..count)
+            .map(|i| FakeDomain::new(i, &format!("vm{i:04}"), "running", 2, 2097152))
+            .collect();
+        Self {
+            latency,
+            ..Self::new(domains)
+        }
+    }
+
+    /// Make every define fail with `error`.
+    #[cfg(test)]
+    pub fn with_define_error(self, error: &str) -> Self {
+        Self {
+            define_error: Some(error.to_string()),
+            ..self
+        }
+    }
+
+    /// Call `hook` with the domain name before every dumpxml.
+    #[cfg(test)]
+    pub fn with_dumpxml_hook(self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self {
+            dumpxml_hook: Some(Box::new(hook)),
+            ..self
+        }
+    }
+
+    /// Pretend to be connected to `uri`, e.g. to list several fake hosts.
+    #[cfg(test)]
+    pub fn with_uri(self, uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            ..self
+        }
+    }
+
+    fn with_domain<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut FakeDomain) -> Result<T, String>,
+    ) -> Result<T, String> {
+        thread::sleep(self.latency);
+        let mut domains = self.domains.lock().unwrap();
+        let domain = domains
+            .iter_mut()
+            .find(|d| d.name == name)
+            .ok_or_else(|| format!("domain '{name}' not found"))?;
+        f(domain)
+    }
+}
+
+impl Backend for FakeBackend {
+    fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    fn list_domains(&self, show_all: bool) -> Result<Vec<Domain>, String> {
+        thread::sleep(self.latency);
+        let domains = self.domains.lock().unwrap();
+        let mut next_id = 1;
+        let mut list = Vec::new();
+        for d in domains.iter() {
+            let running = !matches!(d.state.as_str(), "shut off" | "saved");
+            let id = if running {
+                next_id += 1;
+                (next_id - 1).to_string()
+            } else {
+                "-".to_string()
+            };
+            if show_all || running {
+                list.push(Domain {
+                    uuid: d.uuid.clone(),
+                    id,
+                    name: d.name.clone(),
+                    state: d.state.clone(),
+                    persistent: Some(true),
+                    autostart: Some(d.autostart),
+                });
+            }
+        }
+        Ok(list)
+    }
+
+    fn dumpxml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        if let Some(hook) = &self.dumpxml_hook {
+            hook(name);
+        }
+        self.with_domain(name, |d| match &d.inactive_xml {
+            Some(xml) if inactive => Ok(xml.clone()),
+            _ => Ok(d.xml.clone()),
+        })
+    }
+
+    fn define(&self, xml: &str) -> Result<(), String> {
+        thread::sleep(self.latency);
+        if let Some(error) = &self.define_error {
+            return Err(error.clone());
+        }
+        let config = DomainConfig::parse(xml).map_err(|e| format!("XML error: {e}"))?;
+        if config.name.is_empty() {
+            return Err("the XML has no domain name".to_string());
+        }
+        let mut domains = self.domains.lock().unwrap();
+        if let Some(uuid) = &config.uuid
+            && let Some(other) = domains.iter().find(|d| &d.uuid == uuid && d.name != config.name)
+        {
+            return Err(format!("domain '{}' already exists with uuid {uuid}", other.name));
+        }
+        match domains.iter_mut().find(|d| d.name == config.name) {
+            Some(d) => {
+                if let Some(uuid) = config.uuid.as_ref().filter(|&u| u != &d.uuid) {
+                    return Err(format!("domain '{}' already exists with uuid {}, not {uuid}", d.name, d.uuid));
+                }
+                if matches!(d.state.as_str(), "shut off" | "saved") {
+                    d.xml = xml.to_string();
+                } else {
+                    d.inactive_xml = Some(xml.to_string());
+                }
+            }
+            None => {
+                let mut domain = FakeDomain::from_xml(xml, "shut off");
+                // libvirt generates a UUID when the XML has none.
+                if domain.uuid.is_empty() {
+                    domain.uuid = format!("00000000-0000-4000-8000-{:012x}", domains.len() + 1);
+                    domain.xml = xml.replacen("</name>", &format!("</name>\n  <uuid>{}</uuid>", domain.uuid), 1);
+                }
+                domains.push(domain);
+            }
+        }
+        Ok(())
+    }
+
+    fn domifaddr(&self, name: &str, source: &str) -> Result<Vec<IfAddr>, String> {
+        self.with_domain(name, |d| {
+            if d.state != "running" {
+                return Err(format!("domain '{name}' is not running"));
+            }
+            Ok(d.ips.get(source).cloned().unwrap_or_default())
+        })
+    }
+
+    fn lifecycle(&self, name: &str, action: &Action) -> Result<(), String> {
+        self.with_domain(name, |d| {
+            if !action.applies_to(&d.state) {
+                return Err(format!("domain '{name}' is {}", d.state));
+            }
+            let to = match action {
+                Action::CreateSnapshot(spec) => {
+                    if d.snapshots.iter().any(|s| s.name == spec.name) {
+                        return Err(format!("snapshot '{}' already exists", spec.name));
+                    }
+                    let state = if spec.disk_only {
+                        "disk-snapshot".to_string()
+                    } else if d.state == "shut off" {
+                        "shutoff".to_string()
+                    } else {
+                        d.state.clone()
+                    };
+                    let now = time::OffsetDateTime::now_utc();
+                    let creation_time = format!(
+                        "{} {:02}:{:02}:{:02} +0000",
+                        now.date(),
+                        now.hour(),
+                        now.minute(),
+                        now.second()
+                    );
+                    d.add_snapshot(&spec.name, &creation_time, &state);
+                    return Ok(());
+                }
+                Action::RevertSnapshot(snapshot) => {
+                    let target = d
+                        .snapshots
+                        .iter()
+                        .find(|s| &s.name == snapshot)
+                        .ok_or_else(|| format!("snapshot '{snapshot}' not found"))?;
+                    let state = match target.state.as_str() {
+                        "running" | "paused" => target.state.clone(),
+                        _ => "shut off".to_string(),
+                    };
+                    for s in &mut d.snapshots {
+                        s.current = &s.name == snapshot;
+                    }
+                    d.state = state;
+                    return Ok(());
+                }
+                Action::DeleteSnapshot(snapshot) => {
+                    let index = d
+                        .snapshots
+                        .iter()
+                        .position(|s| &s.name == snapshot)
+                        .ok_or_else(|| format!("snapshot '{snapshot}' not found"))?;
+                    let removed = d.snapshots.remove(index);
+                    // Like libvirt, children move up to the deleted snapshot's parent.
+                    for s in &mut d.snapshots {
+                        if s.parent.as_ref() == Some(&removed.name) {
+                            s.parent = removed.parent.clone();
+                        }
+                        if removed.current && Some(&s.name) == removed.parent.as_ref() {
+                            s.current = true;
+                        }
+                    }
+                    return Ok(());
+                }
+                Action::Start
+                | Action::Restore
+                | Action::Resume
+                | Action::Reboot
+                | Action::Reset => "running",
+                Action::Shutdown | Action::Destroy => "shut off",
+                Action::Suspend => "paused",
+                Action::ManagedSave => "saved",
+            };
+            d.state = to.to_string();
+            if to == "shut off"
+                && let Some(xml) = d.inactive_xml.take()
+            {
+                d.xml = xml;
+            }
+            Ok(())
+        })
+    }
+
+    fn snapshots(&self, name: &str) -> Result<Vec<Snapshot>, String> {
+        self.with_domain(name, |d| Ok(d.snapshots.clone()))
+    }
+
+    fn create_volume(&self, volume: &NewVolume) -> Result<(), String> {
+        thread::sleep(self.latency);
+        if volume.pool != "default" {
+            return Err(format!("storage pool '{}' not found", volume.pool));
+        }
+        let mut volumes = self.volumes.lock().unwrap();
+        if volumes.iter().any(|v| v.name == volume.name) {
+            return Err(format!("storage volume '{}' already exists", volume.name));
+        }
+        volumes.push(FakeVolume {
+            pool: volume.pool.clone(),
+            name: volume.name.clone(),
+            info: VolumeInfo {
+                capacity: volume.capacity,
+                allocation: if volume.backing.is_some() { 200704 } else { 0 },
+            },
+        });
+        Ok(())
+    }
+
+    fn upload_volume(&self, pool: &str, name: &str, file: &Path) -> Result<(), String> {
+        let size = fs::metadata(file)
+            .map_err(|e| format!("cannot read {}: {e}", file.display()))?
+            .len();
+        self.create_volume(&NewVolume {
+            pool: pool.to_string(),
+            name: name.to_string(),
+            capacity: size,
+            format: "raw".to_string(),
+            backing: None,
+        })
+    }
+
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String> {
+        let mut volumes = self.volumes.lock().unwrap();
+        let before = volumes.len();
+        volumes.retain(|v| v.pool != pool || v.name != name);
+        if volumes.len() == before {
+            return Err(format!("storage volume '{name}' not found"));
+        }
+        Ok(())
+    }
+
+    fn volume_at(&self, path: &str) -> Result<(String, String), String> {
+        thread::sleep(self.latency);
+        let volumes = self.volumes.lock().unwrap();
+        volumes
+            .iter()
+            .find(|v| format!("{FAKE_POOL_DIR}/{}", v.name) == path)
+            .map(|v| (v.pool.clone(), v.name.clone()))
+            .ok_or_else(|| format!("no storage vol with matching path '{path}'"))
+    }
+
+    fn volume_path(&self, pool: &str, name: &str) -> Result<String, String> {
+        self.volume_info(pool, name)?;
+        Ok(format!("{FAKE_POOL_DIR}/{name}"))
+    }
+
+    fn volume_info(&self, pool: &str, name: &str) -> Result<VolumeInfo, String> {
+        thread::sleep(self.latency);
+        let volumes = self.volumes.lock().unwrap();
+        volumes
+            .iter()
+            .find(|v| v.pool == pool && v.name == name)
+            .map(|v| v.info)
+            .ok_or_else(|| format!("storage volume '{name}' not found"))
+    }
+
+    /// Takes a few seconds, so the progress shows.
+    fn clone_volume(&self, pool: &str, source: &str, name: &str) -> Result<(), String> {
+        let info = self.volume_info(pool, source)?;
+        self.create_volume(&NewVolume {
+            pool: pool.to_string(),
+            name: name.to_string(),
+            capacity: info.capacity,
+            format: "qcow2".to_string(),
+            backing: None,
+        })?;
+        loop {
+            thread::sleep(Duration::from_millis(100));
+            let mut volumes = self.volumes.lock().unwrap();
+            let Some(copy) = volumes.iter_mut().find(|v| v.pool == pool && v.name == name) else {
+                return Err(format!("storage volume '{name}' vanished"));
+            };
+            copy.info.allocation = (copy.info.allocation + (64 << 20)).min(info.allocation);
+            if copy.info.allocation == info.allocation {
+                return Ok(());
+            }
+        }
+    }
+
+    fn domstats(&self) -> Result<Vec<DomainStats>, String> {
+        thread::sleep(self.latency);
+        let t = self.started.elapsed().as_secs_f64();
+        // Each counter grows at a rate oscillating around `avg` with a period
+        // that differs per domain; integrating keeps the counters monotonic.
+        let counter = |index: usize, avg: f64| {
+            let w = 0.05 + index as f64 * 0.03;
+            (avg * t + avg * 0.8 * (1.0 - (w * t).cos()) / w) as u64
+        };
+        let domains = self.domains.lock().unwrap();
+        Ok(domains
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.state == "running")
+            .map(|(i, d)| {
+                let config = DomainConfig::parse(&d.xml).unwrap_or_default();
+                let vcpus = config.cpu.vcpus.unwrap_or(1).max(1);
+                let memory = config.memory_kib.unwrap_or(1048576);
+                DomainStats {
+                    name: d.name.clone(),
+                    cpu_time: counter(i, 0.3e9 * vcpus as f64),
+                    vcpus,
+                    balloon_kib: Some(memory),
+                    rss_kib: Some((memory as f64 * (0.45 + 0.15 * (0.1 * t + i as f64).sin())) as u64),
+                    block_read: counter(i + 1, 2e6),
+                    block_write: counter(i + 2, 5e5),
+                    net_rx: counter(i + 3, 1.5e5),
+                    net_tx: counter(i + 4, 4e4),
+                }
+            })
+            .collect())
+    }
+
+    /// A pretend login prompt that echoes what is typed.
+    fn console_command(&self, name: &str) -> Result<Vec<String>, String> {
+        let script = r#"printf 'Connected to domain %s\r\nEscape character is ^]\r\n\r\n%s login: ' "$1" "$1"; exec cat"#;
+        Ok(["sh", "-c", script, "sh", name].map(str::to_string).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virsh_list() {
+        let output = " Id   Name       State
+--------------------------
+ 1    vm1        running
+ -    vm2        shut off
+ 3    vm3        paused
+
+";
+        let domains: Vec<_> = parse_virsh_output(output)
+            .into_iter()
+            .map(|d| (d.id, d.name, d.state))
+            .collect();
+        assert_eq!(
+            domains,
+            [
+                ("1".to_string(), "vm1".to_string(), "running".to_string()),
+                ("-".to_string(), "vm2".to_string(), "shut off".to_string()),
+                ("3".to_string(), "vm3".to_string(), "paused".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn virsh_list_empty() {
+        assert!(parse_virsh_output(" Id   Name   State\n--------------------\n\n").is_empty());
+    }
+
+    #[test]
+    fn domifaddr() {
+        let output = " Name       MAC address          Protocol     Address
+-------------------------------------------------------------------------------
+ vnet0      52:54:00:6c:2a:01    ipv4         192.168.122.10/24
+ -          -                    ipv4         10.0.0.5/8
+ -          -                    ipv6         fe80::5054:ff:fe6c:2a01/64
+ vnet1      52:54:00:6c:2a:11    ipv4         192.168.122.10/24
+ lo         -                    ipv4         127.0.0.1/8
+";
+        let addrs: Vec<_> = parse_domifaddr_output(output)
+            .into_iter()
+            .map(|a| (a.interface, a.mac, a.ip))
+            .collect();
+        assert_eq!(
+            addrs,
+            [
+                ("vnet0".to_string(), Some("52:54:00:6c:2a:01".to_string()), "192.168.122.10".to_string()),
+                ("vnet0".to_string(), Some("52:54:00:6c:2a:01".to_string()), "10.0.0.5".to_string()),
+                ("lo".to_string(), None, "127.0.0.1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_list() {
+        let output = " Name        Creation Time               State     Parent
+--------------------------------------------------------------
+ base        2024-05-01 12:30:00 +0200   shutoff
+ upgraded    2024-05-02 09:00:00 +0200   running   base
+
+";
+        let snapshots = parse_snapshot_list_output(output);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].name, "base");
+        assert_eq!(snapshots[0].creation_time, "2024-05-01 12:30:00 +0200");
+        assert_eq!(snapshots[0].state, "shutoff");
+        assert_eq!(snapshots[0].parent, None);
+        assert_eq!(snapshots[1].name, "upgraded");
+        assert_eq!(snapshots[1].state, "running");
+        assert_eq!(snapshots[1].parent.as_deref(), Some("base"));
+    }
+
+    #[test]
+    fn domstats() {
+        let output = "Domain: 'vm1'
+  state.state=1
+  cpu.time=4061531410
+  balloon.current=2097152
+  balloon.rss=617428
+  vcpu.current=2
+  block.count=2
+  block.0.rd.bytes=226341376
+  block.0.wr.bytes=1024
+  block.1.rd.bytes=1000
+  net.0.rx.bytes=9238
+  net.0.tx.bytes=512
+  net.1.rx.bytes=100
+
+Domain: 'vm2'
+  cpu.time=10
+";
+        let stats = parse_domstats_output(output);
+        assert_eq!(stats.len(), 2);
+        let vm1 = &stats[0];
+        assert_eq!(vm1.name, "vm1");
+        assert_eq!(vm1.cpu_time, 4061531410);
+        assert_eq!(vm1.vcpus, 2);
+        assert_eq!(vm1.balloon_kib, Some(2097152));
+        assert_eq!(vm1.rss_kib, Some(617428));
+        assert_eq!((vm1.block_read, vm1.block_write), (226342376, 1024));
+        assert_eq!((vm1.net_rx, vm1.net_tx), (9338, 512));
+        assert_eq!(stats[1].name, "vm2");
+        assert_eq!(stats[1].cpu_time, 10);
+        assert_eq!(stats[1].balloon_kib, None);
+    }
+
+    #[test]
+    fn fake_lifecycle() {
+        let backend = FakeBackend::new(vec![FakeDomain::new(0, "vm", "running", 1, 1048576)]);
+        let state = || backend.list_domains(true).unwrap()[0].state.clone();
+        let steps = [
+            (Action::Suspend, Ok("paused")),
+            (Action::Reboot, Err("domain 'vm' is paused")),
+            (Action::Resume, Ok("running")),
+            (Action::ManagedSave, Ok("saved")),
+            (Action::Start, Err("domain 'vm' is saved")),
+            (Action::Restore, Ok("running")),
+            (Action::Shutdown, Ok("shut off")),
+            (Action::Destroy, Err("domain 'vm' is shut off")),
+            (Action::Start, Ok("running")),
+            (Action::Destroy, Ok("shut off")),
+        ];
+        for (action, expected) in steps {
+            match expected {
+                Ok(to) => {
+                    assert_eq!(backend.lifecycle("vm", &action), Ok(()), "{}", action.label());
+                    assert_eq!(state(), to, "after {}", action.label());
+                }
+                Err(error) => {
+                    let before = state();
+                    assert_eq!(backend.lifecycle("vm", &action), Err(error.to_string()));
+                    assert_eq!(state(), before);
+                }
+            }
+        }
+        // Only running domains have an id.
+        assert_eq!(backend.list_domains(true).unwrap()[0].id, "-");
+        assert!(backend.list_domains(false).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Start-and-connect: follow a VM from start until its SSH port answers.
+
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::IfAddr;
+use crate::ssh::SshForm;
+
+/// Time between address lookups or port checks.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub enum Stage {
+    /// The start action is running.
+    Starting,
+    WaitingForAddress,
+    /// Waiting for the port of the address `form` would connect to, or of any of
+    /// its addresses while none is chosen.
+    WaitingForSsh { form: Box<SshForm> },
+}
+
+/// Result of one step, delivered by a worker thread.
+pub enum Step {
+    Started(Result<(), String>),
+    Addresses(Vec<IfAddr>),
+    /// The first probed address whose SSH port answered.
+    PortOpen(Option<String>),
+}
+
+/// The VM being waited for; only one at a time.
+pub struct BootWatch {
+    pub host: String,
+    pub vm_name: String,
+    pub stage: Stage,
+    since: Instant,
+    timeout: Duration,
+    /// A step is in flight.
+    pub pending: bool,
+    next_check: Instant,
+}
+
+impl BootWatch {
+    pub fn new(host: &str, vm_name: &str, stage: Stage, timeout: Duration) -> Self {
+        Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            stage,
+            since: Instant::now(),
+            timeout,
+            pending: false,
+            next_check: Instant::now(),
+        }
+    }
+
+    /// Whether the next lookup or port check should be started now.
+    pub fn due(&self) -> bool {
+        !self.pending && Instant::now() >= self.next_check
+    }
+
+    /// Mark a step as finished; the next one waits `delay`.
+    pub fn step_done(&mut self, delay: Duration) {
+        self.pending = false;
+        self.next_check = Instant::now() + delay;
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.since.elapsed() >= self.timeout
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// e.g. `web01: waiting for SSH on 192.168.122.10:22 (14s)`
+    pub fn status(&self) -> String {
+        let what = match &self.stage {
+            Stage::Starting => "starting".to_string(),
+            Stage::WaitingForAddress => "booting, waiting for an IP address".to_string(),
+            Stage::WaitingForSsh { form } => {
+                let (ip, port) = form.target();
+                match form.candidates().len() {
+                    1 => format!("waiting for SSH on {ip}:{port}"),
+                    n => format!("waiting for SSH on any of {n} addresses, port {port}"),
+                }
+            }
+        };
+        format!("{}: {what} ({}s)", self.vm_name, self.since.elapsed().as_secs())
+    }
+}
+
+/// The first of `ips` whose `port` accepts TCP connections; all are probed at once.
+pub fn first_open(ips: &[String], port: u16) -> Option<String> {
+    let open: Vec<bool> = thread::scope(|s| {
+        let probes: Vec<_> = ips.iter().map(|ip| s.spawn(move || port_open(ip, port))).collect();
+        probes.into_iter().map(|p| p.join().unwrap_or(false)).collect()
+    });
+    ips.iter().zip(open).find(|(_, open)| *open).map(|(ip, _)| ip.clone())
+}
+
+/// Whether `ip:port` accepts TCP connections.
+fn port_open(ip: &str, port: u16) -> bool {
+    let addrs: Vec<SocketAddr> = (ip, port).to_socket_addrs().map(Iterator::collect).unwrap_or_default();
+    addrs
+        .iter()
+        .any(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn first_answering_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Nothing listens on 127.0.0.2, which is on the loopback device too.
+        let ips = ["127.0.0.2", "127.0.0.1"].map(str::to_string);
+        assert_eq!(first_open(&ips, port).as_deref(), Some("127.0.0.1"));
+        assert_eq!(first_open(&ips[..1], port), None);
+        assert_eq!(first_open(&[], port), None);
+    }
+}
@@ -0,0 +1,496 @@
+//! Command-line arguments, and the subcommands that run without the TUI.
+
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::cloudinit;
+use crate::create::{self, CloudInit, Firmware, Image, VmSpec};
+use crate::domain::DomainConfig;
+use crate::worker::CancelToken;
+use crate::{Action, ConfigCache, ListOptions, get_domain_config, get_vm_ips, get_vm_list, host_label};
+
+const KEYBINDINGS: &str = "\
+KEYBINDINGS (defaults; see config.toml):
+    j / Down      Move selection down
+    k / Up        Move selection up
+    Enter         Open console (running VMs only); Ctrl-] detaches
+    Tab           Show the next open console
+    X             Close the shown console
+    v             Open graphical console (VNC/SPICE) in a viewer
+    s             SSH into VM (running VMs only)
+    u             Start VM, or restore a saved VM
+    U             Start VM and SSH in once it is up
+    d             Shut down VM (running VMs only)
+    r             Reboot VM (running VMs only)
+    R             Hard reset VM (running VMs only)
+    D             Force off VM (any active VM)
+    p             Suspend a running / resume a paused VM
+    S             Managed save (running or paused VMs)
+    t             Manage snapshots of the VM
+    x             Show the domain XML (live or inactive)
+    e             Edit the domain XML in $VISUAL / $EDITOR
+    P             Show how the live config differs from the next boot
+    C             Create a VM (wizard with dry run and cloud-init)
+    y             Clone a shut-off VM with copies or overlays of its disks
+    o             Cycle sort column and direction
+    /             Search and filter VMs (name, state:, net:, tag:, host:)
+    n / N         Jump to next / previous search match
+    A             Toggle between all / running VMs
+    c             Connect to another hypervisor URI
+    m             Show recent messages
+    Esc           Clear the search filter, hide the console pane, or stop waiting for U
+    q / Esc       Quit";
+
+/// Yet Another LibVirt client — a terminal UI for libvirt/KVM virtual machines.
+///
+/// Without a subcommand the TUI is started.
+#[derive(Parser)]
+#[command(name = "yalv-rust", version, after_help = KEYBINDINGS)]
+pub struct Cli {
+    /// Show all VMs (including inactive)
+    #[arg(long, global = true)]
+    pub all: bool,
+    /// Hypervisor connection URI (default: $LIBVIRT_DEFAULT_URI); repeat to show VMs from several hosts
+    #[arg(short = 'c', long = "connect", value_name = "URI", global = true)]
+    pub connect: Vec<String>,
+    /// Read connection URIs from FILE, one per line
+    #[arg(long, value_name = "FILE", global = true)]
+    pub hosts: Option<String>,
+    /// Use built-in sample VMs instead of libvirt
+    #[arg(long, global = true)]
+    pub demo: bool,
+    /// Read settings from FILE instead of $XDG_CONFIG_HOME/yalv-rust/config.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<String>,
+    /// Comma-separated table columns (default: id,name,vcpus,memory,state,cpu,rss,disk,net);
+    /// available: host,id,name,state,vcpus,memory,cpu,rss,disk,net,ip,autostart,persistent,uptime,title,os
+    #[arg(long, value_name = "LIST")]
+    pub columns: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List VMs (only running ones unless --all is given)
+    List {
+        /// Also look up IP addresses of running VMs
+        #[arg(long)]
+        ips: bool,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the IP addresses and configuration summary of a VM
+    Info {
+        vm: String,
+        /// Print JSON, including the full parsed configuration
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the IPv4 addresses of a VM, best source first (lease, arp, agent)
+    Ip {
+        vm: String,
+        /// Print a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+    /// Start a VM, or restore a saved one
+    Start { vm: String },
+    /// Ask the guest OS to power off
+    Shutdown { vm: String },
+    /// Ask the guest OS to restart
+    Reboot { vm: String },
+    /// Hard-reset the virtual hardware
+    Reset { vm: String },
+    /// Force off a VM immediately
+    Destroy { vm: String },
+    /// Pause a running VM
+    Suspend { vm: String },
+    /// Resume a paused VM
+    Resume { vm: String },
+    /// Save memory to disk and stop the VM (managed save)
+    Save { vm: String },
+    /// Create a VM, optionally with a cloud-init seed
+    Create(Box<CreateArgs>),
+}
+
+#[derive(Args)]
+pub struct CreateArgs {
+    name: String,
+    #[arg(long, default_value_t = 2)]
+    vcpus: u32,
+    /// Memory in MiB
+    #[arg(long, default_value_t = 2048)]
+    memory: u64,
+    /// Disk size in GiB
+    #[arg(long, default_value_t = 20)]
+    disk: u64,
+    /// Storage pool for the disk and the cloud-init seed
+    #[arg(long, default_value = "default")]
+    pool: String,
+    /// Base image for a copy-on-write disk, or installer ISO; a path on the host
+    #[arg(long, value_name = "PATH")]
+    image: Option<String>,
+    /// libvirt network, or bridge:NAME
+    #[arg(long, default_value = "default")]
+    network: String,
+    /// e.g. ubuntu24.04, debian12 or a libosinfo id
+    #[arg(long, value_name = "VARIANT")]
+    os_variant: Option<String>,
+    /// bios or uefi
+    #[arg(long, default_value = "bios")]
+    firmware: String,
+    /// Build a cloud-init seed authorizing the public keys in FILE
+    #[arg(long, value_name = "FILE")]
+    ssh_key: Option<String>,
+    /// cloud-init user-data template with {name}, {user} and {ssh_keys}
+    #[arg(long, value_name = "FILE", requires = "ssh_key")]
+    user_data: Option<String>,
+    /// User created by cloud-init (default: $USER)
+    #[arg(long, requires = "ssh_key")]
+    user: Option<String>,
+    /// Only define the VM
+    #[arg(long)]
+    no_start: bool,
+    /// Print the domain XML and equivalent virsh commands instead of creating anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl CreateArgs {
+    fn spec(&self) -> Result<VmSpec, String> {
+        let cloud_init = match &self.ssh_key {
+            Some(path) => Some(CloudInit {
+                user: self
+                    .user
+                    .clone()
+                    .or_else(|| std::env::var("USER").ok())
+                    .unwrap_or_default(),
+                ssh_keys: cloudinit::read_keys(path)?,
+                template: cloudinit::read_template(self.user_data.as_deref().unwrap_or_default())?,
+            }),
+            None => None,
+        };
+        Ok(VmSpec {
+            name: self.name.clone(),
+            vcpus: self.vcpus,
+            memory_mib: self.memory,
+            disk_gib: self.disk,
+            pool: self.pool.clone(),
+            image: Image::parse(self.image.as_deref().unwrap_or_default()),
+            network: self.network.clone(),
+            os_variant: self.os_variant.clone().unwrap_or_default(),
+            firmware: Firmware::parse(&self.firmware)?,
+            cloud_init,
+            start: !self.no_start,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct VmJson<'a> {
+    host: &'a str,
+    /// `None` for inactive domains.
+    id: Option<u32>,
+    name: &'a str,
+    uuid: Option<&'a str>,
+    state: &'a str,
+    vcpus: Option<u32>,
+    memory_kib: Option<u64>,
+    persistent: Option<bool>,
+    autostart: Option<bool>,
+    /// Only present with `--ips`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ips: Option<&'a [String]>,
+}
+
+#[derive(Serialize)]
+struct InfoJson<'a> {
+    host: &'a str,
+    name: &'a str,
+    state: &'a str,
+    ips: &'a [String],
+    config: &'a DomainConfig,
+}
+
+/// Run a subcommand against `backends`, printing its result to stdout.
+pub fn run(command: Command, backends: &[Arc<dyn Backend>], show_all: bool) -> Result<(), String> {
+    match command {
+        Command::List { ips, json } => print_line(&list(backends, ListOptions { show_all, ips, drift: false }, json)?),
+        Command::Info { vm, json } => print_line(&info(backends, &vm, json)?),
+        Command::Ip { vm, json } => {
+            let (backend, _) = find_vm(backends, &vm)?;
+            let ips = get_vm_ips(backend.as_ref(), &vm, &CancelToken::default());
+            if json {
+                return print_json(&ips);
+            }
+            if ips.is_empty() {
+                return Err(format!("no IPv4 address found for '{vm}'"));
+            }
+            print_line(&ips.join("\n"))
+        }
+        Command::Start { vm } => lifecycle(backends, &vm, &[Action::Start, Action::Restore]),
+        Command::Shutdown { vm } => lifecycle(backends, &vm, &[Action::Shutdown]),
+        Command::Reboot { vm } => lifecycle(backends, &vm, &[Action::Reboot]),
+        Command::Reset { vm } => lifecycle(backends, &vm, &[Action::Reset]),
+        Command::Destroy { vm } => lifecycle(backends, &vm, &[Action::Destroy]),
+        Command::Suspend { vm } => lifecycle(backends, &vm, &[Action::Suspend]),
+        Command::Resume { vm } => lifecycle(backends, &vm, &[Action::Resume]),
+        Command::Save { vm } => lifecycle(backends, &vm, &[Action::ManagedSave]),
+        Command::Create(args) => {
+            let [backend] = backends else {
+                return Err("creating a VM needs exactly one host; pick it with -c".to_string());
+            };
+            let spec = args.spec()?;
+            if args.dry_run {
+                return print_line(spec.dry_run(backend.uri())?.trim_end());
+            }
+            create::create(backend.as_ref(), &spec).map_err(|e| format!("creating '{}' failed: {e}", spec.name))?;
+            print_line(&format!(
+                "{} '{}' on {}",
+                if spec.start { "Created and started" } else { "Created" },
+                spec.name,
+                backend.uri()
+            ))
+        }
+    }
+}
+
+/// Write `text` and a newline to stdout. A closed pipe (e.g. `| head`) is not an error.
+fn print_line(text: &str) -> Result<(), String> {
+    match writeln!(io::stdout().lock(), "{text}") {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(format!("cannot write output: {e}")),
+        _ => Ok(()),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    print_line(&to_json(value)?)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+/// The VM table, or its JSON rows.
+fn list(backends: &[Arc<dyn Backend>], options: ListOptions, json: bool) -> Result<String, String> {
+    let mut vms = Vec::new();
+    for backend in backends {
+        let mut warnings = Vec::new();
+        vms.extend(get_vm_list(backend.as_ref(), options, &ConfigCache::default(), &mut warnings)?);
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+    }
+    if json {
+        let rows: Vec<VmJson> = vms
+            .iter()
+            .map(|vm| VmJson {
+                host: &vm.host,
+                id: vm.id.parse().ok(),
+                name: &vm.name,
+                uuid: vm.config.as_ref().and_then(|c| c.uuid.as_deref()),
+                state: &vm.state,
+                vcpus: vm.config.as_ref().and_then(|c| c.cpu.vcpus),
+                memory_kib: vm.config.as_ref().and_then(|c| c.memory_kib),
+                persistent: vm.persistent,
+                autostart: vm.autostart,
+                ips: options.ips.then_some(vm.ips.as_slice()),
+            })
+            .collect();
+        return to_json(&rows);
+    }
+
+    let mut header = vec!["Id", "Name", "State", "VCPUs", "Memory"];
+    if backends.len() > 1 {
+        header.insert(0, "Host");
+    }
+    if options.ips {
+        header.push("IPs");
+    }
+    let rows: Vec<Vec<String>> = vms
+        .iter()
+        .map(|vm| {
+            let mut row = vec![
+                vm.id.clone(),
+                vm.name.clone(),
+                vm.state.clone(),
+                vm.config.as_ref().map_or_else(|| "N/A".to_string(), |c| c.vcpus_label()),
+                vm.config.as_ref().map_or_else(|| "N/A".to_string(), |c| c.memory_label()),
+            ];
+            if backends.len() > 1 {
+                row.insert(0, host_label(&vm.host).to_string());
+            }
+            if options.ips {
+                row.push(vm.ips.join(","));
+            }
+            row
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).chain([header[i].len()]).max().unwrap_or(0))
+        .collect();
+    let format_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| format!("{c:<w$}")).collect();
+        line.join("  ").trim_end().to_string()
+    };
+    let mut lines = vec![format_row(header.clone())];
+    lines.extend(rows.iter().map(|row| format_row(row.iter().map(String::as_str).collect())));
+    Ok(lines.join("\n"))
+}
+
+/// Addresses and configuration of the VM `name`; fails if its XML cannot be read.
+fn info(backends: &[Arc<dyn Backend>], name: &str, json: bool) -> Result<String, String> {
+    let (backend, state) = find_vm(backends, name)?;
+    let config = get_domain_config(backend.as_ref(), name)?;
+    let ips = get_vm_ips(backend.as_ref(), name, &CancelToken::default());
+    if json {
+        return to_json(&InfoJson {
+            host: backend.uri(),
+            name,
+            state: &state,
+            ips: &ips,
+            config: &config,
+        });
+    }
+    let ip_text = if ips.is_empty() { "N/A".to_string() } else { ips.join(", ") };
+    Ok(format!("IPs: {ip_text}\n{}", config.summary()))
+}
+
+/// Find the host that has a domain called `name`, and the domain's state.
+fn find_vm(backends: &[Arc<dyn Backend>], name: &str) -> Result<(Arc<dyn Backend>, String), String> {
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    for backend in backends {
+        match backend.list_domains(true) {
+            Ok(domains) => {
+                if let Some(d) = domains.into_iter().find(|d| d.name == name) {
+                    found.push((Arc::clone(backend), d.state));
+                }
+            }
+            Err(e) => errors.push(format!("{}: {e}", backend.uri())),
+        }
+    }
+    match found.len() {
+        1 => Ok(found.remove(0)),
+        0 if errors.is_empty() => Err(format!("no VM named '{name}'")),
+        0 => Err(format!("no VM named '{name}' ({})", errors.join("; "))),
+        _ => {
+            let hosts: Vec<&str> = found.iter().map(|(b, _)| b.uri()).collect();
+            Err(format!("'{name}' exists on several hosts ({}); pick one with -c", hosts.join(", ")))
+        }
+    }
+}
+
+/// Apply the first of `actions` that fits the VM's state.
+fn lifecycle(backends: &[Arc<dyn Backend>], name: &str, actions: &[Action]) -> Result<(), String> {
+    let (backend, state) = find_vm(backends, name)?;
+    let Some(action) = actions.iter().find(|a| a.applies_to(&state)) else {
+        return Err(format!("cannot {} '{name}' while it is {state}", actions[0].label().to_lowercase()));
+    };
+    let what = action.describe(name);
+    backend
+        .lifecycle(name, action)
+        .map_err(|e| format!("{what} failed: {e}"))?;
+    print_line(&format!("{what} succeeded"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::backend::{FakeBackend, FakeDomain};
+
+    fn demo() -> Vec<Arc<dyn Backend>> {
+        vec![Arc::new(FakeBackend::demo())]
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        value.as_object().unwrap().keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn list_json() {
+        let options = ListOptions { show_all: true, ips: true, drift: false };
+        let rows: Value = serde_json::from_str(&list(&demo(), options, true).unwrap()).unwrap();
+        let rows = rows.as_array().unwrap();
+        assert_eq!(rows.len(), 4);
+        let expected = ["autostart", "host", "id", "ips", "memory_kib", "name", "persistent", "state", "uuid", "vcpus"];
+        assert_eq!(keys(&rows[0]), expected);
+        assert_eq!(rows[0]["host"], "test:///demo");
+        assert_eq!(rows[0]["id"], 1);
+        assert_eq!(rows[0]["name"], "web01");
+        assert_eq!(rows[0]["vcpus"], 4);
+        assert_eq!(rows[0]["ips"], serde_json::json!(["192.168.122.10"]));
+        assert_eq!(rows[1]["autostart"], true);
+        assert_eq!((&rows[3]["id"], &rows[3]["state"]), (&Value::Null, &Value::from("shut off")));
+        assert_eq!(rows[3]["uuid"], "00000000-0000-4000-8000-000000000004");
+
+        // `ips` is left out rather than empty without --ips.
+        let options = ListOptions { show_all: false, ips: false, drift: false };
+        let rows: Value = serde_json::from_str(&list(&demo(), options, true).unwrap()).unwrap();
+        assert_eq!(rows.as_array().unwrap().len(), 3);
+        assert_eq!(keys(&rows[0]), expected.into_iter().filter(|&k| k != "ips").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn info_json() {
+        let info: Value = serde_json::from_str(&info(&demo(), "db01", true).unwrap()).unwrap();
+        assert_eq!(keys(&info), ["config", "host", "ips", "name", "state"]);
+        assert_eq!(info["state"], "running");
+        assert_eq!(info["config"]["name"], "db01");
+        assert_eq!(info["ips"][0], "192.168.122.11");
+    }
+
+    #[test]
+    fn info_fails_without_config() {
+        let text = info(&demo(), "web01", false).unwrap();
+        assert!(text.starts_with("IPs: 192.168.122.10\n"), "{text}");
+        let mut broken = FakeDomain::new(0, "broken", "running", 1, 1048576);
+        broken.xml = "<domain type=kvm><name>broken</name></domain>".to_string();
+        let backends: Vec<Arc<dyn Backend>> = vec![Arc::new(FakeBackend::new(vec![broken]))];
+        let error = info(&backends, "broken", false).err().unwrap_or_default();
+        assert!(error.starts_with("unable to parse dumpxml for 'broken'"), "{error}");
+        assert_eq!(info(&demo(), "nope", false).err().as_deref(), Some("no VM named 'nope'"));
+    }
+
+    #[test]
+    fn vm_is_found_on_one_host() {
+        let lab = FakeBackend::new(vec![
+            FakeDomain::new(0, "web01", "running", 1, 1048576),
+            FakeDomain::new(1, "lab-only", "shut off", 1, 1048576),
+        ])
+        .with_uri("test:///lab");
+        let backends: Vec<Arc<dyn Backend>> = vec![Arc::new(FakeBackend::demo()), Arc::new(lab)];
+        let found = |name: &str| find_vm(&backends, name).map(|(b, state)| (b.uri().to_string(), state));
+        assert_eq!(found("lab-only"), Ok(("test:///lab".to_string(), "shut off".to_string())));
+        assert_eq!(found("build"), Ok(("test:///demo".to_string(), "paused".to_string())));
+        assert_eq!(found("nope"), Err("no VM named 'nope'".to_string()));
+        assert_eq!(
+            found("web01"),
+            Err("'web01' exists on several hosts (test:///demo, test:///lab); pick one with -c".to_string())
+        );
+    }
+
+    #[test]
+    fn lifecycle_checks_the_state() {
+        let backend = Arc::new(FakeBackend::demo());
+        let backends: Vec<Arc<dyn Backend>> = vec![backend.clone()];
+        assert_eq!(
+            lifecycle(&backends, "template", &[Action::Shutdown]),
+            Err("cannot shut down 'template' while it is shut off".to_string())
+        );
+        assert_eq!(
+            lifecycle(&backends, "build", &[Action::Suspend]),
+            Err("cannot suspend 'build' while it is paused".to_string())
+        );
+        assert_eq!(lifecycle(&backends, "template", &[Action::Start, Action::Restore]), Ok(()));
+        assert_eq!(backend.list_domains(true).unwrap()[3].state, "running");
+    }
+}
@@ -0,0 +1,538 @@
+//! Cloning a shut-off VM: a volume of its own for each of its disks, and its
+//! XML under a new name, UUID and MAC addresses.
+
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::backend::{Backend, NewVolume};
+use crate::create;
+use crate::domain::{self, Element};
+
+/// Time between looks at the size of a volume being copied.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DiskMode {
+    /// Independent copies of the disks.
+    Copy,
+    /// qcow2 overlays on the source VM's disks, which must not change afterwards.
+    Overlay,
+}
+
+impl DiskMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            DiskMode::Copy => "full copies",
+            DiskMode::Overlay => "copy-on-write overlays on the originals (linked clone)",
+        }
+    }
+
+    pub fn toggle(self) -> Self {
+        match self {
+            DiskMode::Copy => DiskMode::Overlay,
+            DiskMode::Overlay => DiskMode::Copy,
+        }
+    }
+}
+
+/// What a running clone is doing; written by the worker, shown by the UI.
+#[derive(Default)]
+pub struct Progress(Mutex<String>);
+
+impl Progress {
+    fn set(&self, text: String) {
+        *self.0.lock().unwrap() = text;
+    }
+
+    pub fn get(&self) -> String {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The clone in progress; only one at a time.
+pub struct CloneJob {
+    pub vm_name: String,
+    pub new_name: String,
+    pub progress: Arc<Progress>,
+    since: Instant,
+}
+
+impl CloneJob {
+    pub fn new(vm_name: &str, new_name: &str) -> Self {
+        Self {
+            vm_name: vm_name.to_string(),
+            new_name: new_name.to_string(),
+            progress: Arc::default(),
+            since: Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// e.g. `Cloning 'web01' as 'web02': copying vda: 1.5 of 3.0 GiB (50%) (12s)`
+    pub fn status(&self) -> String {
+        format!(
+            "Cloning '{}' as '{}': {} ({}s)",
+            self.vm_name,
+            self.new_name,
+            self.progress.get(),
+            self.since.elapsed().as_secs()
+        )
+    }
+}
+
+/// A disk of the source VM and the volume the clone gets in its place.
+struct DiskCopy {
+    /// Position of the `<disk>` among the children of `<devices>`.
+    index: usize,
+    /// e.g. `vda`
+    target: String,
+    pool: String,
+    source: String,
+    /// The new volume, in the same pool.
+    name: String,
+    format: String,
+}
+
+fn gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+}
+
+/// The clone's name for `volume`, e.g. `web02.qcow2` for `web01.qcow2` when
+/// cloning `web01` as `web02`; overlays are always `.qcow2`. The VM name is
+/// only replaced as the stem or a prefix up to `-`, `_` or `.`; other
+/// volumes get the new name in front.
+fn volume_name(volume: &str, vm_name: &str, new_name: &str, mode: DiskMode) -> String {
+    let name = match volume.strip_prefix(vm_name) {
+        Some(rest) if rest.is_empty() || rest.starts_with(['-', '_', '.']) => format!("{new_name}{rest}"),
+        _ => format!("{new_name}-{volume}"),
+    };
+    match (mode, name.rsplit_once('.')) {
+        (DiskMode::Copy, _) => name,
+        (DiskMode::Overlay, Some((_, "qcow2"))) => name,
+        (DiskMode::Overlay, Some((stem, _))) => format!("{stem}.qcow2"),
+        (DiskMode::Overlay, None) => format!("{name}.qcow2"),
+    }
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .map_err(|e| format!("cannot read /dev/urandom: {e}"))?;
+    Ok(bytes)
+}
+
+/// A random (version 4) UUID.
+fn new_uuid() -> Result<String, String> {
+    let mut b: [u8; 16] = random_bytes()?;
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|byte| format!("{byte:02x}")).collect();
+    Ok(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// A random MAC address in QEMU's `52:54:00` range.
+fn new_mac() -> Result<String, String> {
+    let [a, b, c]: [u8; 3] = random_bytes()?;
+    Ok(format!("52:54:00:{a:02x}:{b:02x}:{c:02x}"))
+}
+
+/// The disks of the domain `root` that the clone needs its own volume for.
+/// CD-ROMs, read-only and shareable disks are shared with the clone.
+fn disk_copies(
+    backend: &dyn Backend,
+    root: &Element,
+    vm_name: &str,
+    new_name: &str,
+    mode: DiskMode,
+) -> Result<Vec<DiskCopy>, String> {
+    let Some(devices) = root.child("devices") else {
+        return Ok(Vec::new());
+    };
+    let mut copies: Vec<DiskCopy> = Vec::new();
+    for (index, disk) in devices.children.iter().enumerate() {
+        if disk.name != "disk"
+            || disk.attr("device").is_some_and(|d| d != "disk")
+            || disk.child("readonly").is_some()
+            || disk.child("shareable").is_some()
+        {
+            continue;
+        }
+        let target = disk.child("target").and_then(|t| t.attr("dev")).unwrap_or("?").to_string();
+        let Some(source) = disk.child("source") else {
+            continue;
+        };
+        let (pool, volume) = match disk.attr("type").unwrap_or("file") {
+            "file" | "block" => {
+                let Some(path) = source.attr("file").or(source.attr("dev")) else {
+                    continue;
+                };
+                backend
+                    .volume_at(path)
+                    .map_err(|e| format!("disk {target}: {path} is not a volume of a storage pool: {e}"))?
+            }
+            "volume" => match (source.attr("pool"), source.attr("volume")) {
+                (Some(pool), Some(volume)) => (pool.to_string(), volume.to_string()),
+                _ => return Err(format!("disk {target} has no pool or volume")),
+            },
+            kind => return Err(format!("disk {target} is a {kind} disk; only volumes of storage pools can be cloned")),
+        };
+        let name = volume_name(&volume, vm_name, new_name, mode);
+        if copies.iter().any(|c| c.pool == pool && c.name == name) {
+            return Err(format!("two disks would be copied to volume {name}"));
+        }
+        copies.push(DiskCopy {
+            index,
+            target,
+            pool,
+            source: volume,
+            name,
+            format: disk.child("driver").and_then(|d| d.attr("type")).unwrap_or("raw").to_string(),
+        });
+    }
+    Ok(copies)
+}
+
+/// Copy a volume, reporting how much of it is written so far.
+fn copy_volume(backend: &dyn Backend, copy: &DiskCopy, progress: &Progress) -> Result<(), String> {
+    let total = backend.volume_info(&copy.pool, &copy.source)?.allocation;
+    progress.set(format!("copying {}", copy.target));
+    thread::scope(|scope| {
+        let job = scope.spawn(|| backend.clone_volume(&copy.pool, &copy.source, &copy.name));
+        while !job.is_finished() {
+            thread::sleep(POLL_INTERVAL);
+            // Fails until the new volume exists.
+            if let Ok(info) = backend.volume_info(&copy.pool, &copy.name) {
+                let done = info.allocation.min(total);
+                progress.set(format!(
+                    "copying {}: {} of {} ({}%)",
+                    copy.target,
+                    gib(done),
+                    gib(total),
+                    done * 100 / total.max(1)
+                ));
+            }
+        }
+        job.join().unwrap_or_else(|_| Err("the copy thread panicked".to_string()))
+    })
+}
+
+fn create_overlay(backend: &dyn Backend, copy: &DiskCopy) -> Result<(), String> {
+    let capacity = backend.volume_info(&copy.pool, &copy.source)?.capacity;
+    let backing = backend.volume_path(&copy.pool, &copy.source)?;
+    backend.create_volume(&NewVolume {
+        pool: copy.pool.clone(),
+        name: copy.name.clone(),
+        capacity,
+        format: "qcow2".to_string(),
+        backing: Some((backing, copy.format.clone())),
+    })
+}
+
+/// Give the domain `root` its new name, UUID, MAC addresses and disks, at `paths`.
+fn rewrite(root: &mut Element, new_name: &str, copies: &[DiskCopy], paths: &[String], mode: DiskMode) -> Result<(), String> {
+    for child in &mut root.children {
+        match child.name.as_str() {
+            "name" => child.text = new_name.to_string(),
+            "uuid" => child.text = new_uuid()?,
+            // libvirt creates a fresh variable store from the firmware's template.
+            "os" => child.children.retain(|c| c.name != "nvram"),
+            "devices" => {
+                for interface in child.children.iter_mut().filter(|c| c.name == "interface") {
+                    if let Some(mac) = interface.child_mut("mac") {
+                        mac.set_attr("address", &new_mac()?);
+                    }
+                }
+                for (copy, path) in copies.iter().zip(paths) {
+                    let disk = &mut child.children[copy.index];
+                    let is_volume = disk.attr("type") == Some("volume");
+                    // libvirt probes the backing chain of the new volume itself.
+                    disk.children.retain(|c| c.name != "backingStore");
+                    if let Some(source) = disk.child_mut("source") {
+                        match (is_volume, source.attr("dev").is_some()) {
+                            (true, _) => source.set_attr("volume", &copy.name),
+                            (false, true) => source.set_attr("dev", path),
+                            (false, false) => source.set_attr("file", path),
+                        }
+                    }
+                    if mode == DiskMode::Overlay {
+                        match disk.child_mut("driver") {
+                            Some(driver) => driver.set_attr("type", "qcow2"),
+                            None => disk.children.insert(
+                                0,
+                                Element {
+                                    name: "driver".to_string(),
+                                    attrs: vec![
+                                        ("name".to_string(), "qemu".to_string()),
+                                        ("type".to_string(), "qcow2".to_string()),
+                                    ],
+                                    ..Element::default()
+                                },
+                            ),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Make the clone's volumes and return their paths; `made` collects those that exist.
+fn make_volumes<'a>(
+    backend: &dyn Backend,
+    copies: &'a [DiskCopy],
+    mode: DiskMode,
+    progress: &Progress,
+    made: &mut Vec<&'a DiskCopy>,
+) -> Result<Vec<String>, String> {
+    let mut paths = Vec::new();
+    for copy in copies {
+        info!("Making volume {} of pool {} from {}", copy.name, copy.pool, copy.source);
+        let result = match mode {
+            DiskMode::Copy => copy_volume(backend, copy, progress),
+            DiskMode::Overlay => {
+                progress.set(format!("creating an overlay for {}", copy.target));
+                create_overlay(backend, copy)
+            }
+        };
+        result.map_err(|e| format!("disk {}: {e}", copy.target))?;
+        made.push(copy);
+        paths.push(backend.volume_path(&copy.pool, &copy.name)?);
+    }
+    Ok(paths)
+}
+
+/// Clone the shut-off VM `vm_name` as `new_name`; the volumes made so far are
+/// removed again if a step fails.
+pub fn clone_vm(
+    backend: &dyn Backend,
+    vm_name: &str,
+    new_name: &str,
+    mode: DiskMode,
+    progress: &Progress,
+) -> Result<(), String> {
+    create::check_name(new_name)?;
+    // Rather than after copying the disks.
+    if backend.dumpxml(new_name, true).is_ok() {
+        return Err(format!("a VM named '{new_name}' already exists"));
+    }
+    progress.set("reading the XML".to_string());
+    let xml = backend.dumpxml(vm_name, true)?;
+    let mut root = domain::parse_tree(&xml).map_err(|e| format!("unable to parse the XML: {e}"))?;
+    let copies = disk_copies(backend, &root, vm_name, new_name, mode)?;
+    let mut made = Vec::new();
+    let result = make_volumes(backend, &copies, mode, progress, &mut made).and_then(|paths| {
+        progress.set("defining".to_string());
+        rewrite(&mut root, new_name, &copies, &paths, mode)?;
+        backend.define(&root.to_xml())
+    });
+    if result.is_err() {
+        for copy in made.iter().rev() {
+            if let Err(e) = backend.delete_volume(&copy.pool, &copy.name) {
+                warn!("Cannot remove volume {} of pool {}: {e}", copy.name, copy.pool);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{FakeBackend, FakeDomain};
+    use crate::domain::DomainConfig;
+
+    /// A UEFI VM with a file disk on a backing chain, a block disk, a volume
+    /// disk, a CD-ROM and two NICs.
+    const WEB01: &str = "<domain type='kvm'>
+  <name>web01</name>
+  <uuid>6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e01</uuid>
+  <memory unit='KiB'>1048576</memory>
+  <vcpu>1</vcpu>
+  <os>
+    <type arch='x86_64' machine='q35'>hvm</type>
+    <loader readonly='yes' type='pflash'>/usr/share/OVMF/OVMF_CODE.fd</loader>
+    <nvram>/var/lib/libvirt/qemu/nvram/web01_VARS.fd</nvram>
+  </os>
+  <devices>
+    <disk type='file' device='disk'>
+      <driver name='qemu' type='qcow2'/>
+      <source file='/var/lib/libvirt/images/web01.qcow2'/>
+      <backingStore type='file'>
+        <format type='qcow2'/>
+        <source file='/var/lib/libvirt/images/jammy.img'/>
+      </backingStore>
+      <target dev='vda' bus='virtio'/>
+    </disk>
+    <disk type='block' device='disk'>
+      <driver name='qemu' type='raw'/>
+      <source dev='/var/lib/libvirt/images/web01-data.raw'/>
+      <target dev='vdb' bus='virtio'/>
+    </disk>
+    <disk type='volume' device='disk'>
+      <source pool='default' volume='scratch.raw'/>
+      <target dev='vdc' bus='virtio'/>
+    </disk>
+    <disk type='file' device='cdrom'>
+      <driver name='qemu' type='raw'/>
+      <source file='/var/lib/libvirt/images/web01-seed.iso'/>
+      <target dev='sda' bus='sata'/>
+      <readonly/>
+    </disk>
+    <interface type='network'>
+      <mac address='52:54:00:6c:2a:01'/>
+      <source network='default'/>
+    </interface>
+    <interface type='bridge'>
+      <mac address='52:54:00:6c:2a:11'/>
+      <source bridge='br0'/>
+    </interface>
+  </devices>
+</domain>";
+
+    fn backend() -> FakeBackend {
+        let backend = FakeBackend::new(vec![FakeDomain::from_xml(WEB01, "shut off")]);
+        backend
+            .create_volume(&NewVolume {
+                pool: "default".to_string(),
+                name: "scratch.raw".to_string(),
+                capacity: 1 << 30,
+                format: "raw".to_string(),
+                backing: None,
+            })
+            .unwrap();
+        backend
+    }
+
+    fn copy(index: usize, target: &str, source: &str, name: &str, format: &str) -> DiskCopy {
+        DiskCopy {
+            index,
+            target: target.to_string(),
+            pool: "default".to_string(),
+            source: source.to_string(),
+            name: name.to_string(),
+            format: format.to_string(),
+        }
+    }
+
+    #[test]
+    fn volume_names() {
+        let name = |volume, mode| volume_name(volume, "web01", "web02", mode);
+        assert_eq!(name("web01.qcow2", DiskMode::Copy), "web02.qcow2");
+        assert_eq!(name("web01-data.raw", DiskMode::Copy), "web02-data.raw");
+        assert_eq!(name("scratch.img", DiskMode::Copy), "web02-scratch.img");
+        assert_eq!(name("web01.qcow2", DiskMode::Overlay), "web02.qcow2");
+        assert_eq!(name("web01-data.raw", DiskMode::Overlay), "web02-data.qcow2");
+        assert_eq!(name("scratch", DiskMode::Overlay), "web02-scratch.qcow2");
+        assert_eq!(name("web01", DiskMode::Copy), "web02");
+        assert_eq!(name("web01_swap.img", DiskMode::Copy), "web02_swap.img");
+        // Only as a prefix, and only the whole name.
+        let name = |volume, vm_name, new_name| volume_name(volume, vm_name, new_name, DiskMode::Copy);
+        assert_eq!(name("vdb-db.qcow2", "db", "db02"), "db02-vdb-db.qcow2");
+        assert_eq!(name("db.qcow2", "db", "db02"), "db02.qcow2");
+        assert_eq!(name("dbx.qcow2", "db", "db02"), "db02-dbx.qcow2");
+    }
+
+    #[test]
+    fn rewrite_copies() {
+        let mut root = domain::parse_tree(WEB01).unwrap();
+        let copies = [
+            copy(0, "vda", "web01.qcow2", "web02.qcow2", "qcow2"),
+            copy(1, "vdb", "web01-data.raw", "web02-data.raw", "raw"),
+            copy(2, "vdc", "scratch.raw", "web02-scratch.raw", "raw"),
+        ];
+        let paths = copies.iter().map(|c| format!("/var/lib/libvirt/images/{}", c.name)).collect::<Vec<_>>();
+        rewrite(&mut root, "web02", &copies, &paths, DiskMode::Copy).unwrap();
+        let xml = root.to_xml();
+        assert!(!xml.contains("backingStore"), "{xml}");
+        let config = DomainConfig::from_element(&root);
+        assert_eq!(config.name, "web02");
+        assert_ne!(config.uuid.as_deref(), Some("6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e01"));
+        assert_eq!(config.uuid.as_deref().map(str::len), Some(36));
+        assert_eq!(config.os.nvram, None);
+        assert_eq!(config.os.loader.as_deref(), Some("/usr/share/OVMF/OVMF_CODE.fd"));
+        for (interface, old) in config.interfaces.iter().zip(["52:54:00:6c:2a:01", "52:54:00:6c:2a:11"]) {
+            let mac = interface.mac.as_deref().unwrap_or_default();
+            assert!(mac.starts_with("52:54:00:") && mac.len() == 17, "{mac}");
+            assert_ne!(mac, old);
+        }
+        let disks: Vec<_> = config
+            .disks
+            .iter()
+            .map(|d| (d.source.as_deref().unwrap_or_default(), d.format.as_deref()))
+            .collect();
+        assert_eq!(
+            disks,
+            [
+                ("/var/lib/libvirt/images/web02.qcow2", Some("qcow2")),
+                ("/var/lib/libvirt/images/web02-data.raw", Some("raw")),
+                ("default/web02-scratch.raw", None),
+                ("/var/lib/libvirt/images/web01-seed.iso", Some("raw")),
+            ]
+        );
+    }
+
+    #[test]
+    fn clone_as_overlays() {
+        let backend = backend();
+        clone_vm(&backend, "web01", "web02", DiskMode::Overlay, &Progress::default()).unwrap();
+        let source = DomainConfig::parse(&backend.dumpxml("web01", true).unwrap()).unwrap();
+        let xml = backend.dumpxml("web02", true).unwrap();
+        assert!(!xml.contains("backingStore"), "{xml}");
+        let config = DomainConfig::parse(&xml).unwrap();
+        assert_ne!(config.uuid, source.uuid);
+        assert_eq!(config.os.nvram, None);
+        let macs = |c: &DomainConfig| c.interfaces.iter().map(|i| i.mac.clone().unwrap_or_default()).collect::<Vec<_>>();
+        assert!(macs(&config).iter().all(|mac| mac.starts_with("52:54:00:")));
+        assert!(macs(&config).iter().all(|mac| !macs(&source).contains(mac)));
+        let disks: Vec<_> = config
+            .disks
+            .iter()
+            .map(|d| (d.source.as_deref().unwrap_or_default(), d.format.as_deref()))
+            .collect();
+        assert_eq!(
+            disks,
+            [
+                ("/var/lib/libvirt/images/web02.qcow2", Some("qcow2")),
+                ("/var/lib/libvirt/images/web02-data.qcow2", Some("qcow2")),
+                ("default/web02-scratch.qcow2", Some("qcow2")),
+                ("/var/lib/libvirt/images/web01-seed.iso", Some("raw")),
+            ]
+        );
+        for volume in ["web02.qcow2", "web02-data.qcow2", "web02-scratch.qcow2"] {
+            assert!(backend.volume_info("default", volume).is_ok(), "{volume}");
+        }
+        // The source is left as it was.
+        assert_eq!(backend.dumpxml("web01", true).unwrap(), WEB01);
+    }
+
+    #[test]
+    fn failed_define_removes_the_volumes() {
+        let backend = backend().with_define_error("XML error: invalid device");
+        let error = clone_vm(&backend, "web01", "web02", DiskMode::Overlay, &Progress::default());
+        assert_eq!(error, Err("XML error: invalid device".to_string()));
+        for volume in ["web02.qcow2", "web02-data.qcow2", "web02-scratch.qcow2"] {
+            assert!(backend.volume_info("default", volume).is_err(), "{volume}");
+        }
+        assert!(backend.volume_info("default", "scratch.raw").is_ok());
+        assert!(backend.volume_info("default", "web01.qcow2").is_ok());
+    }
+
+    #[test]
+    fn existing_name_is_refused_before_copying() {
+        let backend = backend();
+        let error = clone_vm(&backend, "web01", "web01", DiskMode::Overlay, &Progress::default());
+        assert_eq!(error, Err("a VM named 'web01' already exists".to_string()));
+        assert!(backend.volume_info("default", "web01.qcow2").is_ok());
+    }
+}
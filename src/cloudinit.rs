@@ -0,0 +1,171 @@
+//! cloud-init NoCloud seeds: `user-data` and `meta-data` on an ISO labelled `cidata`.
+
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+
+/// Used when no user-data template is given.
+pub const DEFAULT_USER_DATA: &str = "#cloud-config
+hostname: {name}
+users:
+  - name: {user}
+    sudo: ALL=(ALL) NOPASSWD:ALL
+    shell: /bin/bash
+    ssh_authorized_keys: {ssh_keys}
+packages:
+  - qemu-guest-agent
+runcmd:
+  - [systemctl, enable, --now, qemu-guest-agent]
+";
+
+/// Programs that write the seed ISO, tried in this order; `{iso}` is the output file.
+const ISO_TOOLS: &[&[&str]] = &[
+    &["cloud-localds", "{iso}", "user-data", "meta-data"],
+    &["genisoimage", "-output", "{iso}", "-volid", "cidata", "-joliet", "-rock", "user-data", "meta-data"],
+    &["xorrisofs", "-output", "{iso}", "-volid", "cidata", "-joliet", "-rock", "user-data", "meta-data"],
+    &["mkisofs", "-output", "{iso}", "-volid", "cidata", "-joliet", "-rock", "user-data", "meta-data"],
+];
+
+/// `path` with a leading `~/` replaced by `$HOME`.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The first of the usual public keys in `~/.ssh` that exists.
+pub fn default_ssh_key() -> Option<String> {
+    ["id_ed25519.pub", "id_ecdsa.pub", "id_rsa.pub"]
+        .iter()
+        .map(|name| format!("~/.ssh/{name}"))
+        .find(|path| expand_home(path).is_file())
+}
+
+/// The public keys in `path`, one per line like `authorized_keys`.
+pub fn read_keys(path: &str) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(expand_home(path)).map_err(|e| format!("cannot read {path}: {e}"))?;
+    if text.contains("PRIVATE KEY") {
+        return Err(format!("{path} is a private key; give the .pub file"));
+    }
+    let keys: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if keys.is_empty() {
+        return Err(format!("{path} holds no public key"));
+    }
+    Ok(keys)
+}
+
+/// The user-data template in `path`, or the built-in one if it is empty.
+pub fn read_template(path: &str) -> Result<String, String> {
+    match path.trim() {
+        "" => Ok(DEFAULT_USER_DATA.to_string()),
+        path => fs::read_to_string(expand_home(path)).map_err(|e| format!("cannot read {path}: {e}")),
+    }
+}
+
+/// `template` with `{name}`, `{user}` and `{ssh_keys}` filled in; the keys
+/// become a YAML list.
+pub fn user_data(template: &str, name: &str, user: &str, ssh_keys: &[String]) -> String {
+    let keys = serde_json::to_string(ssh_keys).unwrap_or_else(|_| "[]".to_string());
+    template
+        .replace("{name}", name)
+        .replace("{user}", user)
+        .replace("{ssh_keys}", &keys)
+}
+
+pub fn meta_data(name: &str) -> String {
+    format!("instance-id: iid-{name}\nlocal-hostname: {name}\n")
+}
+
+/// Whether `program` is an executable file in one of the `$PATH` directories.
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// The command that writes `iso` from `user-data` and `meta-data` in the
+/// current directory: the first of [`ISO_TOOLS`] that is installed, if any.
+pub fn iso_command(iso: &str) -> Option<Vec<String>> {
+    ISO_TOOLS
+        .iter()
+        .find(|tool| on_path(tool[0]))
+        .map(|tool| tool.iter().map(|arg| arg.replace("{iso}", iso)).collect())
+}
+
+/// A seed ISO built in a private temporary directory, removed on drop.
+pub struct Seed {
+    dir: PathBuf,
+}
+
+impl Seed {
+    pub fn build(user_data: &str, meta_data: &str) -> Result<Self, String> {
+        let command = iso_command("seed.iso").ok_or(
+            "no program to build the seed ISO; install cloud-localds (cloud-image-utils), genisoimage or xorriso",
+        )?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        let dir = std::env::temp_dir().join(format!("yalv-rust-seed-{}-{nanos}", std::process::id()));
+        // Only we may read it: the user-data can hold passwords.
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .map_err(|e| format!("cannot create {}: {e}", dir.display()))?;
+        let seed = Self { dir };
+        for (name, text) in [("user-data", user_data), ("meta-data", meta_data)] {
+            let path = seed.dir.join(name);
+            fs::write(&path, text).map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        }
+        info!("Building cloud-init seed: {}", command.join(" "));
+        let output = Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(&seed.dir)
+            .output()
+            .map_err(|e| format!("cannot run {}: {e}", command[0]))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{} failed: {}", command[0], stderr.trim()));
+        }
+        Ok(seed)
+    }
+
+    pub fn iso(&self) -> PathBuf {
+        self.dir.join("seed.iso")
+    }
+}
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!("Cannot remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_data_from_a_template() {
+        let keys = ["ssh-ed25519 AAAA a@laptop".to_string(), "ssh-rsa BBBB b@desk".to_string()];
+        let text = user_data("#cloud-config\nfqdn: {name}.lab\nsystem_info:\n  default_user:\n    name: {user}\nssh_authorized_keys: {ssh_keys}\n", "db02", "admin", &keys);
+        assert_eq!(
+            text,
+            "#cloud-config\nfqdn: db02.lab\nsystem_info:\n  default_user:\n    name: admin\n\
+             ssh_authorized_keys: [\"ssh-ed25519 AAAA a@laptop\",\"ssh-rsa BBBB b@desk\"]\n"
+        );
+    }
+
+    #[test]
+    fn meta_data_names_the_instance() {
+        assert_eq!(meta_data("db02"), "instance-id: iid-db02\nlocal-hostname: db02\n");
+    }
+}
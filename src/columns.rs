@@ -0,0 +1,408 @@
+//! The VM table's columns: what each shows and how it sorts.
+
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+use ratatui::prelude::*;
+use ratatui::widgets::Cell;
+
+use crate::config::Theme;
+use crate::stats::{Rates, format_rate};
+use crate::{Vm, domain, host_label};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Host,
+    Id,
+    Name,
+    State,
+    Vcpus,
+    Memory,
+    Cpu,
+    Rss,
+    Disk,
+    Net,
+    Ip,
+    Autostart,
+    Persistent,
+    Uptime,
+    Title,
+    Os,
+}
+
+/// Columns shown unless configured otherwise. `host` is added automatically
+/// when more than one host is connected.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Name,
+    Column::Vcpus,
+    Column::Memory,
+    Column::State,
+    Column::Cpu,
+    Column::Rss,
+    Column::Disk,
+    Column::Net,
+];
+
+pub const ALL_COLUMNS: &[Column] = &[
+    Column::Host,
+    Column::Id,
+    Column::Name,
+    Column::State,
+    Column::Vcpus,
+    Column::Memory,
+    Column::Cpu,
+    Column::Rss,
+    Column::Disk,
+    Column::Net,
+    Column::Ip,
+    Column::Autostart,
+    Column::Persistent,
+    Column::Uptime,
+    Column::Title,
+    Column::Os,
+];
+
+/// Parse a comma-separated list of column names, e.g. `name,state,ip`.
+pub fn parse_columns(list: &str) -> Result<Vec<Column>, String> {
+    let mut columns = Vec::new();
+    for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let column = ALL_COLUMNS
+            .iter()
+            .copied()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| format!("unknown column '{name}' (expected one of {})", names(ALL_COLUMNS)))?;
+        if columns.contains(&column) {
+            return Err(format!("column '{name}' is listed twice"));
+        }
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// Column names as accepted by `--columns`, e.g. `id,name,state`.
+pub fn names(columns: &[Column]) -> String {
+    columns.iter().map(|c| c.name()).collect::<Vec<_>>().join(",")
+}
+
+/// Per-VM values that are not part of [`Vm`] itself.
+#[derive(Clone, Copy, Default)]
+pub struct RowData<'a> {
+    pub rates: Option<&'a Rates>,
+    pub uptime: Option<&'a Uptime>,
+    /// Char indices of the name to highlight as search matches.
+    pub highlight: &'a [usize],
+}
+
+/// When a VM was first seen running.
+pub struct Uptime {
+    pub since: Instant,
+    /// `false` if the VM was already running when its host was first listed,
+    /// so the real uptime is longer than shown.
+    pub observed_start: bool,
+}
+
+impl Uptime {
+    fn label(&self) -> String {
+        let prefix = if self.observed_start { "" } else { ">" };
+        format!("{prefix}{}", format_duration(self.since.elapsed()))
+    }
+}
+
+/// e.g. `42s`, `7m`, `5h12m`, `3d4h`
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{mins}m")
+    } else if mins > 0 {
+        format!("{mins}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// A comparable cell value; missing values sort last.
+enum SortKey {
+    Text(String),
+    Number(f64),
+    Missing,
+}
+
+impl SortKey {
+    fn cmp(&self, other: &SortKey) -> Ordering {
+        match (self, other) {
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::Missing, SortKey::Missing) => Ordering::Equal,
+            (SortKey::Missing, _) => Ordering::Greater,
+            (_, SortKey::Missing) => Ordering::Less,
+            (SortKey::Number(_), SortKey::Text(_)) => Ordering::Less,
+            (SortKey::Text(_), SortKey::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+fn yes_no(value: Option<bool>) -> String {
+    match value {
+        Some(true) => "yes".to_string(),
+        Some(false) => "no".to_string(),
+        None => "N/A".to_string(),
+    }
+}
+
+impl Column {
+    /// Name used in `--columns`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Host => "host",
+            Column::Id => "id",
+            Column::Name => "name",
+            Column::State => "state",
+            Column::Vcpus => "vcpus",
+            Column::Memory => "memory",
+            Column::Cpu => "cpu",
+            Column::Rss => "rss",
+            Column::Disk => "disk",
+            Column::Net => "net",
+            Column::Ip => "ip",
+            Column::Autostart => "autostart",
+            Column::Persistent => "persistent",
+            Column::Uptime => "uptime",
+            Column::Title => "title",
+            Column::Os => "os",
+        }
+    }
+
+    pub fn header(self) -> &'static str {
+        match self {
+            Column::Host => "Host",
+            Column::Id => "Id",
+            Column::Name => "Name",
+            Column::State => "State",
+            Column::Vcpus => "VCPUs",
+            Column::Memory => "Memory",
+            Column::Cpu => "CPU",
+            Column::Rss => "RSS",
+            Column::Disk => "Disk r/w",
+            Column::Net => "Net rx/tx",
+            Column::Ip => "IP",
+            Column::Autostart => "Autostart",
+            Column::Persistent => "Persistent",
+            Column::Uptime => "Uptime",
+            Column::Title => "Title",
+            Column::Os => "OS",
+        }
+    }
+
+    pub fn width(self) -> Constraint {
+        match self {
+            Column::Name | Column::Title => Constraint::Min(12),
+            Column::Host | Column::Ip => Constraint::Length(16),
+            Column::Id => Constraint::Length(6),
+            Column::Cpu => Constraint::Length(6),
+            Column::Vcpus => Constraint::Length(8),
+            Column::Autostart | Column::Uptime => Constraint::Length(10),
+            Column::Persistent => Constraint::Length(11),
+            Column::Memory | Column::Rss => Constraint::Length(12),
+            Column::Os => Constraint::Length(14),
+            Column::State | Column::Disk | Column::Net => Constraint::Length(15),
+        }
+    }
+
+    /// Live values are only shown while the VM runs.
+    fn live_rates<'a>(vm: &Vm, data: &RowData<'a>) -> Option<&'a Rates> {
+        data.rates.filter(|_| vm.state == "running")
+    }
+
+    pub fn cell(self, vm: &Vm, data: &RowData, theme: &Theme) -> Cell<'static> {
+        let rates = Self::live_rates(vm, data);
+        let text = match self {
+            Column::Host => host_label(&vm.host).to_string(),
+            Column::Id => vm.id.clone(),
+            Column::Name => {
+                let matched = Style::default().fg(theme.search_match).add_modifier(Modifier::BOLD);
+                let mut spans: Vec<Span> = if data.highlight.is_empty() {
+                    vec![Span::raw(vm.name.clone())]
+                } else {
+                    vm.name
+                        .chars()
+                        .enumerate()
+                        .map(|(i, c)| {
+                            let style = if data.highlight.contains(&i) { matched } else { Style::default() };
+                            Span::styled(c.to_string(), style)
+                        })
+                        .collect()
+                };
+                // The next boot will not look like what runs now.
+                if vm.drift > 0 {
+                    spans.push(Span::styled(" Δ", Style::default().fg(theme.warning)));
+                }
+                return Cell::from(Line::from(spans));
+            }
+            Column::State => {
+                let style = match vm.state.as_str() {
+                    "running" => Style::default().fg(theme.running),
+                    "shut off" => Style::default().fg(theme.shut_off),
+                    "paused" | "saved" => Style::default().fg(theme.paused),
+                    _ => Style::default(),
+                };
+                return Cell::from(vm.state.clone()).style(style);
+            }
+            Column::Vcpus => vm.config.as_ref().map_or_else(|| "N/A".to_string(), |c| c.vcpus_label()),
+            Column::Memory => vm.config.as_ref().map_or_else(|| "N/A".to_string(), |c| c.memory_label()),
+            Column::Cpu => rates.map_or_else(String::new, |r| format!("{:.0}%", r.cpu_percent)),
+            Column::Rss => rates.map_or_else(String::new, |r| {
+                r.rss_kib.map_or_else(|| "N/A".to_string(), domain::format_mib)
+            }),
+            Column::Disk => rates.map_or_else(String::new, |r| {
+                format!("{} {}", format_rate(r.disk_read), format_rate(r.disk_write))
+            }),
+            Column::Net => rates.map_or_else(String::new, |r| {
+                format!("{} {}", format_rate(r.net_rx), format_rate(r.net_tx))
+            }),
+            Column::Ip => match vm.ips.as_slice() {
+                [] => String::new(),
+                [ip] => ip.clone(),
+                [ip, rest @ ..] => format!("{ip} +{}", rest.len()),
+            },
+            Column::Autostart => yes_no(vm.autostart),
+            Column::Persistent => yes_no(vm.persistent),
+            Column::Uptime => data
+                .uptime
+                .filter(|_| vm.state == "running")
+                .map_or_else(String::new, Uptime::label),
+            Column::Title => vm
+                .config
+                .as_ref()
+                .and_then(|c| c.title.clone())
+                .unwrap_or_default(),
+            Column::Os => vm
+                .config
+                .as_ref()
+                .and_then(|c| c.os_variant())
+                .unwrap_or_default(),
+        };
+        Cell::from(text)
+    }
+
+    fn sort_key(self, vm: &Vm, data: &RowData) -> SortKey {
+        let rates = Self::live_rates(vm, data);
+        let number = |v: Option<f64>| v.map_or(SortKey::Missing, SortKey::Number);
+        match self {
+            Column::Id => number(vm.id.parse().ok()),
+            Column::Vcpus => number(vm.config.as_ref().and_then(|c| c.cpu.vcpus).map(f64::from)),
+            Column::Memory => number(vm.config.as_ref().and_then(|c| c.memory_kib).map(|k| k as f64)),
+            Column::Cpu => number(rates.map(|r| r.cpu_percent)),
+            Column::Rss => number(rates.and_then(|r| r.rss_kib).map(|k| k as f64)),
+            Column::Disk => number(rates.map(|r| r.disk_read + r.disk_write)),
+            Column::Net => number(rates.map(|r| r.net_rx + r.net_tx)),
+            Column::Uptime => number(
+                data.uptime
+                    .filter(|_| vm.state == "running")
+                    .map(|u| u.since.elapsed().as_secs_f64()),
+            ),
+            Column::Ip => match vm.ips.first().and_then(|ip| ip.parse::<std::net::Ipv4Addr>().ok()) {
+                Some(ip) => SortKey::Number(u32::from(ip) as f64),
+                None => SortKey::Missing,
+            },
+            _ => {
+                let text = self.cell_text(vm);
+                if text.is_empty() {
+                    SortKey::Missing
+                } else {
+                    SortKey::Text(text.to_lowercase())
+                }
+            }
+        }
+    }
+
+    /// Text of the columns that sort alphabetically.
+    fn cell_text(self, vm: &Vm) -> String {
+        match self {
+            Column::Host => host_label(&vm.host).to_string(),
+            Column::Name => vm.name.clone(),
+            Column::State => vm.state.clone(),
+            Column::Autostart => yes_no(vm.autostart),
+            Column::Persistent => yes_no(vm.persistent),
+            Column::Title => vm.config.as_ref().and_then(|c| c.title.clone()).unwrap_or_default(),
+            Column::Os => vm.config.as_ref().and_then(|c| c.os_variant()).unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// Compare two VMs by this column; missing values sort last either way.
+    pub fn compare(self, a: (&Vm, &RowData), b: (&Vm, &RowData), descending: bool) -> Ordering {
+        let (a, b) = (self.sort_key(a.0, a.1), self.sort_key(b.0, b.1));
+        match (&a, &b) {
+            (SortKey::Missing, _) | (_, SortKey::Missing) => a.cmp(&b),
+            _ if descending => b.cmp(&a),
+            _ => a.cmp(&b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeDomain;
+    use crate::domain::DomainConfig;
+    use std::sync::Arc;
+
+    fn vm(id: &str, name: &str, vcpus: Option<u32>) -> Vm {
+        let config = vcpus.map(|vcpus| {
+            let xml = FakeDomain::new(0, name, "running", vcpus, 1048576 * u64::from(vcpus)).xml;
+            Arc::new(DomainConfig::parse(&xml).unwrap())
+        });
+        Vm {
+            host: "qemu:///system".to_string(),
+            id: id.to_string(),
+            name: name.to_string(),
+            state: "running".to_string(),
+            config,
+            persistent: Some(true),
+            autostart: Some(false),
+            ips: Vec::new(),
+            drift: 0,
+        }
+    }
+
+    fn sorted(column: Column, vms: &[Vm], descending: bool) -> Vec<&str> {
+        let mut vms: Vec<&Vm> = vms.iter().collect();
+        let data = RowData::default();
+        vms.sort_by(|a, b| column.compare((a, &data), (b, &data), descending));
+        vms.iter().map(|vm| vm.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parse() {
+        let parse = |list: &str| parse_columns(list).map(|columns| names(&columns));
+        assert_eq!(parse("name, state,,ip"), Ok("name,state,ip".to_string()));
+        assert_eq!(parse(&names(ALL_COLUMNS)), Ok(names(ALL_COLUMNS)));
+        assert_eq!(parse(""), Ok(String::new()));
+        let error = parse("name,mem").unwrap_err();
+        assert!(error.starts_with("unknown column 'mem' (expected one of host,id,name,"), "{error}");
+        assert_eq!(parse("name,state,name"), Err("column 'name' is listed twice".to_string()));
+    }
+
+    #[test]
+    fn numbers_sort_as_numbers() {
+        let vms = [vm("9", "small", Some(2)), vm("10", "large", Some(16)), vm("-", "off", Some(4))];
+        assert_eq!(sorted(Column::Id, &vms, false), ["small", "large", "off"]);
+        assert_eq!(sorted(Column::Vcpus, &vms, false), ["small", "off", "large"]);
+        assert_eq!(sorted(Column::Memory, &vms, true), ["large", "off", "small"]);
+        assert_eq!(sorted(Column::Name, &vms, false), ["large", "off", "small"]);
+    }
+
+    #[test]
+    fn missing_values_sort_last() {
+        let vms = [vm("1", "unknown", None), vm("2", "a", Some(2)), vm("3", "b", Some(8))];
+        assert_eq!(sorted(Column::Vcpus, &vms, false), ["a", "b", "unknown"]);
+        assert_eq!(sorted(Column::Vcpus, &vms, true), ["b", "a", "unknown"]);
+        assert_eq!(sorted(Column::Memory, &vms, true), ["b", "a", "unknown"]);
+        // No rates sampled, so all are missing and the order stays as it was.
+        assert_eq!(sorted(Column::Cpu, &vms, true), ["unknown", "a", "b"]);
+    }
+}
@@ -0,0 +1,247 @@
+//! User settings from `$XDG_CONFIG_HOME/yalv-rust/config.toml`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use ratatui::style::Color;
+use serde::Deserialize;
+
+use crate::columns::{self, Column};
+use crate::keys::{Key, KeyAction, KeyMap};
+use crate::launcher::Launcher;
+use crate::viewer;
+
+/// The file as written by the user; every setting is optional.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    refresh_interval: Option<u64>,
+    boot_timeout: Option<u64>,
+    show_all: Option<bool>,
+    connect: Vec<String>,
+    columns: Option<Vec<String>>,
+    ssh_user: Option<String>,
+    viewer: Option<String>,
+    launcher: Option<String>,
+    log_file: Option<String>,
+    keys: BTreeMap<String, OneOrMany>,
+    colors: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Colors used in the UI.
+pub struct Theme {
+    pub running: Color,
+    pub shut_off: Color,
+    /// Also used for saved VMs.
+    pub paused: Color,
+    pub search_match: Color,
+    pub info: Color,
+    pub warning: Color,
+    pub error: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            running: Color::Green,
+            shut_off: Color::Red,
+            paused: Color::Yellow,
+            search_match: Color::Yellow,
+            info: Color::Green,
+            warning: Color::Yellow,
+            error: Color::Red,
+        }
+    }
+}
+
+impl Theme {
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let slot = match name {
+            "running" => &mut self.running,
+            "shut_off" => &mut self.shut_off,
+            "paused" => &mut self.paused,
+            "search_match" => &mut self.search_match,
+            "info" => &mut self.info,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            _ => {
+                return Err(format!(
+                    "colors: unknown color '{name}' (expected one of running, shut_off, paused, search_match, info, warning, error)"
+                ));
+            }
+        };
+        *slot = Color::from_str(value).map_err(|_| {
+            format!("colors.{name}: invalid color '{value}' (use a name like 'blue', '#rrggbb' or 0-255)")
+        })?;
+        Ok(())
+    }
+}
+
+pub struct Config {
+    pub refresh_interval: Duration,
+    /// How long start-and-connect waits for SSH.
+    pub boot_timeout: Duration,
+    pub show_all: bool,
+    /// Connection URIs used when none are given on the command line.
+    pub connect: Vec<String>,
+    pub columns: Vec<Column>,
+    /// SSH user for VMs without a remembered profile.
+    pub ssh_user: String,
+    /// Graphical console command; see `viewer::launch` for the placeholders.
+    pub viewer: Vec<String>,
+    /// Where SSH sessions and consoles open; by default the tmux or screen session yalv-rust runs in.
+    pub launcher: Launcher,
+    pub log_file: String,
+    pub keys: KeyMap,
+    pub theme: Theme,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh_interval: Duration::from_secs(3),
+            boot_timeout: Duration::from_secs(180),
+            show_all: false,
+            connect: Vec::new(),
+            columns: columns::DEFAULT_COLUMNS.to_vec(),
+            ssh_user: String::new(),
+            viewer: viewer::DEFAULT_VIEWER.split_whitespace().map(str::to_string).collect(),
+            launcher: Launcher::detect(),
+            log_file: "yalv-rust.log".to_string(),
+            keys: KeyMap::default(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/yalv-rust/config.toml`, or `~/.config/yalv-rust/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("yalv-rust").join("config.toml"))
+    }
+
+    /// Read `path`, or the default file if it exists. Errors name the file and the bad setting.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read config file {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())?;
+        let mut config = Config::default();
+        if let Some(secs) = file.refresh_interval {
+            if secs == 0 {
+                return Err("refresh_interval must be at least 1 second".to_string());
+            }
+            config.refresh_interval = Duration::from_secs(secs);
+        }
+        if let Some(secs) = file.boot_timeout {
+            if secs == 0 {
+                return Err("boot_timeout must be at least 1 second".to_string());
+            }
+            config.boot_timeout = Duration::from_secs(secs);
+        }
+        config.show_all = file.show_all.unwrap_or(config.show_all);
+        config.connect = file.connect;
+        if let Some(names) = file.columns {
+            config.columns = columns::parse_columns(&names.join(","))
+                .map_err(|e| format!("columns: {e}"))?;
+            if config.columns.is_empty() {
+                return Err("columns: at least one column is needed".to_string());
+            }
+        }
+        if let Some(user) = file.ssh_user {
+            config.ssh_user = user;
+        }
+        if let Some(viewer) = file.viewer {
+            config.viewer = viewer::parse_command(&viewer).map_err(|e| format!("viewer: {e}"))?;
+        }
+        if let Some(launcher) = file.launcher {
+            config.launcher = Launcher::parse(&launcher).map_err(|e| format!("launcher: {e}"))?;
+        }
+        if let Some(log_file) = file.log_file {
+            config.log_file = log_file;
+        }
+        let mut overrides = Vec::new();
+        for (name, keys) in file.keys {
+            let action = KeyAction::parse(&name).map_err(|e| format!("keys: {e}"))?;
+            let keys = match keys {
+                OneOrMany::One(key) => vec![key],
+                OneOrMany::Many(keys) => keys,
+            };
+            let keys = keys
+                .iter()
+                .map(|k| Key::parse(k))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("keys.{name}: {e}"))?;
+            overrides.push((action, keys));
+        }
+        config.keys = KeyMap::with_overrides(overrides).map_err(|e| format!("keys: {e}"))?;
+        for (name, value) in &file.colors {
+            config.theme.set(name, value)?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.show_all);
+        assert!(config.launcher.describe() == Launcher::detect().describe());
+    }
+
+    #[test]
+    fn launcher_setting() {
+        let launcher = |text: &str| Config::parse(&format!("launcher = {text:?}")).map(|c| c.launcher);
+        assert!(matches!(launcher("tmux-window"), Ok(Launcher::TmuxWindow)));
+        assert!(matches!(launcher("screen-window"), Ok(Launcher::ScreenWindow)));
+        assert!(matches!(launcher("foot -T {title} {command}"), Ok(Launcher::Terminal(_))));
+        assert!(launcher("xterm").err().unwrap_or_default().starts_with("launcher: 'xterm' is not auto"));
+    }
+
+    #[test]
+    fn errors_name_the_setting() {
+        let cases = [
+            ("refresh_interval = 0", "refresh_interval must be at least 1 second"),
+            ("boot_timeout = 0", "boot_timeout must be at least 1 second"),
+            ("refresh = 5", "unknown field `refresh`"),
+            ("columns = []", "columns: "),
+            ("columns = [\"name\", \"cpu\", \"name\"]", "columns: column 'name' is listed twice"),
+            ("[colors]\nrunning = \"blurple\"", "colors.running: invalid color 'blurple'"),
+            ("[colors]\nbackground = \"blue\"", "colors: unknown color 'background'"),
+            ("[keys]\nexplode = \"x\"", "keys: unknown action 'explode'"),
+            ("[keys]\nstart = \"Hyper-x\"", "keys.start: unknown key 'Hyper-x'"),
+            ("[keys]\nstart = [\"s\", \"Ctrl-\"]", "keys.start: unknown key 'Ctrl-'"),
+        ];
+        for (text, expected) in cases {
+            let error = Config::parse(text).err().unwrap_or_default();
+            assert!(error.contains(expected), "{text:?} gave {error:?}");
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Serial consoles running in a PTY and drawn in a pane below the VM table.
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::{info, warn};
+use portable_pty::{ChildKiller, CommandBuilder, MasterPty, PtySize, native_pty_system};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders};
+
+/// A console command attached to a PTY, kept alive while detached.
+pub struct ConsoleSession {
+    pub host: String,
+    pub vm_name: String,
+    /// Fed by the reader thread.
+    parser: Arc<Mutex<vt100::Parser>>,
+    writer: Box<dyn Write + Send>,
+    master: Box<dyn MasterPty + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    /// How the command exited; set by the reader thread.
+    exit: Arc<Mutex<Option<String>>>,
+    exit_reported: bool,
+    size: (u16, u16),
+}
+
+impl ConsoleSession {
+    /// Run `command` (see `Backend::console_command`) in a new PTY of `rows` x `cols`.
+    pub fn spawn(host: &str, vm_name: &str, command: &[String], rows: u16, cols: u16) -> Result<Self, String> {
+        let (program, args) = command.split_first().ok_or("empty console command")?;
+        let pair = native_pty_system()
+            .openpty(pty_size(rows, cols))
+            .map_err(|e| format!("cannot open a PTY: {e}"))?;
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
+        cmd.env("TERM", "xterm-256color");
+        let mut child = pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| format!("cannot run {program}: {e}"))?;
+        // Only the child keeps the slave open, so reads fail once it exits.
+        drop(pair.slave);
+        let mut reader = pair.master.try_clone_reader().map_err(|e| e.to_string())?;
+        let writer = pair.master.take_writer().map_err(|e| e.to_string())?;
+        let killer = child.clone_killer();
+        info!("Console for '{vm_name}' on {host} started: {}", command.join(" "));
+
+        let parser = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+        let exit = Arc::new(Mutex::new(None));
+        let (thread_parser, thread_exit) = (Arc::clone(&parser), Arc::clone(&exit));
+        let name = vm_name.to_string();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => thread_parser.lock().unwrap().process(&buf[..n]),
+                }
+            }
+            let status = match child.wait() {
+                Ok(status) if status.success() => "closed".to_string(),
+                Ok(status) => format!("exited with code {}", status.exit_code()),
+                Err(e) => format!("failed: {e}"),
+            };
+            info!("Console for '{name}' {status}");
+            *thread_exit.lock().unwrap() = Some(status);
+        });
+
+        Ok(Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            parser,
+            writer,
+            master: pair.master,
+            killer,
+            exit,
+            exit_reported: false,
+            size: (rows, cols),
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.exit.lock().unwrap().is_some()
+    }
+
+    /// How the command exited, returned once after it does.
+    pub fn take_exit(&mut self) -> Option<String> {
+        if self.exit_reported {
+            return None;
+        }
+        let exit = self.exit.lock().unwrap().clone();
+        self.exit_reported = exit.is_some();
+        exit
+    }
+
+    /// Forward a key press to the console.
+    pub fn send_key(&mut self, key: KeyEvent) {
+        let application_cursor = self.parser.lock().unwrap().screen().application_cursor();
+        let bytes = key_bytes(key, application_cursor);
+        if !bytes.is_empty()
+            && let Err(e) = self.writer.write_all(&bytes).and_then(|_| self.writer.flush())
+        {
+            warn!("Cannot write to console of '{}': {e}", self.vm_name);
+        }
+    }
+
+    /// Resize the PTY (and so the guest's view of the terminal) to the pane.
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        if self.size == (rows, cols) || rows == 0 || cols == 0 {
+            return;
+        }
+        self.size = (rows, cols);
+        self.parser.lock().unwrap().set_size(rows, cols);
+        if let Err(e) = self.master.resize(pty_size(rows, cols)) {
+            warn!("Cannot resize console of '{}': {e}", self.vm_name);
+        }
+    }
+}
+
+impl Drop for ConsoleSession {
+    fn drop(&mut self) {
+        if !self.is_closed() {
+            info!("Closing console for '{}'", self.vm_name);
+            let _ = self.killer.kill();
+        }
+    }
+}
+
+fn pty_size(rows: u16, cols: u16) -> PtySize {
+    PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Ctrl-] detaches, as in `virsh console`. Terminals report it as Ctrl-5.
+pub fn is_detach_key(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL) && matches!(key.code, KeyCode::Char(']') | KeyCode::Char('5'))
+}
+
+/// The bytes an xterm sends for `key`.
+fn key_bytes(key: KeyEvent, application_cursor: bool) -> Vec<u8> {
+    let csi = |s: &str| format!("\x1b[{s}").into_bytes();
+    let cursor = |c: char| {
+        if application_cursor {
+            format!("\x1bO{c}").into_bytes()
+        } else {
+            format!("\x1b[{c}").into_bytes()
+        }
+    };
+    let mut bytes = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => match c {
+            'a'..='z' | 'A'..='Z' => vec![c.to_ascii_lowercase() as u8 & 0x1f],
+            '@' | ' ' | '2' => vec![0],
+            '[' | '3' => vec![0x1b],
+            '\\' | '4' => vec![0x1c],
+            ']' | '5' => vec![0x1d],
+            '^' | '6' => vec![0x1e],
+            '_' | '/' | '7' => vec![0x1f],
+            _ => return Vec::new(),
+        },
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => vec![b'\r'],
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::BackTab => csi("Z"),
+        KeyCode::Esc => vec![0x1b],
+        KeyCode::Up => cursor('A'),
+        KeyCode::Down => cursor('B'),
+        KeyCode::Right => cursor('C'),
+        KeyCode::Left => cursor('D'),
+        KeyCode::Home => cursor('H'),
+        KeyCode::End => cursor('F'),
+        KeyCode::Insert => csi("2~"),
+        KeyCode::Delete => csi("3~"),
+        KeyCode::PageUp => csi("5~"),
+        KeyCode::PageDown => csi("6~"),
+        KeyCode::F(n @ 1..=4) => format!("\x1bO{}", (b'P' + n - 1) as char).into_bytes(),
+        KeyCode::F(n @ 5..=12) => {
+            let code = [15, 17, 18, 19, 20, 21, 23, 24][usize::from(n - 5)];
+            csi(&format!("{code}~"))
+        }
+        _ => return Vec::new(),
+    };
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// Draw the console screen; the terminal cursor is placed in it when `focused`.
+pub fn render(f: &mut Frame, area: Rect, session: &mut ConsoleSession, title: String, focused: bool) {
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if focused {
+        block = block.border_style(Style::default().add_modifier(Modifier::BOLD));
+    }
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    session.resize(inner.height, inner.width);
+
+    let parser = session.parser.lock().unwrap();
+    let screen = parser.screen();
+    let buf = f.buffer_mut();
+    for row in 0..inner.height {
+        for col in 0..inner.width {
+            let (Some(cell), Some(target)) =
+                (screen.cell(row, col), buf.cell_mut((inner.x + col, inner.y + row)))
+            else {
+                continue;
+            };
+            if cell.is_wide_continuation() {
+                continue;
+            }
+            let contents = cell.contents();
+            target.set_symbol(if contents.is_empty() { " " } else { &contents });
+            let mut style = Style::default().fg(color(cell.fgcolor())).bg(color(cell.bgcolor()));
+            if cell.bold() {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if cell.italic() {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+            if cell.underline() {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            if cell.inverse() {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            target.set_style(style);
+        }
+    }
+    if focused && !screen.hide_cursor() {
+        let (row, col) = screen.cursor_position();
+        if row < inner.height && col < inner.width {
+            f.set_cursor_position((inner.x + col, inner.y + row));
+        }
+    }
+}
+
+fn color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(i) => Color::Indexed(i),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn cursor_keys_follow_the_cursor_mode() {
+        let none = KeyModifiers::NONE;
+        for (code, normal, application) in [
+            (KeyCode::Up, "\x1b[A", "\x1bOA"),
+            (KeyCode::Down, "\x1b[B", "\x1bOB"),
+            (KeyCode::Right, "\x1b[C", "\x1bOC"),
+            (KeyCode::Left, "\x1b[D", "\x1bOD"),
+            (KeyCode::Home, "\x1b[H", "\x1bOH"),
+            (KeyCode::End, "\x1b[F", "\x1bOF"),
+            // Not cursor keys: the same in both modes.
+            (KeyCode::PageUp, "\x1b[5~", "\x1b[5~"),
+            (KeyCode::Delete, "\x1b[3~", "\x1b[3~"),
+        ] {
+            assert_eq!(key_bytes(key(code, none), false), normal.as_bytes(), "{code:?}");
+            assert_eq!(key_bytes(key(code, none), true), application.as_bytes(), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn control_and_function_keys() {
+        let ctrl = KeyModifiers::CONTROL;
+        let none = KeyModifiers::NONE;
+        let cases: [(KeyEvent, &[u8]); 14] = [
+            (key(KeyCode::Char('c'), ctrl), b"\x03"),
+            (key(KeyCode::Char('A'), ctrl), b"\x01"),
+            (key(KeyCode::Char('z'), ctrl), b"\x1a"),
+            (key(KeyCode::Char(' '), ctrl), b"\x00"),
+            (key(KeyCode::Char('['), ctrl), b"\x1b"),
+            (key(KeyCode::Char('1'), ctrl), b""),
+            (key(KeyCode::Char('x'), KeyModifiers::ALT), b"\x1bx"),
+            (key(KeyCode::Enter, none), b"\r"),
+            (key(KeyCode::Backspace, none), b"\x7f"),
+            (key(KeyCode::F(1), none), b"\x1bOP"),
+            (key(KeyCode::F(4), none), b"\x1bOS"),
+            (key(KeyCode::F(5), none), b"\x1b[15~"),
+            (key(KeyCode::F(12), none), b"\x1b[24~"),
+            (key(KeyCode::F(13), none), b""),
+        ];
+        for (event, expected) in cases {
+            assert_eq!(key_bytes(event, false), expected, "{event:?}");
+        }
+    }
+
+    #[test]
+    fn detach_key() {
+        assert!(is_detach_key(&key(KeyCode::Char(']'), KeyModifiers::CONTROL)));
+        assert!(is_detach_key(&key(KeyCode::Char('5'), KeyModifiers::CONTROL)));
+        assert!(is_detach_key(&key(KeyCode::Char(']'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)));
+        assert!(!is_detach_key(&key(KeyCode::Char(']'), KeyModifiers::NONE)));
+        assert!(!is_detach_key(&key(KeyCode::Char('5'), KeyModifiers::ALT)));
+        assert!(!is_detach_key(&key(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+    }
+}
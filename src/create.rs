@@ -0,0 +1,586 @@
+//! Creating a VM: its domain XML, and the steps that make it exist.
+
+use log::{info, warn};
+
+use crate::Action;
+use crate::backend::{Backend, NewVolume};
+use crate::cloudinit::{self, Seed};
+use crate::domain::escape_xml;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// libosinfo vendors of the short OS variant names, e.g. `ubuntu` in `ubuntu22.04`.
+const OS_VENDORS: &[(&str, &str)] = &[
+    ("almalinux", "almalinux.org"),
+    ("alpinelinux", "alpinelinux.org"),
+    ("centos-stream", "centos.org"),
+    ("debian", "debian.org"),
+    ("fedora", "fedoraproject.org"),
+    ("freebsd", "freebsd.org"),
+    ("opensuse", "opensuse.org"),
+    ("rhel", "redhat.com"),
+    ("rocky", "rockylinux.org"),
+    ("sles", "suse.com"),
+    ("ubuntu", "ubuntu.com"),
+    ("win", "microsoft.com"),
+];
+
+/// Whether `name` is fine for a VM and the names of its volumes.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("the VM needs a name".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(format!("'{name}' is not a valid name (use letters, digits, -, _ and .)"));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Firmware {
+    Bios,
+    Uefi,
+}
+
+impl Firmware {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "bios" => Ok(Firmware::Bios),
+            "uefi" | "efi" => Ok(Firmware::Uefi),
+            _ => Err(format!("unknown firmware '{text}' (expected bios or uefi)")),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Firmware::Bios => "BIOS",
+            Firmware::Uefi => "UEFI",
+        }
+    }
+}
+
+/// What the new VM's disk starts from.
+#[derive(Clone)]
+pub enum Image {
+    /// An empty disk, e.g. for network installs.
+    Blank,
+    /// Path of a base image on the host, e.g. a cloud image, that the disk is
+    /// a copy-on-write overlay of.
+    Base(String),
+    /// Path of an installer ISO on the host, booted from a CD-ROM.
+    Iso(String),
+}
+
+impl Image {
+    /// An `.iso` is booted as an installer; any other file is a base image.
+    pub fn parse(path: &str) -> Self {
+        let path = path.trim();
+        if path.is_empty() {
+            Image::Blank
+        } else if path.to_ascii_lowercase().ends_with(".iso") {
+            Image::Iso(path.to_string())
+        } else {
+            Image::Base(path.to_string())
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CloudInit {
+    /// Account created with the SSH keys and passwordless sudo.
+    pub user: String,
+    pub ssh_keys: Vec<String>,
+    /// See [`cloudinit::user_data`].
+    pub template: String,
+}
+
+/// Everything asked by the creation wizard or `yalv-rust create`.
+#[derive(Clone)]
+pub struct VmSpec {
+    pub name: String,
+    pub vcpus: u32,
+    pub memory_mib: u64,
+    pub disk_gib: u64,
+    /// Storage pool for the disk and the cloud-init seed.
+    pub pool: String,
+    pub image: Image,
+    /// A libvirt network, or `bridge:NAME` for a host bridge.
+    pub network: String,
+    /// Short name like `ubuntu22.04` or a libosinfo id; may be empty.
+    pub os_variant: String,
+    pub firmware: Firmware,
+    pub cloud_init: Option<CloudInit>,
+    /// Start the VM once it is defined.
+    pub start: bool,
+}
+
+/// One thing done to create a VM.
+pub enum Step {
+    CreateDisk(NewVolume),
+    /// Build the cloud-init seed ISO and upload it as `volume` in `pool`.
+    UploadSeed {
+        pool: String,
+        volume: String,
+        user_data: String,
+        meta_data: String,
+    },
+    /// Define the domain from this XML.
+    Define(String),
+    Start(String),
+}
+
+impl Step {
+    /// e.g. `creating volume web02.qcow2 in pool default`
+    fn describe(&self) -> String {
+        match self {
+            Step::CreateDisk(volume) => format!("creating volume {} in pool {}", volume.name, volume.pool),
+            Step::UploadSeed { pool, volume, .. } => {
+                format!("uploading the cloud-init seed as volume {volume} in pool {pool}")
+            }
+            Step::Define(_) => "defining the domain".to_string(),
+            Step::Start(name) => format!("starting '{name}'"),
+        }
+    }
+
+    /// Shell commands doing the same with `virsh`, which includes its `-c` option.
+    fn commands(&self, virsh: &str, name: &str) -> Vec<String> {
+        let quote = |s: &str| shlex::try_quote(s).map_or_else(|_| s.to_string(), |q| q.into_owned());
+        match self {
+            Step::CreateDisk(volume) => {
+                let mut command = format!(
+                    "{virsh} vol-create-as {} {} {} --format {}",
+                    quote(&volume.pool),
+                    quote(&volume.name),
+                    volume.capacity,
+                    quote(&volume.format)
+                );
+                if let Some((path, format)) = &volume.backing {
+                    command.push_str(&format!(
+                        " --backing-vol {} --backing-vol-format {}",
+                        quote(path),
+                        quote(format)
+                    ));
+                }
+                vec![command]
+            }
+            Step::UploadSeed { pool, volume, .. } => {
+                let build = cloudinit::iso_command("seed.iso")
+                    .map(|args| args.join(" "))
+                    .unwrap_or_else(|| "cloud-localds seed.iso user-data meta-data".to_string());
+                let (pool, volume) = (quote(pool), quote(volume));
+                vec![
+                    "# in a directory holding the user-data and meta-data above:".to_string(),
+                    build,
+                    format!("{virsh} vol-create-as {pool} {volume} \"$(stat -c %s seed.iso)\" --format raw"),
+                    format!("{virsh} vol-upload --pool {pool} {volume} seed.iso"),
+                ]
+            }
+            Step::Define(_) => vec![format!("{virsh} define --validate {}", quote(&format!("{name}.xml")))],
+            Step::Start(name) => vec![format!("{virsh} start {}", quote(name))],
+        }
+    }
+}
+
+/// libosinfo id of a short OS variant name like `ubuntu22.04`; ids are kept.
+fn libosinfo_id(variant: &str) -> Result<String, String> {
+    if variant.starts_with("http://") || variant.starts_with("https://") {
+        return Ok(variant.to_string());
+    }
+    let unknown = || {
+        format!("unknown OS variant '{variant}'; give its libosinfo id instead, e.g. http://ubuntu.com/ubuntu/22.04")
+    };
+    let split = variant.find(|c: char| c.is_ascii_digit()).ok_or_else(unknown)?;
+    let (distro, version) = variant.split_at(split);
+    let (_, vendor) = OS_VENDORS.iter().find(|(d, _)| *d == distro).ok_or_else(unknown)?;
+    Ok(format!("http://{vendor}/{distro}/{version}"))
+}
+
+impl VmSpec {
+    pub fn disk_volume(&self) -> String {
+        format!("{}.qcow2", self.name)
+    }
+
+    pub fn seed_volume(&self) -> String {
+        format!("{}-seed.iso", self.name)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        check_name(&self.name)?;
+        if self.vcpus == 0 {
+            return Err("the VM needs at least one vCPU".to_string());
+        }
+        if self.memory_mib < 128 {
+            return Err("the VM needs at least 128 MiB of memory".to_string());
+        }
+        if self.disk_gib == 0 {
+            return Err("the disk needs at least 1 GiB".to_string());
+        }
+        if self.pool.is_empty() {
+            return Err("no storage pool given".to_string());
+        }
+        if self.network.is_empty() || self.network == "bridge:" {
+            return Err("no network given".to_string());
+        }
+        if let Some(cloud_init) = &self.cloud_init {
+            let user = &cloud_init.user;
+            if user.is_empty() {
+                return Err("cloud-init needs a user name".to_string());
+            }
+            // It goes into the user-data YAML unquoted.
+            if check_name(user).is_err() || user.starts_with(['-', '.']) {
+                return Err(format!("'{user}' is not a valid user name (use letters, digits, -, _ and .)"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The XML to define, for a KVM guest with virtio devices.
+    pub fn domain_xml(&self) -> Result<String, String> {
+        self.check()?;
+        let mut xml = format!("<domain type='kvm'>\n  <name>{}</name>\n", escape_xml(&self.name));
+        if !self.os_variant.is_empty() {
+            xml.push_str(&format!(
+                "  <metadata>\n    <libosinfo:libosinfo xmlns:libosinfo='http://libosinfo.org/xmlns/libvirt/domain/1.0'>\n      \
+                 <libosinfo:os id='{}'/>\n    </libosinfo:libosinfo>\n  </metadata>\n",
+                escape_xml(&libosinfo_id(&self.os_variant)?)
+            ));
+        }
+        xml.push_str(&format!(
+            "  <memory unit='MiB'>{}</memory>\n  <vcpu>{}</vcpu>\n",
+            self.memory_mib, self.vcpus
+        ));
+        xml.push_str(match self.firmware {
+            Firmware::Bios => "  <os>\n",
+            Firmware::Uefi => "  <os firmware='efi'>\n",
+        });
+        xml.push_str("    <type arch='x86_64' machine='q35'>hvm</type>\n");
+        if let Image::Iso(_) = self.image {
+            xml.push_str("    <boot dev='cdrom'/>\n");
+        }
+        xml.push_str("    <boot dev='hd'/>\n  </os>\n");
+        xml.push_str("  <features>\n    <acpi/>\n    <apic/>\n  </features>\n");
+        xml.push_str("  <cpu mode='host-passthrough'/>\n  <clock offset='utc'/>\n");
+
+        xml.push_str("  <devices>\n");
+        xml.push_str(&format!(
+            "    <disk type='volume' device='disk'>\n      <driver name='qemu' type='qcow2' discard='unmap'/>\n      \
+             <source pool='{}' volume='{}'/>\n      <target dev='vda' bus='virtio'/>\n    </disk>\n",
+            escape_xml(&self.pool),
+            escape_xml(&self.disk_volume())
+        ));
+        let mut cdroms = Vec::new();
+        if let Image::Iso(path) = &self.image {
+            cdroms.push(format!("<disk type='file' device='cdrom'>\n      <source file='{}'/>", escape_xml(path)));
+        }
+        if self.cloud_init.is_some() {
+            cdroms.push(format!(
+                "<disk type='volume' device='cdrom'>\n      <source pool='{}' volume='{}'/>",
+                escape_xml(&self.pool),
+                escape_xml(&self.seed_volume())
+            ));
+        }
+        for (cdrom, dev) in cdroms.iter().zip(["sda", "sdb"]) {
+            xml.push_str(&format!(
+                "    {cdrom}\n      <target dev='{dev}' bus='sata'/>\n      <readonly/>\n    </disk>\n"
+            ));
+        }
+        match self.network.strip_prefix("bridge:") {
+            Some(bridge) => xml.push_str(&format!(
+                "    <interface type='bridge'>\n      <source bridge='{}'/>\n",
+                escape_xml(bridge)
+            )),
+            None => xml.push_str(&format!(
+                "    <interface type='network'>\n      <source network='{}'/>\n",
+                escape_xml(&self.network)
+            )),
+        }
+        xml.push_str("      <model type='virtio'/>\n    </interface>\n");
+        xml.push_str(
+            "    <serial type='pty'/>\n    <console type='pty'/>\n    \
+             <channel type='unix'>\n      <target type='virtio' name='org.qemu.guest_agent.0'/>\n    </channel>\n    \
+             <graphics type='vnc' autoport='yes'/>\n    <video>\n      <model type='virtio'/>\n    </video>\n    \
+             <rng model='virtio'>\n      <backend model='random'>/dev/urandom</backend>\n    </rng>\n",
+        );
+        xml.push_str("  </devices>\n</domain>\n");
+        Ok(xml)
+    }
+
+    pub fn steps(&self) -> Result<Vec<Step>, String> {
+        let xml = self.domain_xml()?;
+        let mut steps = vec![Step::CreateDisk(NewVolume {
+            pool: self.pool.clone(),
+            name: self.disk_volume(),
+            capacity: self.disk_gib * GIB,
+            format: "qcow2".to_string(),
+            // libvirt needs the backing format: raw if the name says so, else qcow2.
+            backing: match &self.image {
+                Image::Base(path) if path.ends_with(".raw") => Some((path.clone(), "raw".to_string())),
+                Image::Base(path) => Some((path.clone(), "qcow2".to_string())),
+                _ => None,
+            },
+        })];
+        if let Some(cloud_init) = &self.cloud_init {
+            steps.push(Step::UploadSeed {
+                pool: self.pool.clone(),
+                volume: self.seed_volume(),
+                user_data: cloudinit::user_data(&cloud_init.template, &self.name, &cloud_init.user, &cloud_init.ssh_keys),
+                meta_data: cloudinit::meta_data(&self.name),
+            });
+        }
+        steps.push(Step::Define(xml));
+        if self.start {
+            steps.push(Step::Start(self.name.clone()));
+        }
+        Ok(steps)
+    }
+
+    /// What creating the VM on `uri` would do: the domain XML, the cloud-init
+    /// files and the equivalent `virsh` commands.
+    pub fn dry_run(&self, uri: &str) -> Result<String, String> {
+        let steps = self.steps()?;
+        let virsh = format!("virsh -c {}", shlex::try_quote(uri).map_err(|e| e.to_string())?);
+        let mut text = String::new();
+        for step in &steps {
+            if let Step::Define(xml) = step {
+                text.push_str(&format!("# {}.xml\n{xml}\n", self.name));
+            }
+        }
+        for step in &steps {
+            if let Step::UploadSeed {
+                user_data, meta_data, ..
+            } = step
+            {
+                text.push_str(&format!("# user-data\n{user_data}\n# meta-data\n{meta_data}\n"));
+            }
+        }
+        text.push_str("# Commands\n");
+        for step in &steps {
+            for command in step.commands(&virsh, &self.name) {
+                text.push_str(&command);
+                text.push('\n');
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// Create the VM on `backend`. If a step fails before the domain is defined,
+/// the volumes created so far are deleted again.
+pub fn create(backend: &dyn Backend, spec: &VmSpec) -> Result<(), String> {
+    let steps = spec.steps()?;
+    let mut volumes: Vec<(String, String)> = Vec::new();
+    for step in &steps {
+        info!("Creating '{}': {}", spec.name, step.describe());
+        let result = match step {
+            Step::CreateDisk(volume) => backend
+                .create_volume(volume)
+                .map(|()| volumes.push((volume.pool.clone(), volume.name.clone()))),
+            Step::UploadSeed {
+                pool,
+                volume,
+                user_data,
+                meta_data,
+            } => Seed::build(user_data, meta_data)
+                .and_then(|seed| backend.upload_volume(pool, volume, &seed.iso()))
+                .map(|()| volumes.push((pool.clone(), volume.clone()))),
+            // From now on the volumes belong to the domain.
+            Step::Define(xml) => backend.define(xml).map(|()| volumes.clear()),
+            Step::Start(name) => backend
+                .lifecycle(name, &Action::Start)
+                .map_err(|e| format!("it is defined, but {} failed: {e}", step.describe())),
+        };
+        if let Err(e) = result {
+            for (pool, volume) in volumes.iter().rev() {
+                if let Err(e) = backend.delete_volume(pool, volume) {
+                    warn!("Cannot remove volume {volume} of pool {pool}: {e}");
+                }
+            }
+            return match step {
+                Step::Start(_) => Err(e),
+                _ => Err(format!("{}: {e}", step.describe())),
+            };
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{FakeBackend, VirshBackend};
+    use crate::domain::DomainConfig;
+
+    fn spec() -> VmSpec {
+        VmSpec {
+            name: "web02".to_string(),
+            vcpus: 2,
+            memory_mib: 2048,
+            disk_gib: 20,
+            pool: "default".to_string(),
+            image: Image::parse("/var/lib/libvirt/images/jammy-server-cloudimg-amd64.img"),
+            network: "default".to_string(),
+            os_variant: "ubuntu22.04".to_string(),
+            firmware: Firmware::Uefi,
+            cloud_init: Some(CloudInit {
+                user: "ubuntu".to_string(),
+                ssh_keys: vec!["ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA admin@laptop".to_string()],
+                template: cloudinit::DEFAULT_USER_DATA.to_string(),
+            }),
+            start: true,
+        }
+    }
+
+    #[test]
+    fn domain_xml() {
+        let config = DomainConfig::parse(&spec().domain_xml().unwrap()).unwrap();
+        assert_eq!(config.name, "web02");
+        assert_eq!(config.cpu.vcpus, Some(2));
+        assert_eq!(config.memory_kib, Some(2048 * 1024));
+        assert_eq!(config.os.firmware.as_deref(), Some("efi"));
+        assert_eq!(config.os.boot_devices, ["hd"]);
+        assert_eq!(config.metadata.libosinfo_id.as_deref(), Some("http://ubuntu.com/ubuntu/22.04"));
+        let disks: Vec<_> = config
+            .disks
+            .iter()
+            .map(|d| (d.kind.as_str(), d.device.as_str(), d.source.as_deref(), d.target.as_deref(), d.readonly))
+            .collect();
+        assert_eq!(
+            disks,
+            [
+                ("volume", "disk", Some("default/web02.qcow2"), Some("vda"), false),
+                ("volume", "cdrom", Some("default/web02-seed.iso"), Some("sda"), true),
+            ]
+        );
+        assert_eq!(config.interfaces.len(), 1);
+        assert_eq!(config.interfaces[0].kind, "network");
+        assert_eq!(config.interfaces[0].source.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn domain_xml_installer_on_a_bridge() {
+        let spec = VmSpec {
+            image: Image::parse("/srv/iso/debian-12.iso"),
+            network: "bridge:br0".to_string(),
+            os_variant: String::new(),
+            firmware: Firmware::Bios,
+            cloud_init: None,
+            ..spec()
+        };
+        let config = DomainConfig::parse(&spec.domain_xml().unwrap()).unwrap();
+        assert_eq!(config.os.firmware, None);
+        assert_eq!(config.os.boot_devices, ["cdrom", "hd"]);
+        assert!(config.metadata.entries.is_empty());
+        assert_eq!(config.disks[1].source.as_deref(), Some("/srv/iso/debian-12.iso"));
+        assert_eq!(config.disks.len(), 2);
+        assert_eq!(config.interfaces[0].kind, "bridge");
+        assert_eq!(config.interfaces[0].source.as_deref(), Some("br0"));
+    }
+
+    #[test]
+    fn invalid_specs() {
+        let error = |spec: VmSpec| spec.domain_xml().err().unwrap_or_default();
+        assert!(error(VmSpec { name: "web 02".to_string(), ..spec() }).contains("not a valid name"));
+        assert!(error(VmSpec { vcpus: 0, ..spec() }).contains("vCPU"));
+        assert!(error(VmSpec { network: "bridge:".to_string(), ..spec() }).contains("no network"));
+        assert!(error(VmSpec { os_variant: "plan9".to_string(), ..spec() }).contains("unknown OS variant"));
+        let user = |user: &str| VmSpec {
+            cloud_init: spec().cloud_init.map(|c| CloudInit {
+                user: user.to_string(),
+                ..c
+            }),
+            ..spec()
+        };
+        assert!(error(user("")).contains("needs a user name"));
+        assert!(error(user("admin: true")).contains("not a valid user name"));
+        assert!(error(user("-root")).contains("not a valid user name"));
+    }
+
+    #[test]
+    fn nocloud_seed() {
+        let steps = spec().steps().unwrap();
+        let Some(Step::CreateDisk(disk)) = steps.first() else {
+            panic!("the disk is not created first");
+        };
+        assert_eq!(disk.capacity, 20 * GIB);
+        assert_eq!(
+            disk.backing,
+            Some(("/var/lib/libvirt/images/jammy-server-cloudimg-amd64.img".to_string(), "qcow2".to_string()))
+        );
+        let Some(Step::UploadSeed {
+            pool,
+            volume,
+            user_data,
+            meta_data,
+        }) = steps.get(1)
+        else {
+            panic!("no cloud-init seed");
+        };
+        assert_eq!((pool.as_str(), volume.as_str()), ("default", "web02-seed.iso"));
+        assert_eq!(
+            user_data,
+            "#cloud-config
+hostname: web02
+users:
+  - name: ubuntu
+    sudo: ALL=(ALL) NOPASSWD:ALL
+    shell: /bin/bash
+    ssh_authorized_keys: [\"ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA admin@laptop\"]
+packages:
+  - qemu-guest-agent
+runcmd:
+  - [systemctl, enable, --now, qemu-guest-agent]
+"
+        );
+        assert_eq!(meta_data, "instance-id: iid-web02\nlocal-hostname: web02\n");
+        assert!(matches!(&steps[2], Step::Define(_)));
+        assert!(matches!(&steps[3], Step::Start(name) if name == "web02"));
+    }
+
+    #[test]
+    fn dry_run() {
+        let text = spec().dry_run("qemu:///system").unwrap();
+        assert!(text.starts_with("# web02.xml\n<domain type='kvm'>\n  <name>web02</name>\n"));
+        assert!(text.contains("# user-data\n#cloud-config\nhostname: web02\n"));
+        assert!(text.contains("# meta-data\ninstance-id: iid-web02\n"));
+        let commands = text.split_once("# Commands\n").map(|(_, c)| c).unwrap_or_default();
+        let commands: Vec<&str> = commands.lines().filter(|c| !c.starts_with('#')).collect();
+        assert_eq!(
+            commands[0],
+            "virsh -c qemu:///system vol-create-as default web02.qcow2 21474836480 --format qcow2 \
+             --backing-vol /var/lib/libvirt/images/jammy-server-cloudimg-amd64.img --backing-vol-format qcow2"
+        );
+        assert_eq!(commands[commands.len() - 2], "virsh -c qemu:///system define --validate web02.xml");
+        assert_eq!(commands[commands.len() - 1], "virsh -c qemu:///system start web02");
+    }
+
+    #[test]
+    fn create_on_fake_backend() {
+        let backend = FakeBackend::new(Vec::new());
+        let spec = VmSpec {
+            cloud_init: None,
+            ..spec()
+        };
+        create(&backend, &spec).unwrap();
+        assert!(backend.volume_info("default", "web02.qcow2").is_ok());
+        let config = DomainConfig::parse(&backend.dumpxml("web02", true).unwrap()).unwrap();
+        assert_eq!(config.cpu.vcpus, Some(2));
+        let error = create(&backend, &spec).err().unwrap_or_default();
+        assert_eq!(error, "creating volume web02.qcow2 in pool default: storage volume 'web02.qcow2' already exists");
+    }
+
+    /// Runs where `virsh` is installed; each call opens a fresh
+    /// `test:///default`, so the domain does not outlive the define.
+    #[test]
+    fn dry_run_and_define_on_test_driver() {
+        let Ok(backend) = VirshBackend::connect(Some("test:///default")) else {
+            eprintln!("skipped: virsh is not available");
+            return;
+        };
+        let spec = spec();
+        let text = spec.dry_run(backend.uri()).unwrap();
+        assert!(text.contains("virsh -c test:///default define --validate web02.xml"));
+        backend.define(&spec.domain_xml().unwrap()).unwrap();
+        let bad = spec.domain_xml().unwrap().replace("<vcpu>2</vcpu>", "<vcpu>two</vcpu>");
+        assert!(backend.define(&bad).is_err());
+    }
+}
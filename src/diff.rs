@@ -0,0 +1,108 @@
+//! Unified diff of two versions of a domain's XML, shown in place of the whole UI.
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use similar::{ChangeTag, TextDiff};
+
+/// Lines of context around each change.
+const CONTEXT: usize = 3;
+
+enum DiffLine {
+    /// `@@ -a,b +c,d @@`
+    Hunk(String),
+    Change(ChangeTag, String),
+}
+
+pub struct DiffView {
+    title: String,
+    lines: Vec<DiffLine>,
+    added: usize,
+    removed: usize,
+    /// First line shown.
+    offset: usize,
+    /// Lines that fit in the last render, for paging.
+    height: usize,
+}
+
+impl DiffView {
+    /// The changes from `old` to `new`, under `title`, e.g. `Changes to 'web01'`.
+    pub fn new(title: &str, old: &str, new: &str) -> Self {
+        let diff = TextDiff::from_lines(old, new);
+        let mut lines = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+        for hunk in diff.unified_diff().context_radius(CONTEXT).iter_hunks() {
+            lines.push(DiffLine::Hunk(hunk.header().to_string()));
+            for change in hunk.iter_changes() {
+                match change.tag() {
+                    ChangeTag::Insert => added += 1,
+                    ChangeTag::Delete => removed += 1,
+                    ChangeTag::Equal => {}
+                }
+                let text = change.value().trim_end_matches(['\r', '\n']).to_string();
+                lines.push(DiffLine::Change(change.tag(), text));
+            }
+        }
+        Self {
+            title: title.to_string(),
+            lines,
+            added,
+            removed,
+            offset: 0,
+            height: 0,
+        }
+    }
+
+    /// e.g. `+3 -1`
+    pub fn summary(&self) -> String {
+        format!("+{} -{}", self.added, self.removed)
+    }
+
+    pub fn scroll(&mut self, delta: isize) {
+        let max = self.lines.len().saturating_sub(self.height.max(1));
+        self.offset = self.offset.saturating_add_signed(delta).min(max);
+    }
+
+    pub fn page(&mut self, down: bool) {
+        let delta = self.height.max(1) as isize;
+        self.scroll(if down { delta } else { -delta });
+    }
+
+    pub fn home(&mut self) {
+        self.offset = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.scroll(isize::MAX);
+    }
+}
+
+pub fn render(f: &mut Frame, area: Rect, view: &mut DiffView) {
+    let title = format!(
+        " {} ({}) — j/k: scroll, PgDn/PgUp: page, Esc: close ",
+        view.title,
+        view.summary()
+    );
+    let block = Block::default().borders(Borders::ALL).title(title);
+    view.height = block.inner(area).height as usize;
+    if view.lines.is_empty() {
+        f.render_widget(Paragraph::new("No differences.").block(block), area);
+        return;
+    }
+    let lines: Vec<Line> = view
+        .lines
+        .iter()
+        .skip(view.offset)
+        .take(view.height)
+        .map(|line| match line {
+            DiffLine::Hunk(header) => Line::styled(header.as_str(), Style::default().fg(Color::Cyan)),
+            DiffLine::Change(ChangeTag::Insert, text) => {
+                Line::styled(format!("+{text}"), Style::default().fg(Color::Green))
+            }
+            DiffLine::Change(ChangeTag::Delete, text) => {
+                Line::styled(format!("-{text}"), Style::default().fg(Color::Red))
+            }
+            DiffLine::Change(ChangeTag::Equal, text) => Line::raw(format!(" {text}")),
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
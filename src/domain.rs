@@ -0,0 +1,965 @@
+//! Typed view of a domain's `dumpxml`, produced by a single parser.
+
+use serde::Serialize;
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// A generic XML element, used as the intermediate form for [`DomainConfig`].
+#[derive(Clone, Default)]
+pub struct Element {
+    /// Qualified name, e.g. `disk` or `libosinfo:os`.
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.children.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Set attribute `name`, adding it if it is missing.
+    pub fn set_attr(&mut self, name: &str, value: &str) {
+        match self.attrs.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.attrs.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// Trimmed text content, or `None` if it is empty.
+    pub fn text(&self) -> Option<&str> {
+        let text = self.text.trim();
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn child_text(&self, name: &str) -> Option<String> {
+        self.child(name).and_then(Element::text).map(str::to_string)
+    }
+
+    fn child_attr(&self, name: &str, attr: &str) -> Option<String> {
+        self.child(name)
+            .and_then(|c| c.attr(attr))
+            .map(str::to_string)
+    }
+
+    /// The element as XML, indented like `virsh dumpxml`; the counterpart of [`parse_tree`].
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        self.write(&mut xml, 0);
+        xml
+    }
+
+    fn write(&self, xml: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        xml.push_str(&format!("{indent}<{}", self.name));
+        for (name, value) in &self.attrs {
+            xml.push_str(&format!(" {name}='{}'", escape_xml(value)));
+        }
+        match (self.text(), self.children.is_empty()) {
+            (None, true) => xml.push_str("/>\n"),
+            (Some(text), true) => xml.push_str(&format!(">{}</{}>\n", escape_xml(text), self.name)),
+            (text, false) => {
+                xml.push_str(">\n");
+                if let Some(text) = text {
+                    xml.push_str(&format!("{indent}  {}\n", escape_xml(text)));
+                }
+                for child in &self.children {
+                    child.write(xml, depth + 1);
+                }
+                xml.push_str(&format!("{indent}</{}>\n", self.name));
+            }
+        }
+    }
+}
+
+fn qualified(prefix: &str, local: &str) -> String {
+    if prefix.is_empty() {
+        local.to_string()
+    } else {
+        format!("{prefix}:{local}")
+    }
+}
+
+/// Escape `text` for XML, as element text or an attribute value in either quotes.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parse `xml` into an element tree and return its root element.
+pub fn parse_tree(xml: &str) -> Result<Element, xmlparser::Error> {
+    // The bottom of the stack is a synthetic document node.
+    let mut stack: Vec<Element> = vec![Element::default()];
+    for token in Tokenizer::from(xml) {
+        match token? {
+            Token::ElementStart { prefix, local, .. } => {
+                stack.push(Element {
+                    name: qualified(prefix.as_str(), local.as_str()),
+                    ..Element::default()
+                });
+            }
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                ..
+            } => {
+                if let Some(elem) = stack.last_mut() {
+                    elem.attrs.push((
+                        qualified(prefix.as_str(), local.as_str()),
+                        unescape(value.as_str()),
+                    ));
+                }
+            }
+            Token::Text { text } => {
+                if let Some(elem) = stack.last_mut() {
+                    elem.text.push_str(&unescape(text.as_str()));
+                }
+            }
+            Token::Cdata { text, .. } => {
+                if let Some(elem) = stack.last_mut() {
+                    elem.text.push_str(text.as_str());
+                }
+            }
+            Token::ElementEnd { end, .. } => match end {
+                ElementEnd::Open => {}
+                ElementEnd::Empty | ElementEnd::Close(_, _) => {
+                    if stack.len() > 1
+                        && let Some(elem) = stack.pop()
+                        && let Some(parent) = stack.last_mut()
+                    {
+                        parent.children.push(elem);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(stack
+        .into_iter()
+        .next()
+        .and_then(|doc| doc.children.into_iter().next())
+        .unwrap_or_default())
+}
+
+/// Convert a libvirt memory value with `unit` (default KiB) to KiB.
+pub fn memory_to_kib(value: &str, unit: Option<&str>) -> Option<u64> {
+    let amount = value.trim().parse::<f64>().ok()?;
+    let unit = unit.unwrap_or("KiB").to_ascii_lowercase();
+    let kib = match unit.as_str() {
+        "b" | "byte" | "bytes" => amount / 1024.0,
+        "kb" => amount * 1000.0 / 1024.0,
+        "k" | "kib" => amount,
+        "mb" => amount * 1_000_000.0 / 1024.0,
+        "m" | "mib" => amount * 1024.0,
+        "gb" => amount * 1_000_000_000.0 / 1024.0,
+        "g" | "gib" => amount * 1024.0 * 1024.0,
+        "tb" => amount * 1_000_000_000_000.0 / 1024.0,
+        "t" | "tib" => amount * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some(kib.round() as u64)
+}
+
+/// Format a KiB amount as MiB, e.g. `2048 MiB` or `1.5 MiB`.
+pub fn format_mib(kib: u64) -> String {
+    let mib = kib as f64 / 1024.0;
+    if mib.fract().abs() < 0.01 {
+        format!("{mib:.0} MiB")
+    } else {
+        format!("{mib:.1} MiB")
+    }
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Os {
+    /// `hvm`, `linux`, `exe`, ...
+    pub os_type: Option<String>,
+    pub arch: Option<String>,
+    pub machine: Option<String>,
+    /// `efi` or `bios`, from `<os firmware=...>` or inferred from a pflash loader.
+    pub firmware: Option<String>,
+    pub secure_boot: bool,
+    /// Firmware image, e.g. `/usr/share/OVMF/OVMF_CODE_4M.fd`.
+    pub loader: Option<String>,
+    /// The VM's own UEFI variable store.
+    pub nvram: Option<String>,
+    pub boot_devices: Vec<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Cpu {
+    pub vcpus: Option<u32>,
+    /// Online vCPUs when fewer than the maximum (`<vcpu current=...>`).
+    pub current_vcpus: Option<u32>,
+    pub mode: Option<String>,
+    pub model: Option<String>,
+    pub sockets: Option<u32>,
+    pub cores: Option<u32>,
+    pub threads: Option<u32>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Disk {
+    /// `file`, `block`, `network` or `volume`.
+    pub kind: String,
+    /// `disk`, `cdrom`, `floppy` or `lun`.
+    pub device: String,
+    /// Image format from `<driver type=...>`, e.g. `qcow2`.
+    pub format: Option<String>,
+    /// Path, device, `protocol://host/name` or `pool/volume`, depending on `kind`.
+    pub source: Option<String>,
+    pub target: Option<String>,
+    pub bus: Option<String>,
+    pub readonly: bool,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Interface {
+    /// `network`, `bridge`, `direct` (macvtap), `user`, ...
+    pub kind: String,
+    /// Network name, bridge name or host device, depending on `kind`.
+    pub source: Option<String>,
+    /// macvtap mode for `direct` interfaces, e.g. `bridge` or `vepa`.
+    pub mode: Option<String>,
+    pub mac: Option<String>,
+    pub model: Option<String>,
+    pub target: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Graphics {
+    /// `vnc`, `spice`, ...
+    pub kind: String,
+    /// `None` until assigned, or when autoport has not allocated one yet (-1).
+    pub port: Option<u16>,
+    pub tls_port: Option<u16>,
+    pub autoport: bool,
+    pub listen: Option<String>,
+    pub has_password: bool,
+}
+
+impl Graphics {
+    /// e.g. `spice://lab1:5901` for `remote-viewer`; `None` if no port is assigned
+    /// or the protocol has no URI scheme. `host` replaces a wildcard listen address.
+    pub fn display_uri(&self, host: &str) -> Option<String> {
+        if self.kind != "vnc" && self.kind != "spice" {
+            return None;
+        }
+        if let Some(socket) = self.listen.as_deref().filter(|l| l.starts_with('/')) {
+            return Some(format!("{}+unix://{socket}", self.kind));
+        }
+        let port = self.port?;
+        let host = match self.listen.as_deref() {
+            None | Some("0.0.0.0" | "::" | "") => host,
+            Some(listen) => listen,
+        };
+        let host = if host.contains(':') { format!("[{host}]") } else { host.to_string() };
+        let mut uri = format!("{}://{host}:{port}", self.kind);
+        if let Some(tls_port) = self.tls_port.filter(|_| self.kind == "spice") {
+            uri.push_str(&format!("?tls-port={tls_port}"));
+        }
+        Some(uri)
+    }
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Serial {
+    /// Host side: `pty`, `file`, `tcp`, ...
+    pub kind: String,
+    pub target_port: Option<String>,
+    /// Host pty or file path, when known.
+    pub path: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Channel {
+    pub kind: String,
+    /// e.g. `org.qemu.guest_agent.0`.
+    pub target_name: Option<String>,
+    /// `connected` / `disconnected`, when reported by a running domain.
+    pub state: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct HostDev {
+    /// `pci`, `usb`, `scsi`, ...
+    pub kind: String,
+    /// PCI address or USB vendor:product.
+    pub source: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct Metadata {
+    /// Qualified names of the elements under `<metadata>`.
+    pub entries: Vec<String>,
+    /// OS id from libosinfo, e.g. `http://ubuntu.com/ubuntu/22.04`.
+    pub libosinfo_id: Option<String>,
+    /// Words from the `<tags>` element in [`TAGS_NAMESPACE`].
+    pub tags: Vec<String>,
+}
+
+/// Namespace of yalv-rust's own metadata, e.g.
+/// `<yalv:tags xmlns:yalv="urn:yalv-rust:tags">ci build</yalv:tags>`.
+pub const TAGS_NAMESPACE: &str = "urn:yalv-rust:tags";
+
+/// Everything yalv-rust reads from a domain's XML.
+#[derive(Clone, Default, Serialize)]
+pub struct DomainConfig {
+    /// Hypervisor type, e.g. `kvm`.
+    pub domain_type: String,
+    pub name: String,
+    pub uuid: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub os: Os,
+    pub cpu: Cpu,
+    pub memory_kib: Option<u64>,
+    pub current_memory_kib: Option<u64>,
+    pub emulator: Option<String>,
+    pub disks: Vec<Disk>,
+    pub interfaces: Vec<Interface>,
+    pub graphics: Vec<Graphics>,
+    pub serials: Vec<Serial>,
+    pub channels: Vec<Channel>,
+    pub hostdevs: Vec<HostDev>,
+    pub metadata: Metadata,
+}
+
+fn parse_u32(text: Option<&str>) -> Option<u32> {
+    text.and_then(|t| t.trim().parse().ok())
+}
+
+fn parse_port(text: Option<&str>) -> Option<u16> {
+    text.and_then(|t| t.trim().parse().ok())
+}
+
+fn memory(elem: Option<&Element>) -> Option<u64> {
+    let elem = elem?;
+    memory_to_kib(elem.text()?, elem.attr("unit"))
+}
+
+impl DomainConfig {
+    pub fn parse(xml: &str) -> Result<Self, xmlparser::Error> {
+        Ok(Self::from_element(&parse_tree(xml)?))
+    }
+
+    pub fn from_element(root: &Element) -> Self {
+        let devices = root.child("devices");
+        let devices_named = |name: &'static str| {
+            devices
+                .into_iter()
+                .flat_map(move |d| d.children_named(name))
+        };
+        Self {
+            domain_type: root.attr("type").unwrap_or_default().to_string(),
+            name: root.child_text("name").unwrap_or_default(),
+            uuid: root.child_text("uuid"),
+            title: root.child_text("title"),
+            description: root.child_text("description"),
+            os: root.child("os").map(parse_os).unwrap_or_default(),
+            cpu: parse_cpu(root),
+            memory_kib: memory(root.child("memory")),
+            current_memory_kib: memory(root.child("currentMemory")),
+            emulator: devices.and_then(|d| d.child_text("emulator")),
+            disks: devices_named("disk").map(parse_disk).collect(),
+            interfaces: devices_named("interface").map(parse_interface).collect(),
+            graphics: devices_named("graphics").map(parse_graphics).collect(),
+            serials: devices_named("serial").map(parse_serial).collect(),
+            channels: devices_named("channel").map(parse_channel).collect(),
+            hostdevs: devices_named("hostdev").map(parse_hostdev).collect(),
+            metadata: root.child("metadata").map(parse_metadata).unwrap_or_default(),
+        }
+    }
+
+    /// vCPU count for the table, e.g. `4` or `2/4` when some are offline.
+    pub fn vcpus_label(&self) -> String {
+        match (self.cpu.current_vcpus, self.cpu.vcpus) {
+            (Some(current), Some(max)) if current != max => format!("{current}/{max}"),
+            (_, Some(max)) => max.to_string(),
+            _ => "N/A".to_string(),
+        }
+    }
+
+    /// Short OS name derived from the libosinfo id, e.g. `ubuntu22.04`
+    /// for `http://ubuntu.com/ubuntu/22.04`.
+    pub fn os_variant(&self) -> Option<String> {
+        let id = self.metadata.libosinfo_id.as_deref()?;
+        let mut parts = id.trim_end_matches('/').rsplit('/');
+        let version = parts.next()?;
+        let distro = parts.next()?;
+        Some(format!("{distro}{version}"))
+    }
+
+    pub fn memory_label(&self) -> String {
+        self.memory_kib.map_or_else(|| "N/A".to_string(), format_mib)
+    }
+
+    /// Networks, bridges and host devices the interfaces attach to, without duplicates.
+    pub fn networks(&self) -> Vec<&str> {
+        let mut networks: Vec<&str> = Vec::new();
+        for source in self.interfaces.iter().filter_map(|i| i.source.as_deref()) {
+            if !networks.contains(&source) {
+                networks.push(source);
+            }
+        }
+        networks
+    }
+
+    /// Multi-line summary shown in the info pane.
+    ///
+    /// The Network, Interfaces, Emulator and Disks lines are always present;
+    /// other lines only when the domain has something to show.
+    pub fn summary(&self) -> String {
+        let or_na = |items: Vec<String>| {
+            if items.is_empty() {
+                "N/A".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        let mut lines = Vec::new();
+
+        let mut identity = Vec::new();
+        if let Some(uuid) = &self.uuid {
+            identity.push(format!("UUID: {uuid}"));
+        }
+        if let Some(title) = &self.title {
+            identity.push(format!("Title: {title}"));
+        }
+        if !identity.is_empty() {
+            lines.push(identity.join("  "));
+        }
+
+        let mut os = Vec::new();
+        if !self.domain_type.is_empty() {
+            os.push(self.domain_type.clone());
+        }
+        os.extend(self.os.os_type.clone());
+        os.extend(self.os.arch.clone());
+        if let Some(machine) = &self.os.machine {
+            os.push(format!("({machine})"));
+        }
+        match self.os.firmware.as_deref() {
+            Some("efi") if self.os.secure_boot => os.push("UEFI, Secure Boot".to_string()),
+            Some("efi") => os.push("UEFI".to_string()),
+            Some(other) => os.push(other.to_uppercase()),
+            None => {}
+        }
+        if !self.os.boot_devices.is_empty() {
+            os.push(format!("boot: {}", self.os.boot_devices.join(",")));
+        }
+        if let Some(variant) = self.os_variant() {
+            os.push(format!("[{variant}]"));
+        }
+        if !os.is_empty() {
+            lines.push(format!("OS: {}", os.join(" ")));
+        }
+
+        let mut cpu = format!("{} vCPUs", self.vcpus_label());
+        let mut cpu_details = Vec::new();
+        cpu_details.extend(self.cpu.mode.clone());
+        cpu_details.extend(self.cpu.model.clone());
+        if let (Some(s), Some(c), Some(t)) = (self.cpu.sockets, self.cpu.cores, self.cpu.threads) {
+            cpu_details.push(format!("{s}s/{c}c/{t}t"));
+        }
+        if !cpu_details.is_empty() {
+            cpu.push_str(&format!(" ({})", cpu_details.join(", ")));
+        }
+        let mut memory = self.memory_label();
+        if let (Some(current), Some(max)) = (self.current_memory_kib, self.memory_kib)
+            && current != max
+        {
+            memory = format!("{} of {memory}", format_mib(current));
+        }
+        lines.push(format!("CPU: {cpu}  Memory: {memory}"));
+
+        lines.push(format!(
+            "Network: {}",
+            or_na(self.networks().into_iter().map(str::to_string).collect())
+        ));
+
+        let interfaces = self
+            .interfaces
+            .iter()
+            .map(|i| {
+                let mut parts = vec![i.kind.clone()];
+                parts.extend(i.source.clone());
+                if let Some(mode) = &i.mode {
+                    parts.push(format!("mode={mode}"));
+                }
+                parts.extend(i.model.clone());
+                parts.extend(i.mac.clone());
+                if let Some(target) = &i.target {
+                    parts.push(format!("-> {target}"));
+                }
+                parts.join(" ")
+            })
+            .collect();
+        lines.push(format!("Interfaces: {}", or_na(interfaces)));
+        lines.push(format!("Emulator: {}", self.emulator.as_deref().unwrap_or("N/A")));
+
+        let disks = self
+            .disks
+            .iter()
+            .map(|d| {
+                let target = d.target.as_deref().unwrap_or("unknown");
+                let source = d.source.as_deref().unwrap_or("unknown");
+                let mut details = vec![d.kind.clone()];
+                details.extend(d.format.clone());
+                details.extend(d.bus.clone());
+                if d.readonly {
+                    details.push("ro".to_string());
+                }
+                let details = details.join(", ");
+                if d.device == "disk" {
+                    format!("{target}: {source} ({details})")
+                } else {
+                    format!("{target} [{}]: {source} ({details})", d.device)
+                }
+            })
+            .collect();
+        lines.push(format!("Disks: {}", or_na(disks)));
+
+        let graphics: Vec<String> = self
+            .graphics
+            .iter()
+            .map(|g| {
+                let listen = g.listen.as_deref().unwrap_or("*");
+                let mut text = match g.port {
+                    Some(port) => format!("{} {listen}:{port}", g.kind),
+                    None => format!("{} {listen}", g.kind),
+                };
+                if let Some(tls_port) = g.tls_port {
+                    text.push_str(&format!(" tls:{tls_port}"));
+                }
+                let mut flags = Vec::new();
+                if g.autoport {
+                    flags.push("autoport");
+                }
+                if g.has_password {
+                    flags.push("password");
+                }
+                if !flags.is_empty() {
+                    text.push_str(&format!(" ({})", flags.join(", ")));
+                }
+                text
+            })
+            .collect();
+        if !graphics.is_empty() {
+            lines.push(format!("Graphics: {}", graphics.join(", ")));
+        }
+
+        let mut devices: Vec<String> = Vec::new();
+        devices.extend(self.serials.iter().map(|s| {
+            let mut text = format!("serial{} {}", s.target_port.as_deref().unwrap_or(""), s.kind);
+            if let Some(path) = &s.path {
+                text.push_str(&format!(" {path}"));
+            }
+            text
+        }));
+        devices.extend(self.channels.iter().map(|c| {
+            let mut text = format!(
+                "channel {} {}",
+                c.kind,
+                c.target_name.as_deref().unwrap_or("")
+            );
+            if let Some(state) = &c.state {
+                text.push_str(&format!(" ({state})"));
+            }
+            text
+        }));
+        devices.extend(self.hostdevs.iter().map(|h| {
+            format!("hostdev {} {}", h.kind, h.source.as_deref().unwrap_or("unknown"))
+        }));
+        if !devices.is_empty() {
+            lines.push(format!("Devices: {}", devices.join(", ")));
+        }
+
+        if !self.metadata.entries.is_empty() {
+            lines.push(format!("Metadata: {}", self.metadata.entries.join(", ")));
+        }
+        if !self.metadata.tags.is_empty() {
+            lines.push(format!("Tags: {}", self.metadata.tags.join(", ")));
+        }
+        if let Some(description) = &self.description {
+            lines.push(format!("Description: {}", description.replace('\n', " ")));
+        }
+        lines.join("\n")
+    }
+}
+
+fn parse_os(os: &Element) -> Os {
+    let os_type = os.child("type");
+    let loader = os.child("loader");
+    let firmware = os.attr("firmware").map(str::to_string).or_else(|| {
+        loader
+            .and_then(|l| l.attr("type"))
+            .filter(|t| *t == "pflash")
+            .map(|_| "efi".to_string())
+    });
+    Os {
+        os_type: os_type.and_then(Element::text).map(str::to_string),
+        arch: os_type.and_then(|t| t.attr("arch")).map(str::to_string),
+        machine: os_type.and_then(|t| t.attr("machine")).map(str::to_string),
+        firmware,
+        secure_boot: loader.and_then(|l| l.attr("secure")) == Some("yes")
+            || os
+                .child("firmware")
+                .into_iter()
+                .flat_map(|f| f.children_named("feature"))
+                .any(|f| f.attr("name") == Some("secure-boot") && f.attr("enabled") == Some("yes")),
+        loader: os.child_text("loader"),
+        nvram: os.child_text("nvram"),
+        boot_devices: os
+            .children_named("boot")
+            .filter_map(|b| b.attr("dev"))
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
+fn parse_cpu(root: &Element) -> Cpu {
+    let vcpu = root.child("vcpu");
+    let cpu = root.child("cpu");
+    let topology = cpu.and_then(|c| c.child("topology"));
+    Cpu {
+        vcpus: parse_u32(vcpu.and_then(Element::text)),
+        current_vcpus: parse_u32(vcpu.and_then(|v| v.attr("current"))),
+        mode: cpu.and_then(|c| c.attr("mode")).map(str::to_string),
+        model: cpu.and_then(|c| c.child_text("model")),
+        sockets: parse_u32(topology.and_then(|t| t.attr("sockets"))),
+        cores: parse_u32(topology.and_then(|t| t.attr("cores"))),
+        threads: parse_u32(topology.and_then(|t| t.attr("threads"))),
+    }
+}
+
+fn parse_disk(disk: &Element) -> Disk {
+    let kind = disk.attr("type").unwrap_or("file").to_string();
+    let source = disk.child("source").and_then(|s| match kind.as_str() {
+        "network" => {
+            let protocol = s.attr("protocol").unwrap_or("network");
+            let name = s.attr("name").unwrap_or("");
+            let host = s.child("host").and_then(|h| h.attr("name"));
+            Some(match host {
+                Some(host) => format!("{protocol}://{host}/{name}"),
+                None => format!("{protocol}:{name}"),
+            })
+        }
+        "volume" => Some(format!(
+            "{}/{}",
+            s.attr("pool").unwrap_or(""),
+            s.attr("volume").unwrap_or("")
+        )),
+        _ => ["file", "dev", "name", "path"]
+            .iter()
+            .find_map(|a| s.attr(a))
+            .map(str::to_string),
+    });
+    Disk {
+        device: disk.attr("device").unwrap_or("disk").to_string(),
+        format: disk.child_attr("driver", "type"),
+        source,
+        target: disk.child_attr("target", "dev"),
+        bus: disk.child_attr("target", "bus"),
+        readonly: disk.child("readonly").is_some(),
+        kind,
+    }
+}
+
+fn parse_interface(iface: &Element) -> Interface {
+    let source = iface.child("source");
+    Interface {
+        kind: iface.attr("type").unwrap_or_default().to_string(),
+        source: source
+            .and_then(|s| ["network", "bridge", "dev"].iter().find_map(|a| s.attr(a)))
+            .map(str::to_string),
+        mode: source.and_then(|s| s.attr("mode")).map(str::to_string),
+        mac: iface.child_attr("mac", "address"),
+        model: iface.child_attr("model", "type"),
+        target: iface.child_attr("target", "dev"),
+    }
+}
+
+fn parse_graphics(graphics: &Element) -> Graphics {
+    let listen = graphics.attr("listen").map(str::to_string).or_else(|| {
+        graphics
+            .child("listen")
+            .and_then(|l| l.attr("address").or(l.attr("socket")))
+            .map(str::to_string)
+    });
+    Graphics {
+        kind: graphics.attr("type").unwrap_or_default().to_string(),
+        port: parse_port(graphics.attr("port")),
+        tls_port: parse_port(graphics.attr("tlsPort")),
+        autoport: graphics.attr("autoport") == Some("yes"),
+        listen,
+        has_password: graphics.attr("passwd").is_some(),
+    }
+}
+
+fn parse_serial(serial: &Element) -> Serial {
+    let target = serial.child("target");
+    Serial {
+        kind: serial.attr("type").unwrap_or_default().to_string(),
+        target_port: target.and_then(|t| t.attr("port")).map(str::to_string),
+        path: serial
+            .child_attr("source", "path")
+            .or_else(|| serial.attr("tty").map(str::to_string)),
+    }
+}
+
+fn parse_channel(channel: &Element) -> Channel {
+    Channel {
+        kind: channel.attr("type").unwrap_or_default().to_string(),
+        target_name: channel.child_attr("target", "name"),
+        state: channel.child_attr("target", "state"),
+    }
+}
+
+fn parse_hostdev(hostdev: &Element) -> HostDev {
+    let source = hostdev.child("source");
+    let pci = source.and_then(|s| s.child("address")).map(|a| {
+        let field = |name: &str| {
+            a.attr(name)
+                .map(|v| v.trim_start_matches("0x").to_string())
+                .unwrap_or_default()
+        };
+        format!("{}:{}:{}.{}", field("domain"), field("bus"), field("slot"), field("function"))
+    });
+    let usb = source.and_then(|s| {
+        let vendor = s.child_attr("vendor", "id")?;
+        let product = s.child_attr("product", "id")?;
+        Some(format!(
+            "{}:{}",
+            vendor.trim_start_matches("0x"),
+            product.trim_start_matches("0x")
+        ))
+    });
+    HostDev {
+        kind: hostdev.attr("type").unwrap_or_default().to_string(),
+        source: usb.or(pci),
+    }
+}
+
+fn parse_metadata(metadata: &Element) -> Metadata {
+    Metadata {
+        entries: metadata.children.iter().map(|c| c.name.clone()).collect(),
+        libosinfo_id: metadata
+            .children
+            .iter()
+            .find(|c| c.name.ends_with(":libosinfo"))
+            .and_then(|l| l.children.iter().find(|c| c.name.ends_with(":os")))
+            .and_then(|os| os.attr("id"))
+            .map(str::to_string),
+        tags: metadata
+            .children
+            .iter()
+            .find(|c| {
+                c.attrs
+                    .iter()
+                    .any(|(k, v)| k.starts_with("xmlns") && v == TAGS_NAMESPACE)
+            })
+            .and_then(Element::text)
+            .map(|text| {
+                text.split([',', ' ', '\n', '\t'])
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> DomainConfig {
+        DomainConfig::parse(xml).unwrap()
+    }
+
+    #[test]
+    fn uefi_secure_boot() {
+        let config = parse(include_str!("../fixtures/web01-uefi.xml"));
+        assert_eq!(config.name, "web01");
+        assert_eq!(config.os.firmware.as_deref(), Some("efi"));
+        assert!(config.os.secure_boot);
+        assert_eq!(config.os.loader.as_deref(), Some("/usr/share/OVMF/OVMF_CODE_4M.ms.fd"));
+        assert_eq!(config.os.nvram.as_deref(), Some("/var/lib/libvirt/qemu/nvram/web01_VARS.fd"));
+        assert_eq!(config.os.boot_devices, ["hd"]);
+        let cdrom = &config.disks[1];
+        assert_eq!((cdrom.device.as_str(), cdrom.readonly), ("cdrom", true));
+        assert_eq!(config.interfaces[0].mac.as_deref(), Some("52:54:00:6c:2a:01"));
+    }
+
+    #[test]
+    fn network_and_volume_disks() {
+        let config = parse(include_str!("../fixtures/db01-network-disks.xml"));
+        assert_eq!(config.os.firmware, None);
+        assert_eq!((config.os.loader.as_deref(), config.os.nvram.as_deref()), (None, None));
+        let disks: Vec<_> = config
+            .disks
+            .iter()
+            .map(|d| (d.kind.as_str(), d.device.as_str(), d.source.as_deref().unwrap_or("")))
+            .collect();
+        assert_eq!(
+            disks,
+            [
+                ("network", "disk", "rbd://ceph-mon1.lab/vms/db01-root"),
+                ("volume", "disk", "fast-ssd/db01-data.qcow2"),
+                ("network", "lun", "iscsi://san1.lab/iqn.2024-01.lab.storage:db-logs/1"),
+                ("block", "disk", "/dev/mapper/vg_db-backup"),
+            ]
+        );
+        assert_eq!(config.disks[1].format.as_deref(), Some("qcow2"));
+    }
+
+    #[test]
+    fn bridge_and_macvtap_interfaces() {
+        let config = parse(include_str!("../fixtures/build-bridge-macvtap.xml"));
+        // No `firmware` attribute; inferred from the pflash loader.
+        assert_eq!(config.os.firmware.as_deref(), Some("efi"));
+        assert!(!config.os.secure_boot);
+        assert_eq!(config.os.loader.as_deref(), Some("/usr/share/edk2/ovmf/OVMF_CODE.fd"));
+        assert_eq!(config.os.nvram.as_deref(), Some("/var/lib/libvirt/qemu/nvram/build_VARS.fd"));
+        let interfaces: Vec<_> = config
+            .interfaces
+            .iter()
+            .map(|i| (i.kind.as_str(), i.source.as_deref(), i.mode.as_deref(), i.mac.as_deref()))
+            .collect();
+        assert_eq!(
+            interfaces,
+            [
+                ("bridge", Some("br0"), None, Some("52:54:00:6c:2a:03")),
+                ("direct", Some("enp5s0f1"), Some("bridge"), Some("52:54:00:6c:2a:13")),
+            ]
+        );
+    }
+
+    #[test]
+    fn summaries() {
+        assert_eq!(
+            parse(include_str!("../fixtures/web01-uefi.xml")).summary(),
+            "UUID: 6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e01  Title: Public web frontend
+OS: kvm hvm x86_64 (pc-q35-8.2) UEFI, Secure Boot boot: hd [ubuntu22.04]
+CPU: 4 vCPUs (host-passthrough, 1s/2c/2t)  Memory: 4096 MiB
+Network: default
+Interfaces: network default virtio 52:54:00:6c:2a:01 -> vnet0
+Emulator: /usr/bin/qemu-system-x86_64
+Disks: vda: /var/lib/libvirt/images/web01.qcow2 (file, qcow2, virtio), \
+sda [cdrom]: /var/lib/libvirt/images/web01-seed.iso (file, raw, sata, ro)
+Graphics: spice 127.0.0.1:5900 (autoport)
+Devices: serial0 pty /dev/pts/3, channel unix org.qemu.guest_agent.0 (connected)
+Metadata: libosinfo:libosinfo
+Description: nginx + app server, managed by ansible"
+        );
+        assert_eq!(
+            parse(include_str!("../fixtures/db01-network-disks.xml")).summary(),
+            "UUID: 6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e02
+OS: kvm hvm x86_64 (pc-i440fx-rhel7.6.0) boot: hd,network [rhel9.4]
+CPU: 4/8 vCPUs (custom, Cascadelake-Server-noTSX, 2s/4c/1t)  Memory: 12288 MiB of 16384 MiB
+Network: default
+Interfaces: network default virtio 52:54:00:6c:2a:02
+Emulator: /usr/libexec/qemu-kvm
+Disks: vda: rbd://ceph-mon1.lab/vms/db01-root (network, raw, virtio), \
+vdb: fast-ssd/db01-data.qcow2 (volume, qcow2, virtio), \
+sda [lun]: iscsi://san1.lab/iqn.2024-01.lab.storage:db-logs/1 (network, raw, scsi), \
+vdc: /dev/mapper/vg_db-backup (block, raw, virtio)
+Graphics: vnc 0.0.0.0 (autoport, password)
+Devices: serial0 pty, channel unix org.qemu.guest_agent.0 (disconnected), hostdev pci 0000:3b:00.1
+Metadata: libosinfo:libosinfo"
+        );
+        assert_eq!(
+            parse(include_str!("../fixtures/build-bridge-macvtap.xml")).summary(),
+            "UUID: 6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e03  Title: CI build runner
+OS: kvm hvm x86_64 (q35) UEFI
+CPU: 8 vCPUs (host-model)  Memory: 16384 MiB
+Network: br0, enp5s0f1
+Interfaces: bridge br0 virtio 52:54:00:6c:2a:03, direct enp5s0f1 mode=bridge virtio 52:54:00:6c:2a:13
+Emulator: /usr/bin/qemu-system-x86_64
+Disks: vda: /srv/vm/build.qcow2 (file, qcow2, virtio)
+Devices: hostdev usb 1050:0407
+Metadata: ci:runner"
+        );
+    }
+
+    #[test]
+    fn display_uris() {
+        let web01 = parse(include_str!("../fixtures/web01-uefi.xml"));
+        assert_eq!(web01.graphics[0].display_uri("lab1").as_deref(), Some("spice://127.0.0.1:5900"));
+        // autoport has not allocated a port while the domain is shut off.
+        let db01 = parse(include_str!("../fixtures/db01-network-disks-inactive.xml"));
+        assert_eq!(db01.graphics[0].display_uri("lab1"), None);
+
+        let cases = [
+            ("<graphics type='vnc' port='5901'/>", "lab1", Some("vnc://lab1:5901")),
+            ("<graphics type='vnc' port='5901' listen='0.0.0.0'/>", "lab1", Some("vnc://lab1:5901")),
+            ("<graphics type='vnc' port='5901' listen='::'/>", "lab1", Some("vnc://lab1:5901")),
+            ("<graphics type='vnc' port='5901'><listen type='address' address='::'/></graphics>", "fd00::1", Some("vnc://[fd00::1]:5901")),
+            ("<graphics type='vnc' port='5901' listen='fd00::5'/>", "lab1", Some("vnc://[fd00::5]:5901")),
+            ("<graphics type='vnc' port='5901' listen='192.168.1.5'/>", "lab1", Some("vnc://192.168.1.5:5901")),
+            ("<graphics type='vnc'><listen type='socket' socket='/run/libvirt/qemu/vnc.sock'/></graphics>", "lab1", Some("vnc+unix:///run/libvirt/qemu/vnc.sock")),
+            ("<graphics type='spice' port='5902' tlsPort='5903'/>", "lab1", Some("spice://lab1:5902?tls-port=5903")),
+            ("<graphics type='vnc' port='5902' tlsPort='5903'/>", "lab1", Some("vnc://lab1:5902")),
+            ("<graphics type='spice' autoport='yes'/>", "lab1", None),
+            ("<graphics type='sdl' display=':0'/>", "lab1", None),
+        ];
+        for (graphics, host, expected) in cases {
+            let config = parse(&format!("<domain><name>vm</name><devices>{graphics}</devices></domain>"));
+            assert_eq!(config.graphics[0].display_uri(host).as_deref(), expected, "{graphics}");
+        }
+    }
+
+    #[test]
+    fn tags_from_yalv_metadata() {
+        let config = parse(include_str!("../fixtures/tagged.xml"));
+        assert_eq!(config.metadata.tags, ["ci", "build", "nightly"]);
+        assert_eq!(config.metadata.entries, ["ci:runner", "yalv:tags"]);
+    }
+
+    #[test]
+    fn no_tags_without_yalv_metadata() {
+        let config = parse(include_str!("../fixtures/build-bridge-macvtap.xml"));
+        assert!(config.metadata.tags.is_empty());
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        let text = r#"<a href="x">Tom & Jerry's</a>"#;
+        assert_eq!(escape_xml(text), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+        assert_eq!(unescape(&escape_xml(text)), text);
+        let xml = format!("<domain><description title='{}'>{}</description></domain>", escape_xml(text), escape_xml(text));
+        let tree = parse_tree(&xml).unwrap();
+        let again = parse_tree(&tree.to_xml()).unwrap();
+        let description = &again.children[0];
+        assert_eq!(description.text, text);
+        assert_eq!(description.attrs, [("title".to_string(), text.to_string())]);
+    }
+}
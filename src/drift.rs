@@ -0,0 +1,282 @@
+//! Differences between a running VM's live configuration and the persistent
+//! one it boots with next time, e.g. after hotplugging a disk.
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table};
+
+use crate::domain::{self, Disk, DomainConfig, Interface};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// In the live configuration only; gone after a restart.
+    LiveOnly,
+    /// In the persistent configuration only; appears after a restart.
+    NextBootOnly,
+    Differs,
+}
+
+pub struct Change {
+    pub kind: ChangeKind,
+    /// e.g. `disk vdb`, `NIC 52:54:00:6c:2a:02` or `memory`
+    pub item: String,
+    pub live: Option<String>,
+    pub next_boot: Option<String>,
+}
+
+/// Compare the items of `live` and `next_boot` that share a key, and list those in only one.
+fn compare<T>(
+    changes: &mut Vec<Change>,
+    live: &[T],
+    next_boot: &[T],
+    key: impl Fn(&T) -> String,
+    describe: impl Fn(&T) -> String,
+) {
+    for l in live {
+        let item = key(l);
+        let next = next_boot.iter().find(|n| key(n) == item).map(&describe);
+        let kind = match &next {
+            None => ChangeKind::LiveOnly,
+            Some(next) if *next != describe(l) => ChangeKind::Differs,
+            Some(_) => continue,
+        };
+        changes.push(Change {
+            kind,
+            item,
+            live: Some(describe(l)),
+            next_boot: next,
+        });
+    }
+    for n in next_boot.iter().filter(|n| !live.iter().any(|l| key(l) == key(n))) {
+        changes.push(Change {
+            kind: ChangeKind::NextBootOnly,
+            item: key(n),
+            live: None,
+            next_boot: Some(describe(n)),
+        });
+    }
+}
+
+fn disk_key(disk: &Disk) -> String {
+    let target = disk.target.as_deref().or(disk.source.as_deref()).unwrap_or("?");
+    format!("{} {target}", disk.device)
+}
+
+fn describe_disk(disk: &Disk) -> String {
+    let mut text = disk.source.clone().unwrap_or_else(|| "(empty)".to_string());
+    if let Some(bus) = &disk.bus {
+        text.push_str(&format!(" ({bus})"));
+    }
+    if disk.readonly {
+        text.push_str(" read-only");
+    }
+    text
+}
+
+fn nic_key(nic: &Interface) -> String {
+    format!("NIC {}", nic.mac.as_deref().unwrap_or("?"))
+}
+
+fn describe_nic(nic: &Interface) -> String {
+    let mut text = format!("{} {}", nic.kind, nic.source.as_deref().unwrap_or("?"));
+    if let Some(model) = &nic.model {
+        text.push_str(&format!(" ({model})"));
+    }
+    text
+}
+
+/// What differs between the `live` and the `next_boot` (inactive) configuration:
+/// memory, vCPUs, disks and NICs.
+pub fn changes(live: &DomainConfig, next_boot: &DomainConfig) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut value = |item: &str, live: String, next: String| {
+        if live != next {
+            changes.push(Change {
+                kind: ChangeKind::Differs,
+                item: item.to_string(),
+                live: Some(live),
+                next_boot: Some(next),
+            });
+        }
+    };
+    value("memory", live.memory_label(), next_boot.memory_label());
+    let current = |c: &DomainConfig| c.current_memory_kib.map_or_else(|| "N/A".to_string(), domain::format_mib);
+    value("current memory", current(live), current(next_boot));
+    value("vCPUs", live.vcpus_label(), next_boot.vcpus_label());
+    compare(&mut changes, &live.disks, &next_boot.disks, disk_key, describe_disk);
+    compare(&mut changes, &live.interfaces, &next_boot.interfaces, nic_key, describe_nic);
+    changes
+}
+
+/// Number of differences between the live XML and the inactive XML of a domain.
+pub fn count(live: &DomainConfig, inactive_xml: &str) -> Result<usize, String> {
+    let next_boot = DomainConfig::parse(inactive_xml).map_err(|e| format!("unable to parse the inactive XML: {e}"))?;
+    Ok(changes(live, &next_boot).len())
+}
+
+/// The differences of one VM, shown in place of the VM table.
+pub struct DriftPanel {
+    pub host: String,
+    pub vm_name: String,
+    /// Live and inactive XML; `None` until loaded.
+    xml: Option<Result<(String, String), String>>,
+    changes: Vec<Change>,
+}
+
+impl DriftPanel {
+    pub fn new(host: &str, vm_name: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            xml: None,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Compare the live and the inactive XML in `result`.
+    pub fn set_xml(&mut self, result: Result<(String, String), String>) {
+        let parsed = result.and_then(|(live, inactive)| {
+            let parse = |xml: &str| DomainConfig::parse(xml).map_err(|e| format!("unable to parse the XML: {e}"));
+            self.changes = changes(&parse(&live)?, &parse(&inactive)?);
+            Ok((live, inactive))
+        });
+        if parsed.is_err() {
+            self.changes.clear();
+        }
+        self.xml = Some(parsed);
+    }
+
+    /// The live and the inactive XML, once loaded.
+    pub fn xml(&self) -> Option<(&str, &str)> {
+        match &self.xml {
+            Some(Ok((live, inactive))) => Some((live, inactive)),
+            _ => None,
+        }
+    }
+}
+
+pub fn render(f: &mut Frame, area: Rect, panel: &DriftPanel) {
+    let title = format!(
+        " Live vs next boot of '{}' (d: XML diff, r: reload, Esc: close) ",
+        panel.vm_name
+    );
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let changes = match &panel.xml {
+        None => {
+            f.render_widget(Paragraph::new("Loading…").block(block), area);
+            return;
+        }
+        Some(Err(e)) => {
+            let text = Paragraph::new(e.as_str()).style(Style::default().fg(Color::Red));
+            f.render_widget(text.block(block), area);
+            return;
+        }
+        Some(Ok(_)) if panel.changes.is_empty() => {
+            let text = "The live configuration matches the one for the next boot.";
+            f.render_widget(Paragraph::new(text).block(block), area);
+            return;
+        }
+        Some(Ok(_)) => &panel.changes,
+    };
+
+    let absent = || Cell::from("—").style(Style::default().fg(Color::DarkGray));
+    let rows: Vec<Row> = changes
+        .iter()
+        .map(|c| {
+            let (label, color) = match c.kind {
+                ChangeKind::LiveOnly => ("live only", Color::Yellow),
+                ChangeKind::NextBootOnly => ("next boot only", Color::Cyan),
+                ChangeKind::Differs => ("differs", Color::Magenta),
+            };
+            Row::new(vec![
+                Cell::from(label).style(Style::default().fg(color)),
+                Cell::from(c.item.clone()),
+                c.live.clone().map_or_else(absent, Cell::from),
+                c.next_boot.clone().map_or_else(absent, Cell::from),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(15),
+        Constraint::Length(28),
+        Constraint::Min(20),
+        Constraint::Min(20),
+    ];
+    let header = Row::new(["", "Item", "Live", "Next boot"])
+        .style(Style::default().bold())
+        .bottom_margin(1);
+    f.render_widget(Table::new(rows, widths).header(header).block(block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIVE: &str = include_str!("../fixtures/db01-network-disks.xml");
+    const INACTIVE: &str = include_str!("../fixtures/db01-network-disks-inactive.xml");
+
+    fn parse(xml: &str) -> DomainConfig {
+        DomainConfig::parse(xml).unwrap()
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(ChangeKind, &str, Option<&str>, Option<&str>)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.item.as_str(), c.live.as_deref(), c.next_boot.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn hotplugged_disk_and_vcpus() {
+        let changes = changes(&parse(LIVE), &parse(INACTIVE));
+        assert!(
+            summary(&changes)
+                == [
+                    (ChangeKind::Differs, "vCPUs", Some("4/8"), Some("8")),
+                    (
+                        ChangeKind::LiveOnly,
+                        "disk vdc",
+                        Some("/dev/mapper/vg_db-backup (virtio)"),
+                        None
+                    ),
+                ]
+        );
+        assert_eq!(count(&parse(LIVE), INACTIVE), Ok(2));
+    }
+
+    #[test]
+    fn nic_added_for_next_boot() {
+        let nic = "<interface type='bridge'>
+      <mac address='52:54:00:6c:2a:12'/>
+      <source bridge='br0'/>
+      <model type='e1000'/>
+    </interface>
+    <hostdev ";
+        let inactive = LIVE.replacen("<hostdev ", nic, 1);
+        let changes = changes(&parse(LIVE), &parse(&inactive));
+        assert!(
+            summary(&changes)
+                == [(
+                    ChangeKind::NextBootOnly,
+                    "NIC 52:54:00:6c:2a:12",
+                    None,
+                    Some("bridge br0 (e1000)")
+                )]
+        );
+    }
+
+    #[test]
+    fn memory_changed() {
+        let inactive = LIVE.replace("<memory unit='GiB'>16</memory>", "<memory unit='GiB'>32</memory>");
+        let changes = changes(&parse(LIVE), &parse(&inactive));
+        assert!(
+            summary(&changes) == [(ChangeKind::Differs, "memory", Some("16384 MiB"), Some("32768 MiB"))]
+        );
+    }
+
+    #[test]
+    fn identical_configs() {
+        assert!(changes(&parse(LIVE), &parse(LIVE)).is_empty());
+        assert_eq!(count(&parse(INACTIVE), INACTIVE), Ok(0));
+        assert!(count(&parse(LIVE), "<domain type=kvm>").is_err());
+    }
+}
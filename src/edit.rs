@@ -0,0 +1,292 @@
+//! Editing a domain's XML in `$VISUAL` / `$EDITOR`, like `virsh edit`.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use log::{info, warn};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+use crate::backend::Backend;
+use crate::domain::{self, DomainConfig};
+
+/// Marks the comment with libvirt's error that is put above the XML.
+const ERROR_MARK: &str = "<!-- yalv-rust: ";
+
+/// The inactive XML of a VM, fetched and ready to be edited.
+pub struct EditJob {
+    pub host: String,
+    pub vm_name: String,
+    pub xml: String,
+}
+
+/// How an edit ended.
+pub enum Outcome {
+    /// libvirt accepted the edited XML, which is returned.
+    Defined(String),
+    /// The file was saved without changes.
+    Unchanged,
+    /// The editor was left without fixing the XML rejected with this error.
+    Abandoned(String),
+}
+
+/// The editor command: `$VISUAL`, else `$EDITOR`, else `vi`.
+fn editor() -> Result<Vec<String>, String> {
+    let command = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(|v| v.to_string_lossy().into_owned())
+        .find(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    crate::viewer::parse_command(&command).map_err(|e| format!("invalid editor command: {e}"))
+}
+
+/// The temporary file being edited; removed on drop.
+struct EditFile {
+    path: PathBuf,
+}
+
+impl EditFile {
+    fn create(vm_name: &str) -> Result<Self, String> {
+        let name: String = vm_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = std::env::temp_dir().join(format!("yalv-rust-{name}-{}.xml", std::process::id()));
+        // Only we may read it: the XML can hold passwords, e.g. of VNC.
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| format!("cannot create {}: {e}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// Write `xml`, preceded by `error` as a comment if given, and return what was written.
+    fn write(&self, xml: &str, error: Option<&str>) -> Result<String, String> {
+        let mut text = String::new();
+        if let Some(error) = error {
+            // "--" may not appear inside a comment.
+            let error = error.replace("--", "- -");
+            text.push_str(&format!(
+                "{ERROR_MARK}this XML was rejected; fix it and save,\n     \
+                 or quit without saving to give up.\n\n     {error} -->\n"
+            ));
+        }
+        text.push_str(xml);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .map_err(|e| format!("cannot write {}: {e}", self.path.display()))?;
+        file.write_all(text.as_bytes())
+            .map_err(|e| format!("cannot write {}: {e}", self.path.display()))?;
+        Ok(text)
+    }
+
+    fn read(&self) -> Result<String, String> {
+        fs::read_to_string(&self.path).map_err(|e| format!("cannot read {}: {e}", self.path.display()))
+    }
+}
+
+impl Drop for EditFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Cannot remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// `text` without the error comment written by [`EditFile::write`].
+fn strip_error(text: &str) -> &str {
+    match text.strip_prefix(ERROR_MARK).and_then(|rest| rest.split_once("-->")) {
+        Some((_, xml)) => xml.strip_prefix('\n').unwrap_or(xml),
+        None => text,
+    }
+}
+
+/// Check that every end tag matches its start tag, which the tokenizer leaves to us.
+fn check_tags(xml: &str) -> Result<(), String> {
+    let line = |pos: usize| xml[..pos].matches('\n').count() + 1;
+    let mut open: Vec<(String, usize)> = Vec::new();
+    for token in Tokenizer::from(xml) {
+        match token.map_err(|e| format!("XML error: {e}"))? {
+            Token::ElementStart { span, .. } => {
+                let name = span.as_str().trim_start_matches('<').to_string();
+                open.push((name, span.start()));
+            }
+            Token::ElementEnd { end: ElementEnd::Empty, .. } => {
+                open.pop();
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Close(prefix, local),
+                span,
+            } => {
+                let name = if prefix.is_empty() {
+                    local.to_string()
+                } else {
+                    format!("{prefix}:{local}")
+                };
+                match open.pop() {
+                    Some((start, _)) if start == name => {}
+                    Some((start, pos)) => {
+                        return Err(format!(
+                            "XML error: </{name}> on line {} does not close <{start}> from line {}",
+                            line(span.start()),
+                            line(pos)
+                        ));
+                    }
+                    None => return Err(format!("XML error: unexpected </{name}> on line {}", line(span.start()))),
+                }
+            }
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some((name, pos)) => Err(format!("XML error: <{name}> from line {} is not closed", line(pos))),
+        None => Ok(()),
+    }
+}
+
+/// What can be checked before libvirt sees the XML: it is well-formed and
+/// still describes the same domain.
+fn check(xml: &str, original: &DomainConfig) -> Result<(), String> {
+    check_tags(xml)?;
+    let root = domain::parse_tree(xml).map_err(|e| format!("XML error: {e}"))?;
+    if root.name != "domain" {
+        return Err(format!("the root element is <{}>, not <domain>", root.name));
+    }
+    let config = DomainConfig::parse(xml).map_err(|e| format!("XML error: {e}"))?;
+    if config.name != original.name {
+        return Err(format!(
+            "the name changed from '{}' to '{}'; renaming is not supported",
+            original.name, config.name
+        ));
+    }
+    if let (Some(old), Some(new)) = (&original.uuid, &config.uuid)
+        && old != new
+    {
+        return Err(format!("the UUID changed from {old} to {new}; it must stay the same"));
+    }
+    Ok(())
+}
+
+/// Let the user edit `job.xml` and define the result, re-opening the editor
+/// with the error as long as it is rejected. Runs the editor in the current
+/// terminal, so the TUI must be suspended.
+pub fn edit(backend: &dyn Backend, job: &EditJob) -> Result<Outcome, String> {
+    let original = DomainConfig::parse(&job.xml).map_err(|e| format!("unable to parse the current XML: {e}"))?;
+    let editor = editor()?;
+    let file = EditFile::create(&job.vm_name)?;
+    let mut xml = job.xml.clone();
+    let mut error: Option<String> = None;
+    loop {
+        let written = file.write(&xml, error.as_deref())?;
+        info!("Editing '{}' with {}", job.vm_name, editor.join(" "));
+        let status = Command::new(&editor[0])
+            .args(&editor[1..])
+            .arg(&file.path)
+            .status()
+            .map_err(|e| format!("cannot run {}: {e}", editor[0]))?;
+        if !status.success() {
+            return Err(format!("{} exited with {status}", editor[0]));
+        }
+        let text = file.read()?;
+        if let Some(error) = error.filter(|_| text == written) {
+            return Ok(Outcome::Abandoned(error));
+        }
+        xml = strip_error(&text).to_string();
+        if xml == job.xml {
+            return Ok(Outcome::Unchanged);
+        }
+        match check(&xml, &original).and_then(|()| backend.define(&xml)) {
+            Ok(()) => {
+                info!("Redefined '{}' on {}", job.vm_name, job.host);
+                return Ok(Outcome::Defined(xml));
+            }
+            Err(e) => {
+                info!("Definition of '{}' rejected: {e}", job.vm_name);
+                error = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEB01: &str = include_str!("../fixtures/web01-uefi.xml");
+
+    #[test]
+    fn error_comment_round_trip() {
+        let file = EditFile::create("web01 (test)").unwrap();
+        let error = "XML error: unsupported configuration: --> 'virtio-scsi' <!-- here";
+        let written = file.write(WEB01, Some(error)).unwrap();
+        assert!(written.starts_with(ERROR_MARK));
+        assert!(written.contains("unsupported configuration: - -> 'virtio-scsi' <!- - here -->\n"));
+        assert_eq!(file.read().unwrap(), written);
+        assert_eq!(strip_error(&written), WEB01);
+        assert_eq!(strip_error(WEB01), WEB01);
+        let own_comment = format!("<!-- keep me -->\n{WEB01}");
+        assert_eq!(strip_error(&own_comment), own_comment);
+        let path = file.path.clone();
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn well_formed_tags() {
+        assert_eq!(check_tags(WEB01), Ok(()));
+        assert_eq!(check_tags("<domain><yalv:tags xmlns:yalv='urn:x'>a</yalv:tags><vcpu/></domain>"), Ok(()));
+    }
+
+    #[test]
+    fn mismatched_tags() {
+        let xml = "<domain>\n  <name>web01</name>\n  <devices>\n    <disk>\n  </devices>\n</domain>";
+        assert_eq!(
+            check_tags(xml),
+            Err("XML error: </devices> on line 5 does not close <disk> from line 4".to_string())
+        );
+        assert_eq!(
+            check_tags("<domain>\n</name>"),
+            Err("XML error: </name> on line 2 does not close <domain> from line 1".to_string())
+        );
+        assert!(check_tags("<domain/>\n</domain>").is_err());
+    }
+
+    #[test]
+    fn unclosed_tags() {
+        assert_eq!(
+            check_tags("<domain>\n  <name>web01</name>\n  <devices>\n"),
+            Err("XML error: <devices> from line 3 is not closed".to_string())
+        );
+        assert!(check_tags("<domain><name>web01</name").is_err());
+    }
+
+    #[test]
+    fn same_domain() {
+        let original = DomainConfig::parse(WEB01).unwrap();
+        assert_eq!(check(WEB01, &original), Ok(()));
+        assert_eq!(check(&WEB01.replace("<vcpu placement='static'>4", "<vcpu placement='static'>2"), &original), Ok(()));
+        assert_eq!(
+            check(&WEB01.replace("<name>web01</name>", "<name>web02</name>"), &original),
+            Err("the name changed from 'web01' to 'web02'; renaming is not supported".to_string())
+        );
+        let uuid = "6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e01";
+        let other = "6c2a8f0e-3b51-4d6e-9a8f-1f2b3c4d5e02";
+        assert_eq!(
+            check(&WEB01.replace(uuid, other), &original),
+            Err(format!("the UUID changed from {uuid} to {other}; it must stay the same"))
+        );
+        // libvirt keeps the UUID when none is given.
+        assert_eq!(check(&WEB01.replace(&format!("<uuid>{uuid}</uuid>"), ""), &original), Ok(()));
+        assert_eq!(
+            check("<network><name>web01</name></network>", &original),
+            Err("the root element is <network>, not <domain>".to_string())
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! The `/` search: fuzzy name matching plus `key:value` filters.
+
+use crate::{Vm, host_label};
+
+/// One whitespace-separated word of the search text.
+enum Term {
+    /// Fuzzy-matched against the VM name.
+    Name(String),
+    /// Prefix of the state, e.g. `run` or `shut`.
+    State(String),
+    /// Network, bridge or host device of an interface.
+    Net(String),
+    Tag(String),
+    /// Substring of the host name.
+    Host(String),
+}
+
+/// A parsed search; every term must match for a VM to be listed.
+#[derive(Default)]
+pub struct Filter {
+    text: String,
+    terms: Vec<Term>,
+}
+
+/// How well a VM name matched the fuzzy terms.
+pub struct Match {
+    pub score: i64,
+    /// Char indices of the matched letters in the name, sorted.
+    pub positions: Vec<usize>,
+}
+
+impl Filter {
+    /// Parse e.g. `web state:running tag:ci`. Values are case-insensitive;
+    /// unknown keys are matched against the name like plain words.
+    pub fn parse(text: &str) -> Self {
+        let terms = text
+            .split_whitespace()
+            .map(|word| {
+                let lower = word.to_lowercase();
+                match lower.split_once(':') {
+                    Some(("state", v)) => Term::State(v.to_string()),
+                    Some(("net", v)) => Term::Net(v.to_string()),
+                    Some(("tag", v)) => Term::Tag(v.to_string()),
+                    Some(("host", v)) => Term::Host(v.to_string()),
+                    _ => Term::Name(lower),
+                }
+            })
+            .collect();
+        Self {
+            text: text.trim().to_string(),
+            terms,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// `None` if the VM is filtered out.
+    pub fn matches(&self, vm: &Vm) -> Option<Match> {
+        let mut result = Match {
+            score: 0,
+            positions: Vec::new(),
+        };
+        for term in &self.terms {
+            match term {
+                Term::Name(pattern) => {
+                    let (score, positions) = fuzzy_match(pattern, &vm.name)?;
+                    result.score += score;
+                    result.positions.extend(positions);
+                }
+                Term::State(state) => {
+                    if !vm.state.starts_with(state.as_str()) {
+                        return None;
+                    }
+                }
+                Term::Net(net) => {
+                    let config = vm.config.as_ref()?;
+                    if !config.networks().iter().any(|n| n.eq_ignore_ascii_case(net)) {
+                        return None;
+                    }
+                }
+                Term::Tag(tag) => {
+                    let config = vm.config.as_ref()?;
+                    if !config.metadata.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                        return None;
+                    }
+                }
+                Term::Host(host) => {
+                    if !host_label(&vm.host).to_lowercase().contains(host.as_str()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        result.positions.sort_unstable();
+        result.positions.dedup();
+        Some(result)
+    }
+}
+
+/// Match `pattern` (lowercase) as a subsequence of `text`, case-insensitively.
+///
+/// Consecutive letters and letters at the start of a word score higher, so
+/// `db` ranks `db01` above `dev-backup`.
+fn fuzzy_match(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut positions = Vec::new();
+    let mut score = 0;
+    let mut start = 0;
+    for p in pattern.chars() {
+        let offset = chars[start..]
+            .iter()
+            .position(|c| c.to_lowercase().eq(std::iter::once(p)))?;
+        let i = start + offset;
+        score += 1;
+        if positions.last().is_some_and(|&last| last + 1 == i) {
+            score += 5;
+        }
+        if i == 0 || !chars[i - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= offset as i64;
+        positions.push(i);
+        start = i + 1;
+    }
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::DomainConfig;
+    use std::sync::Arc;
+
+    fn vm(name: &str, state: &str, xml: Option<&str>) -> Vm {
+        Vm {
+            host: "qemu+ssh://root@lab1/system".to_string(),
+            id: "-".to_string(),
+            name: name.to_string(),
+            state: state.to_string(),
+            config: xml.map(|xml| Arc::new(DomainConfig::parse(xml).unwrap())),
+            persistent: Some(true),
+            autostart: Some(false),
+            ips: Vec::new(),
+            drift: 0,
+        }
+    }
+
+    fn score(filter: &str, name: &str) -> Option<i64> {
+        Filter::parse(filter).matches(&vm(name, "running", None)).map(|m| m.score)
+    }
+
+    #[test]
+    fn fuzzy_ranking() {
+        assert!(score("db", "db01") > score("db", "dev-backup"));
+        assert!(score("db", "dev-backup").is_some());
+        assert_eq!(score("db", "web01"), None);
+        assert_eq!(score("DB", "db01"), score("db", "db01"));
+    }
+
+    #[test]
+    fn positions_are_sorted_and_deduplicated() {
+        let m = Filter::parse("b01 web").matches(&vm("web01", "running", None)).unwrap();
+        assert_eq!(m.positions, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn state_prefix() {
+        let shut = vm("template", "shut off", None);
+        assert!(Filter::parse("state:shut").matches(&shut).is_some());
+        assert!(Filter::parse("State:SHUT").matches(&shut).is_some());
+        assert!(Filter::parse("state:run").matches(&shut).is_none());
+        assert!(Filter::parse("temp state:shut").matches(&shut).is_some());
+    }
+
+    #[test]
+    fn unknown_key_matches_the_name() {
+        assert!(Filter::parse("foo:bar").matches(&vm("foo:bar", "running", None)).is_some());
+        assert!(Filter::parse("foo:bar").matches(&vm("fbar", "running", None)).is_none());
+    }
+
+    #[test]
+    fn net_tag_and_host() {
+        let build = vm("build", "paused", Some(include_str!("../fixtures/build-bridge-macvtap.xml")));
+        assert!(Filter::parse("net:br0").matches(&build).is_some());
+        assert!(Filter::parse("net:ENP5S0F1").matches(&build).is_some());
+        assert!(Filter::parse("net:default").matches(&build).is_none());
+        let runner = vm("runner", "running", Some(include_str!("../fixtures/tagged.xml")));
+        assert!(Filter::parse("tag:nightly").matches(&runner).is_some());
+        assert!(Filter::parse("tag:night").matches(&runner).is_none());
+        assert!(Filter::parse("host:lab").matches(&runner).is_some());
+        assert!(Filter::parse("host:lab2").matches(&runner).is_none());
+    }
+
+    #[test]
+    fn no_config_is_filtered_out_by_net_and_tag() {
+        let unknown = vm("build", "running", None);
+        assert!(Filter::parse("net:br0").matches(&unknown).is_none());
+        assert!(Filter::parse("tag:ci").matches(&unknown).is_none());
+        assert!(Filter::parse("build state:run").matches(&unknown).is_some());
+    }
+}
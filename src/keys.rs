@@ -0,0 +1,364 @@
+//! Remappable keybindings of the VM table.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Something a key in the VM table can do.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Quit,
+    Down,
+    Up,
+    /// Open or re-attach the selected VM's console.
+    Console,
+    /// Show the next open console.
+    NextConsole,
+    CloseConsole,
+    /// Open the graphical console in an external viewer.
+    Viewer,
+    Ssh,
+    Start,
+    /// Start the VM if needed, wait until SSH answers, then connect.
+    StartConnect,
+    Shutdown,
+    Reboot,
+    Reset,
+    ForceOff,
+    /// Suspend a running VM or resume a paused one.
+    Suspend,
+    Save,
+    Snapshots,
+    /// Show the domain XML.
+    Xml,
+    /// Edit the inactive XML in `$EDITOR`.
+    Edit,
+    /// Show how the live config differs from the next boot.
+    Pending,
+    /// Open the VM creation wizard.
+    Create,
+    /// Copy the selected VM and its disks.
+    Clone,
+    Sort,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    ToggleAll,
+    Connect,
+    Messages,
+}
+
+const ALL_ACTIONS: &[KeyAction] = &[
+    KeyAction::Quit,
+    KeyAction::Down,
+    KeyAction::Up,
+    KeyAction::Console,
+    KeyAction::NextConsole,
+    KeyAction::CloseConsole,
+    KeyAction::Viewer,
+    KeyAction::Ssh,
+    KeyAction::Start,
+    KeyAction::StartConnect,
+    KeyAction::Shutdown,
+    KeyAction::Reboot,
+    KeyAction::Reset,
+    KeyAction::ForceOff,
+    KeyAction::Suspend,
+    KeyAction::Save,
+    KeyAction::Snapshots,
+    KeyAction::Xml,
+    KeyAction::Edit,
+    KeyAction::Pending,
+    KeyAction::Create,
+    KeyAction::Clone,
+    KeyAction::Sort,
+    KeyAction::Search,
+    KeyAction::NextMatch,
+    KeyAction::PreviousMatch,
+    KeyAction::ToggleAll,
+    KeyAction::Connect,
+    KeyAction::Messages,
+];
+
+impl KeyAction {
+    /// Name used in the `[keys]` table of the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "quit",
+            KeyAction::Down => "down",
+            KeyAction::Up => "up",
+            KeyAction::Console => "console",
+            KeyAction::NextConsole => "next_console",
+            KeyAction::CloseConsole => "close_console",
+            KeyAction::Viewer => "viewer",
+            KeyAction::Ssh => "ssh",
+            KeyAction::Start => "start",
+            KeyAction::StartConnect => "start_connect",
+            KeyAction::Shutdown => "shutdown",
+            KeyAction::Reboot => "reboot",
+            KeyAction::Reset => "reset",
+            KeyAction::ForceOff => "force_off",
+            KeyAction::Suspend => "suspend",
+            KeyAction::Save => "save",
+            KeyAction::Snapshots => "snapshots",
+            KeyAction::Xml => "xml",
+            KeyAction::Edit => "edit",
+            KeyAction::Pending => "pending",
+            KeyAction::Create => "create",
+            KeyAction::Clone => "clone",
+            KeyAction::Sort => "sort",
+            KeyAction::Search => "search",
+            KeyAction::NextMatch => "next_match",
+            KeyAction::PreviousMatch => "previous_match",
+            KeyAction::ToggleAll => "toggle_all",
+            KeyAction::Connect => "connect",
+            KeyAction::Messages => "messages",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<KeyAction, String> {
+        ALL_ACTIONS
+            .iter()
+            .copied()
+            .find(|a| a.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = ALL_ACTIONS.iter().map(|a| a.name()).collect();
+                format!("unknown action '{name}' (expected one of {})", names.join(", "))
+            })
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            KeyAction::Quit => &["q", "Esc"],
+            KeyAction::Down => &["j", "Down"],
+            KeyAction::Up => &["k", "Up"],
+            KeyAction::Console => &["Enter"],
+            KeyAction::NextConsole => &["Tab"],
+            KeyAction::CloseConsole => &["X"],
+            KeyAction::Viewer => &["v"],
+            KeyAction::Ssh => &["s"],
+            KeyAction::Start => &["u"],
+            KeyAction::StartConnect => &["U"],
+            KeyAction::Shutdown => &["d"],
+            KeyAction::Reboot => &["r"],
+            KeyAction::Reset => &["R"],
+            KeyAction::ForceOff => &["D"],
+            KeyAction::Suspend => &["p"],
+            KeyAction::Save => &["S"],
+            KeyAction::Snapshots => &["t"],
+            KeyAction::Xml => &["x"],
+            KeyAction::Edit => &["e"],
+            KeyAction::Pending => &["P"],
+            KeyAction::Create => &["C"],
+            KeyAction::Clone => &["y"],
+            KeyAction::Sort => &["o"],
+            KeyAction::Search => &["/"],
+            KeyAction::NextMatch => &["n"],
+            KeyAction::PreviousMatch => &["N"],
+            KeyAction::ToggleAll => &["A"],
+            KeyAction::Connect => &["c"],
+            KeyAction::Messages => &["m"],
+        }
+    }
+}
+
+/// A key press; Shift is implied by the character, other modifiers but Ctrl are ignored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    ctrl: bool,
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Space", KeyCode::Char(' ')),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Del", KeyCode::Delete),
+];
+
+impl Key {
+    /// Parse e.g. `r`, `R`, `Enter`, `F5` or `Ctrl-r`. Key names are case-insensitive.
+    pub fn parse(text: &str) -> Result<Key, String> {
+        let (ctrl, name) = match text.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("ctrl-") && text.len() > 5 => (true, &text[5..]),
+            _ => (false, text),
+        };
+        let mut chars = name.chars();
+        let code = if let (Some(c), None) = (chars.next(), chars.next()) {
+            KeyCode::Char(c)
+        } else if let Some((_, code)) = NAMED_KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            *code
+        } else if let Some(n) = name.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok())
+            && (1..=12).contains(&n)
+        {
+            KeyCode::F(n)
+        } else {
+            return Err(format!("unknown key '{text}'"));
+        };
+        Ok(Key { code, ctrl })
+    }
+
+    pub fn from_event(event: &KeyEvent) -> Key {
+        Key {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+
+    /// e.g. `r`, `Enter`, `Ctrl-r`
+    pub fn label(self) -> String {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{n}"),
+            code => NAMED_KEYS
+                .iter()
+                .find(|(_, c)| *c == code)
+                .map_or("?", |(n, _)| n)
+                .to_string(),
+        };
+        if self.ctrl { format!("Ctrl-{name}") } else { name }
+    }
+}
+
+pub struct KeyMap {
+    bindings: Vec<(KeyAction, Vec<Key>)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = ALL_ACTIONS
+            .iter()
+            .map(|&action| {
+                let keys = action.default_keys().iter().filter_map(|k| Key::parse(k).ok()).collect();
+                (action, keys)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyMap {
+    /// The default bindings with the keys of some actions replaced.
+    ///
+    /// Fails if a key ends up bound to two actions.
+    pub fn with_overrides(overrides: Vec<(KeyAction, Vec<Key>)>) -> Result<Self, String> {
+        let mut map = Self::default();
+        for (action, keys) in overrides {
+            if let Some((_, bound)) = map.bindings.iter_mut().find(|(a, _)| *a == action) {
+                *bound = keys;
+            }
+        }
+        for (i, (action, keys)) in map.bindings.iter().enumerate() {
+            for key in keys {
+                if let Some((other, _)) = map.bindings[i + 1..].iter().find(|(_, k)| k.contains(key)) {
+                    return Err(format!(
+                        "key '{}' is bound to both {} and {}",
+                        key.label(),
+                        action.name(),
+                        other.name()
+                    ));
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<KeyAction> {
+        let key = Key::from_event(event);
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    /// First key bound to `action`, for hints in the UI; empty if it is unbound.
+    pub fn label(&self, action: KeyAction) -> String {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .and_then(|(_, keys)| keys.first())
+            .map(|k| k.label())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        Key::parse(text).unwrap()
+    }
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert!(key("r") == Key { code: KeyCode::Char('r'), ctrl: false });
+        assert!(key("R") == Key { code: KeyCode::Char('R'), ctrl: false });
+        assert!(key("/") == Key { code: KeyCode::Char('/'), ctrl: false });
+        assert!(key("ctrl-r") == Key { code: KeyCode::Char('r'), ctrl: true });
+        assert!(key("Ctrl-Enter") == Key { code: KeyCode::Enter, ctrl: true });
+        assert!(key("pageup") == Key { code: KeyCode::PageUp, ctrl: false });
+        assert!(key("Space") == Key { code: KeyCode::Char(' '), ctrl: false });
+        assert!(key("F12") == Key { code: KeyCode::F(12), ctrl: false });
+        assert_eq!(key("Ctrl-Del").label(), "Ctrl-Del");
+        assert_eq!(key("space").label(), "Space");
+        assert_eq!(key("ü").label(), "ü");
+    }
+
+    #[test]
+    fn bad_keys() {
+        for text in ["", "Ctrl-", "Ctrl-Ctrl-r", "F0", "F13", "Fx", "Enterr", "ab", "Ctrl-üü", "Ctrlü", "üü"] {
+            assert_eq!(Key::parse(text).err(), Some(format!("unknown key '{text}'")), "{text}");
+        }
+    }
+
+    #[test]
+    fn override_replaces_the_default() {
+        let map = KeyMap::with_overrides(vec![(KeyAction::Reboot, vec![key("Ctrl-r"), key("F5")])]).unwrap();
+        assert!(map.action(&press(KeyCode::Char('r'), KeyModifiers::CONTROL)) == Some(KeyAction::Reboot));
+        assert!(map.action(&press(KeyCode::F(5), KeyModifiers::NONE)) == Some(KeyAction::Reboot));
+        assert!(map.action(&press(KeyCode::Char('r'), KeyModifiers::NONE)).is_none());
+        assert_eq!(map.label(KeyAction::Reboot), "Ctrl-r");
+        // Shift is part of the character.
+        assert!(map.action(&press(KeyCode::Char('R'), KeyModifiers::SHIFT)) == Some(KeyAction::Reset));
+    }
+
+    #[test]
+    fn freed_default_key_can_be_reused() {
+        let map = KeyMap::with_overrides(vec![
+            (KeyAction::Reboot, vec![key("b")]),
+            (KeyAction::Reset, vec![key("r")]),
+        ])
+        .unwrap();
+        assert!(map.action(&press(KeyCode::Char('r'), KeyModifiers::NONE)) == Some(KeyAction::Reset));
+        assert_eq!(map.label(KeyAction::Reset), "r");
+    }
+
+    #[test]
+    fn conflicts_are_rejected() {
+        let error = |overrides| KeyMap::with_overrides(overrides).err().unwrap_or_default();
+        assert_eq!(
+            error(vec![(KeyAction::Reboot, vec![key("d")])]),
+            "key 'd' is bound to both shutdown and reboot"
+        );
+        assert_eq!(
+            error(vec![(KeyAction::Xml, vec![key("F2")]), (KeyAction::Edit, vec![key("f2")])]),
+            "key 'F2' is bound to both xml and edit"
+        );
+        assert!(KeyMap::with_overrides(vec![(KeyAction::Reboot, vec![key("Ctrl-d")])]).is_ok());
+    }
+}
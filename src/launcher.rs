@@ -0,0 +1,169 @@
+//! Where SSH sessions and consoles run: in place of the TUI, or in another window.
+
+use std::process::{Command, Stdio};
+use std::thread;
+
+use log::info;
+
+/// How interactive sessions are started; see the `launcher` setting.
+#[derive(Clone)]
+pub enum Launcher {
+    /// SSH takes over the terminal until it exits; consoles use the embedded pane.
+    Suspend,
+    TmuxWindow,
+    TmuxSplit,
+    ScreenWindow,
+    /// Terminal emulator command with `{title}` and `{command}` placeholders.
+    Terminal(Vec<String>),
+}
+
+impl Launcher {
+    /// Parse a `launcher` setting; `auto` picks the multiplexer yalv-rust runs in, if any.
+    pub fn parse(text: &str) -> Result<Launcher, String> {
+        match text {
+            "auto" => Ok(Self::detect()),
+            "suspend" => Ok(Launcher::Suspend),
+            "tmux-window" => Ok(Launcher::TmuxWindow),
+            "tmux-split" => Ok(Launcher::TmuxSplit),
+            "screen-window" => Ok(Launcher::ScreenWindow),
+            _ => {
+                let words = crate::viewer::parse_command(text)?;
+                if !words.iter().any(|w| w == "{command}") {
+                    return Err(format!(
+                        "'{text}' is not auto, suspend, tmux-window, tmux-split or screen-window, \
+                         and as a terminal command it needs a {{command}} argument"
+                    ));
+                }
+                Ok(Launcher::Terminal(words))
+            }
+        }
+    }
+
+    /// A window of the tmux or screen session yalv-rust runs in, else `Suspend`;
+    /// for `launcher = "auto"`, the default.
+    pub fn detect() -> Launcher {
+        Self::detect_with(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+    }
+
+    /// `detect` with `set` telling whether an environment variable is set.
+    fn detect_with(set: impl Fn(&str) -> bool) -> Launcher {
+        if set("TMUX") {
+            Launcher::TmuxWindow
+        } else if set("STY") {
+            Launcher::ScreenWindow
+        } else {
+            Launcher::Suspend
+        }
+    }
+
+    /// e.g. `a tmux window`, for status messages.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Launcher::Suspend => "the terminal",
+            Launcher::TmuxWindow => "a tmux window",
+            Launcher::TmuxSplit => "a tmux pane",
+            Launcher::ScreenWindow => "a screen window",
+            Launcher::Terminal(_) => "a new terminal",
+        }
+    }
+
+    /// Start `command` in a window titled `title`. The session is not waited for,
+    /// but tmux and screen are, so call this off the UI thread.
+    ///
+    /// Not for `Suspend`, which the caller handles by leaving the TUI.
+    pub fn open(&self, title: &str, command: &[String]) -> Result<(), String> {
+        info!("Opening '{title}' in {}: {}", self.describe(), command.join(" "));
+        match self {
+            Launcher::Suspend => Err("no window to open in".to_string()),
+            Launcher::TmuxWindow => run(Command::new("tmux").args(["new-window", "-n", title]).args(command)).map(|_| ()),
+            Launcher::TmuxSplit => {
+                let pane = run(Command::new("tmux")
+                    .args(["split-window", "-h", "-P", "-F", "#{pane_id}"])
+                    .args(command))?;
+                run(Command::new("tmux").args(["select-pane", "-t", pane.trim(), "-T", title])).map(|_| ())
+            }
+            Launcher::ScreenWindow => run(Command::new("screen").args(["-X", "screen", "-t", title]).args(command)).map(|_| ()),
+            Launcher::Terminal(template) => {
+                let args = terminal_args(template, title, command);
+                let mut child = Command::new(&args[0])
+                    .args(&args[1..])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("cannot run {}: {e}", args[0]))?;
+                let title = title.to_string();
+                thread::spawn(move || match child.wait() {
+                    Ok(status) => info!("Terminal for '{title}' exited with {status}"),
+                    Err(e) => info!("Waiting for terminal of '{title}' failed: {e}"),
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The terminal `template` with `{title}` filled in and `{command}` replaced by its words.
+fn terminal_args(template: &[String], title: &str, command: &[String]) -> Vec<String> {
+    let mut args = Vec::new();
+    for word in template {
+        if word == "{command}" {
+            args.extend(command.iter().cloned());
+        } else {
+            args.push(word.replace("{title}", title));
+        }
+    }
+    args
+}
+
+/// Run a multiplexer command and return its stdout.
+fn run(command: &mut Command) -> Result<String, String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("cannot run {program}: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{program} failed: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_the_multiplexer() {
+        let detect = |vars: &[&str]| Launcher::detect_with(|var| vars.contains(&var));
+        assert!(matches!(detect(&["TMUX"]), Launcher::TmuxWindow));
+        assert!(matches!(detect(&["STY"]), Launcher::ScreenWindow));
+        assert!(matches!(detect(&["TMUX", "STY"]), Launcher::TmuxWindow));
+        assert!(matches!(detect(&[]), Launcher::Suspend));
+    }
+
+    #[test]
+    fn parse_settings() {
+        assert!(matches!(Launcher::parse("suspend"), Ok(Launcher::Suspend)));
+        assert!(matches!(Launcher::parse("tmux-split"), Ok(Launcher::TmuxSplit)));
+        let Ok(Launcher::Terminal(words)) = Launcher::parse("foot --title '{title} VM' {command}") else {
+            panic!("not a terminal command");
+        };
+        assert_eq!(words, ["foot", "--title", "{title} VM", "{command}"]);
+        // `{command}` must be a word of its own.
+        assert!(Launcher::parse("xterm -e 'sh -c {command}'").is_err());
+        assert!(Launcher::parse("xterm -e {command}x").is_err());
+        assert!(Launcher::parse("").is_err());
+    }
+
+    #[test]
+    fn terminal_template() {
+        let template = crate::viewer::parse_command("alacritty --title {title} --class yalv-{title} -e {command}").unwrap();
+        let command = ["ssh", "-p", "2222", "admin@10.0.0.5"].map(str::to_string);
+        assert_eq!(
+            terminal_args(&template, "ssh:web01", &command),
+            ["alacritty", "--title", "ssh:web01", "--class", "yalv-ssh:web01", "-e", "ssh", "-p", "2222", "admin@10.0.0.5"]
+        );
+    }
+}
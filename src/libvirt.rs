@@ -0,0 +1,969 @@
+//! Backend talking to libvirt through its C API instead of forking `virsh`.
+//!
+//! Enabled with the `libvirt` cargo feature; requires `libvirt.so` at link time.
+
+use std::ffi::{CStr, CString, c_char, c_int, c_uint, c_ulonglong, c_void};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::ptr;
+
+use log::{info, warn};
+
+use crate::Action;
+use crate::backend::{Backend, Domain, DomainStats, IfAddr, NewVolume, Snapshot, VolumeInfo};
+use crate::domain::{self, escape_xml};
+
+#[repr(C)]
+struct VirConnect {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VirDomain {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VirDomainSnapshot {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VirStoragePool {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VirStorageVol {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VirStream {
+    _private: [u8; 0],
+}
+
+/// `virStorageVolInfo`
+#[repr(C)]
+struct VirStorageVolInfo {
+    kind: c_int,
+    capacity: c_ulonglong,
+    allocation: c_ulonglong,
+}
+
+#[repr(C)]
+union VirTypedParameterValue {
+    i: c_int,
+    ui: c_uint,
+    l: i64,
+    ul: u64,
+    d: f64,
+    b: c_char,
+    s: *mut c_char,
+}
+
+#[repr(C)]
+struct VirTypedParameter {
+    field: [c_char; 80],
+    kind: c_int,
+    value: VirTypedParameterValue,
+}
+
+#[repr(C)]
+struct VirDomainStatsRecord {
+    dom: *mut VirDomain,
+    params: *mut VirTypedParameter,
+    nparams: c_int,
+}
+
+#[repr(C)]
+struct VirDomainIpAddress {
+    kind: c_int,
+    addr: *mut c_char,
+    _prefix: c_uint,
+}
+
+#[repr(C)]
+struct VirDomainInterface {
+    name: *mut c_char,
+    hwaddr: *mut c_char,
+    naddrs: c_uint,
+    addrs: *mut VirDomainIpAddress,
+}
+
+const VIR_CONNECT_LIST_DOMAINS_ACTIVE: c_uint = 1;
+const VIR_CONNECT_LIST_DOMAINS_INACTIVE: c_uint = 2;
+
+const VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_LEASE: c_uint = 0;
+const VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_AGENT: c_uint = 1;
+const VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_ARP: c_uint = 2;
+
+const VIR_IP_ADDR_TYPE_IPV4: c_int = 0;
+
+const VIR_DOMAIN_SHUTOFF: c_int = 5;
+
+const VIR_DOMAIN_XML_INACTIVE: c_uint = 1 << 1;
+const VIR_DOMAIN_DEFINE_VALIDATE: c_uint = 1;
+
+const VIR_DOMAIN_STATS_CPU_TOTAL: c_uint = 1 << 1;
+const VIR_DOMAIN_STATS_BALLOON: c_uint = 1 << 2;
+const VIR_DOMAIN_STATS_VCPU: c_uint = 1 << 3;
+const VIR_DOMAIN_STATS_INTERFACE: c_uint = 1 << 4;
+const VIR_DOMAIN_STATS_BLOCK: c_uint = 1 << 5;
+const VIR_CONNECT_GET_ALL_DOMAINS_STATS_ACTIVE: c_uint = 1;
+
+const VIR_TYPED_PARAM_INT: c_int = 1;
+const VIR_TYPED_PARAM_UINT: c_int = 2;
+const VIR_TYPED_PARAM_LLONG: c_int = 3;
+const VIR_TYPED_PARAM_ULLONG: c_int = 4;
+
+const VIR_DOMAIN_SNAPSHOT_CREATE_DISK_ONLY: c_uint = 1 << 4;
+const VIR_DOMAIN_SNAPSHOT_CREATE_ATOMIC: c_uint = 1 << 7;
+
+#[link(name = "virt")]
+unsafe extern "C" {
+    fn virConnectOpen(name: *const c_char) -> *mut VirConnect;
+    fn virConnectClose(conn: *mut VirConnect) -> c_int;
+    fn virConnectGetURI(conn: *mut VirConnect) -> *mut c_char;
+    fn virConnectListAllDomains(
+        conn: *mut VirConnect,
+        domains: *mut *mut *mut VirDomain,
+        flags: c_uint,
+    ) -> c_int;
+    fn virDomainLookupByName(conn: *mut VirConnect, name: *const c_char) -> *mut VirDomain;
+    fn virDomainFree(domain: *mut VirDomain) -> c_int;
+    fn virDomainGetName(domain: *mut VirDomain) -> *const c_char;
+    fn virDomainGetID(domain: *mut VirDomain) -> c_uint;
+    fn virDomainGetUUIDString(domain: *mut VirDomain, buf: *mut c_char) -> c_int;
+    fn virDomainGetState(
+        domain: *mut VirDomain,
+        state: *mut c_int,
+        reason: *mut c_int,
+        flags: c_uint,
+    ) -> c_int;
+    fn virDomainIsPersistent(domain: *mut VirDomain) -> c_int;
+    fn virDomainGetAutostart(domain: *mut VirDomain, autostart: *mut c_int) -> c_int;
+    fn virDomainGetXMLDesc(domain: *mut VirDomain, flags: c_uint) -> *mut c_char;
+    fn virDomainDefineXMLFlags(conn: *mut VirConnect, xml: *const c_char, flags: c_uint) -> *mut VirDomain;
+    fn virDomainInterfaceAddresses(
+        domain: *mut VirDomain,
+        ifaces: *mut *mut *mut VirDomainInterface,
+        source: c_uint,
+        flags: c_uint,
+    ) -> c_int;
+    fn virDomainInterfaceFree(iface: *mut VirDomainInterface);
+    fn virDomainCreate(domain: *mut VirDomain) -> c_int;
+    fn virDomainShutdown(domain: *mut VirDomain) -> c_int;
+    fn virDomainReboot(domain: *mut VirDomain, flags: c_uint) -> c_int;
+    fn virDomainReset(domain: *mut VirDomain, flags: c_uint) -> c_int;
+    fn virDomainDestroy(domain: *mut VirDomain) -> c_int;
+    fn virDomainSuspend(domain: *mut VirDomain) -> c_int;
+    fn virDomainResume(domain: *mut VirDomain) -> c_int;
+    fn virDomainManagedSave(domain: *mut VirDomain, flags: c_uint) -> c_int;
+    fn virDomainHasManagedSaveImage(domain: *mut VirDomain, flags: c_uint) -> c_int;
+    fn virDomainListAllSnapshots(
+        domain: *mut VirDomain,
+        snaps: *mut *mut *mut VirDomainSnapshot,
+        flags: c_uint,
+    ) -> c_int;
+    fn virDomainSnapshotLookupByName(
+        domain: *mut VirDomain,
+        name: *const c_char,
+        flags: c_uint,
+    ) -> *mut VirDomainSnapshot;
+    fn virDomainSnapshotCreateXML(
+        domain: *mut VirDomain,
+        xml: *const c_char,
+        flags: c_uint,
+    ) -> *mut VirDomainSnapshot;
+    fn virDomainSnapshotGetName(snapshot: *mut VirDomainSnapshot) -> *const c_char;
+    fn virDomainSnapshotGetParent(
+        snapshot: *mut VirDomainSnapshot,
+        flags: c_uint,
+    ) -> *mut VirDomainSnapshot;
+    fn virDomainSnapshotIsCurrent(snapshot: *mut VirDomainSnapshot, flags: c_uint) -> c_int;
+    fn virDomainSnapshotGetXMLDesc(snapshot: *mut VirDomainSnapshot, flags: c_uint) -> *mut c_char;
+    fn virDomainRevertToSnapshot(snapshot: *mut VirDomainSnapshot, flags: c_uint) -> c_int;
+    fn virDomainSnapshotDelete(snapshot: *mut VirDomainSnapshot, flags: c_uint) -> c_int;
+    fn virDomainSnapshotFree(snapshot: *mut VirDomainSnapshot) -> c_int;
+    fn virConnectGetAllDomainStats(
+        conn: *mut VirConnect,
+        stats: c_uint,
+        records: *mut *mut *mut VirDomainStatsRecord,
+        flags: c_uint,
+    ) -> c_int;
+    fn virDomainStatsRecordListFree(records: *mut *mut VirDomainStatsRecord);
+    fn virStoragePoolLookupByName(conn: *mut VirConnect, name: *const c_char) -> *mut VirStoragePool;
+    fn virStoragePoolFree(pool: *mut VirStoragePool) -> c_int;
+    fn virStorageVolCreateXML(pool: *mut VirStoragePool, xml: *const c_char, flags: c_uint) -> *mut VirStorageVol;
+    fn virStorageVolLookupByName(pool: *mut VirStoragePool, name: *const c_char) -> *mut VirStorageVol;
+    fn virStorageVolLookupByPath(conn: *mut VirConnect, path: *const c_char) -> *mut VirStorageVol;
+    fn virStorageVolCreateXMLFrom(
+        pool: *mut VirStoragePool,
+        xml: *const c_char,
+        clonevol: *mut VirStorageVol,
+        flags: c_uint,
+    ) -> *mut VirStorageVol;
+    fn virStorageVolGetName(vol: *mut VirStorageVol) -> *const c_char;
+    fn virStorageVolGetPath(vol: *mut VirStorageVol) -> *mut c_char;
+    fn virStorageVolGetInfo(vol: *mut VirStorageVol, info: *mut VirStorageVolInfo) -> c_int;
+    fn virStorageVolGetXMLDesc(vol: *mut VirStorageVol, flags: c_uint) -> *mut c_char;
+    fn virStoragePoolLookupByVolume(vol: *mut VirStorageVol) -> *mut VirStoragePool;
+    fn virStoragePoolGetName(pool: *mut VirStoragePool) -> *const c_char;
+    fn virStorageVolDelete(vol: *mut VirStorageVol, flags: c_uint) -> c_int;
+    fn virStorageVolFree(vol: *mut VirStorageVol) -> c_int;
+    fn virStorageVolUpload(
+        vol: *mut VirStorageVol,
+        stream: *mut VirStream,
+        offset: c_ulonglong,
+        length: c_ulonglong,
+        flags: c_uint,
+    ) -> c_int;
+    fn virStreamNew(conn: *mut VirConnect, flags: c_uint) -> *mut VirStream;
+    fn virStreamSend(stream: *mut VirStream, data: *const c_char, nbytes: usize) -> c_int;
+    fn virStreamFinish(stream: *mut VirStream) -> c_int;
+    fn virStreamAbort(stream: *mut VirStream) -> c_int;
+    fn virStreamFree(stream: *mut VirStream) -> c_int;
+    fn virGetLastErrorMessage() -> *const c_char;
+    fn free(ptr: *mut c_void);
+}
+
+fn last_error() -> String {
+    // SAFETY: virGetLastErrorMessage always returns a valid, static-lifetime string.
+    unsafe { CStr::from_ptr(virGetLastErrorMessage()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Map a `virDomainState` value to the label `virsh list` prints.
+fn state_label(state: c_int) -> &'static str {
+    match state {
+        1 => "running",
+        2 => "idle",
+        3 => "paused",
+        4 => "in shutdown",
+        VIR_DOMAIN_SHUTOFF => "shut off",
+        6 => "crashed",
+        7 => "pmsuspended",
+        _ => "no state",
+    }
+}
+
+/// Owned `virDomainPtr`, freed on drop.
+struct DomainHandle(*mut VirDomain);
+
+impl DomainHandle {
+    fn name(&self) -> String {
+        // SAFETY: the handle is valid and the returned name lives as long as it.
+        unsafe { CStr::from_ptr(virDomainGetName(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn uuid(&self) -> String {
+        // VIR_UUID_STRING_BUFLEN
+        let mut buf = [0 as c_char; 37];
+        // SAFETY: the handle is valid and `buf` has room for the NUL-terminated UUID.
+        if unsafe { virDomainGetUUIDString(self.0, buf.as_mut_ptr()) } < 0 {
+            return String::new();
+        }
+        // SAFETY: libvirt NUL-terminated the buffer.
+        unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn id(&self) -> Option<u32> {
+        // SAFETY: the handle is valid.
+        let id = unsafe { virDomainGetID(self.0) };
+        (id != c_uint::MAX).then_some(id)
+    }
+
+    fn persistent(&self) -> Option<bool> {
+        // SAFETY: the handle is valid.
+        let rc = unsafe { virDomainIsPersistent(self.0) };
+        (rc >= 0).then_some(rc == 1)
+    }
+
+    fn autostart(&self) -> Option<bool> {
+        let mut autostart: c_int = 0;
+        // SAFETY: the handle is valid and `autostart` outlives the call.
+        let rc = unsafe { virDomainGetAutostart(self.0, &mut autostart) };
+        (rc >= 0).then_some(autostart != 0)
+    }
+
+    fn state(&self) -> Result<&'static str, String> {
+        let mut state: c_int = 0;
+        // SAFETY: the handle is valid and `state` outlives the call.
+        let rc = unsafe { virDomainGetState(self.0, &mut state, ptr::null_mut(), 0) };
+        if rc < 0 {
+            return Err(last_error());
+        }
+        if state == VIR_DOMAIN_SHUTOFF && self.has_managed_save() {
+            return Ok("saved");
+        }
+        Ok(state_label(state))
+    }
+
+    fn has_managed_save(&self) -> bool {
+        // SAFETY: the handle is valid.
+        unsafe { virDomainHasManagedSaveImage(self.0, 0) > 0 }
+    }
+}
+
+impl Drop for DomainHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was obtained from libvirt and is freed exactly once.
+        unsafe {
+            virDomainFree(self.0);
+        }
+    }
+}
+
+/// Owned `virDomainSnapshotPtr`, freed on drop.
+struct SnapshotHandle(*mut VirDomainSnapshot);
+
+impl SnapshotHandle {
+    fn name(&self) -> String {
+        // SAFETY: the handle is valid and the returned name lives as long as it.
+        unsafe { CStr::from_ptr(virDomainSnapshotGetName(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn parent(&self) -> Option<String> {
+        // SAFETY: the handle is valid; a null result means there is no parent.
+        let parent = unsafe { virDomainSnapshotGetParent(self.0, 0) };
+        (!parent.is_null()).then(|| SnapshotHandle(parent).name())
+    }
+
+    fn is_current(&self) -> bool {
+        // SAFETY: the handle is valid.
+        unsafe { virDomainSnapshotIsCurrent(self.0, 0) == 1 }
+    }
+
+    fn xml(&self) -> Result<String, String> {
+        // SAFETY: the handle is valid; the returned string is ours to free.
+        unsafe {
+            let xml = virDomainSnapshotGetXMLDesc(self.0, 0);
+            if xml.is_null() {
+                return Err(last_error());
+            }
+            let text = CStr::from_ptr(xml).to_string_lossy().into_owned();
+            free(xml.cast());
+            Ok(text)
+        }
+    }
+
+    fn snapshot(&self) -> Result<Snapshot, String> {
+        let xml = self.xml()?;
+        let root = domain::parse_tree(&xml).map_err(|e| e.to_string())?;
+        let creation_time = root
+            .child("creationTime")
+            .and_then(|c| c.text())
+            .and_then(|t| t.parse::<i64>().ok())
+            .and_then(|t| time::OffsetDateTime::from_unix_timestamp(t).ok())
+            .map(|t| {
+                format!(
+                    "{} {:02}:{:02}:{:02} +0000",
+                    t.date(),
+                    t.hour(),
+                    t.minute(),
+                    t.second()
+                )
+            })
+            .unwrap_or_default();
+        Ok(Snapshot {
+            name: self.name(),
+            parent: self.parent(),
+            creation_time,
+            state: root
+                .child("state")
+                .and_then(|c| c.text())
+                .unwrap_or_default()
+                .to_string(),
+            current: self.is_current(),
+        })
+    }
+}
+
+impl Drop for SnapshotHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was obtained from libvirt and is freed exactly once.
+        unsafe {
+            virDomainSnapshotFree(self.0);
+        }
+    }
+}
+
+/// Owned `virStoragePoolPtr`, freed on drop.
+struct PoolHandle(*mut VirStoragePool);
+
+impl PoolHandle {
+    fn name(&self) -> String {
+        // SAFETY: the handle is valid and the returned name lives as long as it.
+        unsafe { CStr::from_ptr(virStoragePoolGetName(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn volume(&self, name: &str) -> Result<VolumeHandle, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: the handle is valid and `c_name` is NUL-terminated.
+        let vol = unsafe { virStorageVolLookupByName(self.0, c_name.as_ptr()) };
+        if vol.is_null() {
+            return Err(last_error());
+        }
+        Ok(VolumeHandle(vol))
+    }
+}
+
+impl Drop for PoolHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was obtained from libvirt and is freed exactly once.
+        unsafe {
+            virStoragePoolFree(self.0);
+        }
+    }
+}
+
+/// Owned `virStorageVolPtr`, freed on drop.
+struct VolumeHandle(*mut VirStorageVol);
+
+impl VolumeHandle {
+    fn name(&self) -> String {
+        // SAFETY: the handle is valid and the returned name lives as long as it.
+        unsafe { CStr::from_ptr(virStorageVolGetName(self.0)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn path(&self) -> Result<String, String> {
+        // SAFETY: the handle is valid; the returned string is ours to free.
+        unsafe {
+            let raw = virStorageVolGetPath(self.0);
+            if raw.is_null() {
+                return Err(last_error());
+            }
+            let path = CStr::from_ptr(raw).to_string_lossy().into_owned();
+            free(raw.cast());
+            Ok(path)
+        }
+    }
+
+    fn info(&self) -> Result<VolumeInfo, String> {
+        let mut info = VirStorageVolInfo {
+            kind: 0,
+            capacity: 0,
+            allocation: 0,
+        };
+        // SAFETY: the handle is valid and `info` is a properly sized out-parameter.
+        if unsafe { virStorageVolGetInfo(self.0, &mut info) } < 0 {
+            return Err(last_error());
+        }
+        Ok(VolumeInfo {
+            capacity: info.capacity,
+            allocation: info.allocation,
+        })
+    }
+
+    fn xml(&self) -> Result<String, String> {
+        // SAFETY: the handle is valid; the returned string is ours to free.
+        unsafe {
+            let raw = virStorageVolGetXMLDesc(self.0, 0);
+            if raw.is_null() {
+                return Err(last_error());
+            }
+            let xml = CStr::from_ptr(raw).to_string_lossy().into_owned();
+            free(raw.cast());
+            Ok(xml)
+        }
+    }
+
+    fn pool(&self) -> Result<PoolHandle, String> {
+        // SAFETY: the handle is valid.
+        let pool = unsafe { virStoragePoolLookupByVolume(self.0) };
+        if pool.is_null() {
+            return Err(last_error());
+        }
+        Ok(PoolHandle(pool))
+    }
+
+    fn delete(&self) -> Result<(), String> {
+        // SAFETY: the handle is valid.
+        if unsafe { virStorageVolDelete(self.0, 0) } < 0 {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VolumeHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was obtained from libvirt and is freed exactly once.
+        unsafe {
+            virStorageVolFree(self.0);
+        }
+    }
+}
+
+/// Owned `virStreamPtr`, freed on drop.
+struct StreamHandle(*mut VirStream);
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle was obtained from libvirt and is freed exactly once.
+        unsafe {
+            virStreamFree(self.0);
+        }
+    }
+}
+
+/// Backend holding one persistent libvirt connection.
+pub struct LibvirtBackend {
+    conn: *mut VirConnect,
+    uri: String,
+}
+
+// libvirt connection objects are safe to share between threads.
+unsafe impl Send for LibvirtBackend {}
+unsafe impl Sync for LibvirtBackend {}
+
+impl LibvirtBackend {
+    /// Open a connection to `uri`, or libvirt's default URI when `None`.
+    pub fn open(uri: Option<&str>) -> Result<Self, String> {
+        let c_uri = uri
+            .map(CString::new)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        // SAFETY: a null URI selects libvirt's default connection.
+        let conn = unsafe { virConnectOpen(c_uri.as_ref().map_or(ptr::null(), |u| u.as_ptr())) };
+        if conn.is_null() {
+            return Err(format!("unable to connect to libvirt: {}", last_error()));
+        }
+        // SAFETY: the connection is open; the returned string is ours to free.
+        let uri = unsafe {
+            let raw = virConnectGetURI(conn);
+            if raw.is_null() {
+                uri.unwrap_or_default().to_string()
+            } else {
+                let text = CStr::from_ptr(raw).to_string_lossy().into_owned();
+                free(raw.cast());
+                text
+            }
+        };
+        info!("Opened native libvirt connection to {uri}");
+        Ok(Self { conn, uri })
+    }
+
+    fn lookup(&self, name: &str) -> Result<DomainHandle, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: the connection is open and `c_name` is NUL-terminated.
+        let dom = unsafe { virDomainLookupByName(self.conn, c_name.as_ptr()) };
+        if dom.is_null() {
+            return Err(last_error());
+        }
+        Ok(DomainHandle(dom))
+    }
+
+    fn lookup_pool(&self, name: &str) -> Result<PoolHandle, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: the connection is open and `c_name` is NUL-terminated.
+        let pool = unsafe { virStoragePoolLookupByName(self.conn, c_name.as_ptr()) };
+        if pool.is_null() {
+            return Err(last_error());
+        }
+        Ok(PoolHandle(pool))
+    }
+
+    /// Send the contents of `file` to the new volume `vol`.
+    fn upload(&self, vol: &VolumeHandle, file: &Path) -> Result<(), String> {
+        let mut file = File::open(file).map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+        // SAFETY: the connection is open.
+        let stream = unsafe { virStreamNew(self.conn, 0) };
+        if stream.is_null() {
+            return Err(last_error());
+        }
+        let stream = StreamHandle(stream);
+        // SAFETY: both handles are valid; a length of 0 uploads until the stream finishes.
+        if unsafe { virStorageVolUpload(vol.0, stream.0, 0, 0, 0) } < 0 {
+            return Err(last_error());
+        }
+        let mut buf = vec![0u8; 256 * 1024];
+        loop {
+            let read = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    // SAFETY: the stream is valid and is not used after aborting.
+                    unsafe { virStreamAbort(stream.0) };
+                    return Err(format!("cannot read the upload: {e}"));
+                }
+            };
+            let mut sent = 0;
+            while sent < read {
+                // SAFETY: the stream is valid and `buf[sent..read]` is initialized.
+                let rc = unsafe { virStreamSend(stream.0, buf[sent..].as_ptr().cast(), read - sent) };
+                if rc < 0 {
+                    let e = last_error();
+                    // SAFETY: the stream is valid and is not used after aborting.
+                    unsafe { virStreamAbort(stream.0) };
+                    return Err(e);
+                }
+                sent += rc as usize;
+            }
+        }
+        // SAFETY: the stream is valid.
+        if unsafe { virStreamFinish(stream.0) } < 0 {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+
+    fn lookup_snapshot(dom: &DomainHandle, name: &str) -> Result<SnapshotHandle, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        // SAFETY: the handle is valid and `c_name` is NUL-terminated.
+        let snap = unsafe { virDomainSnapshotLookupByName(dom.0, c_name.as_ptr(), 0) };
+        if snap.is_null() {
+            return Err(last_error());
+        }
+        Ok(SnapshotHandle(snap))
+    }
+}
+
+impl Drop for LibvirtBackend {
+    fn drop(&mut self) {
+        // SAFETY: the connection was opened in `open` and is closed exactly once.
+        unsafe {
+            virConnectClose(self.conn);
+        }
+    }
+}
+
+impl Backend for LibvirtBackend {
+    fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    fn list_domains(&self, show_all: bool) -> Result<Vec<Domain>, String> {
+        let mut flags = VIR_CONNECT_LIST_DOMAINS_ACTIVE;
+        if show_all {
+            flags |= VIR_CONNECT_LIST_DOMAINS_INACTIVE;
+        }
+        let mut raw: *mut *mut VirDomain = ptr::null_mut();
+        // SAFETY: the connection is open; libvirt allocates `raw`.
+        let count = unsafe { virConnectListAllDomains(self.conn, &mut raw, flags) };
+        if count < 0 {
+            return Err(format!("unable to list domains: {}", last_error()));
+        }
+        // SAFETY: libvirt returned `count` valid domain pointers in `raw`,
+        // each of which is now owned by a `DomainHandle`.
+        let handles: Vec<DomainHandle> = unsafe {
+            let handles = (0..count as usize)
+                .map(|i| DomainHandle(*raw.add(i)))
+                .collect();
+            free(raw.cast());
+            handles
+        };
+
+        let mut domains = Vec::with_capacity(handles.len());
+        for handle in &handles {
+            let id = handle.id();
+            domains.push((
+                id,
+                Domain {
+                    uuid: handle.uuid(),
+                    id: id.map_or_else(|| "-".to_string(), |id| id.to_string()),
+                    name: handle.name(),
+                    state: handle.state()?.to_string(),
+                    persistent: handle.persistent(),
+                    autostart: handle.autostart(),
+                },
+            ));
+        }
+        // Same order as `virsh list`: running domains by id, then inactive by name.
+        domains.sort_by(|(a_id, a), (b_id, b)| match (a_id, b_id) {
+            (Some(a_id), Some(b_id)) => a_id.cmp(b_id),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a.name.cmp(&b.name),
+        });
+        Ok(domains.into_iter().map(|(_, d)| d).collect())
+    }
+
+    fn dumpxml(&self, name: &str, inactive: bool) -> Result<String, String> {
+        let dom = self.lookup(name)?;
+        // SAFETY: the handle is valid; the returned string is ours to free.
+        unsafe {
+            let xml = virDomainGetXMLDesc(dom.0, if inactive { VIR_DOMAIN_XML_INACTIVE } else { 0 });
+            if xml.is_null() {
+                return Err(last_error());
+            }
+            let text = CStr::from_ptr(xml).to_string_lossy().into_owned();
+            free(xml.cast());
+            Ok(text)
+        }
+    }
+
+    fn define(&self, xml: &str) -> Result<(), String> {
+        let c_xml = CString::new(xml).map_err(|e| e.to_string())?;
+        // SAFETY: the connection is open and `c_xml` is NUL-terminated.
+        let dom = unsafe { virDomainDefineXMLFlags(self.conn, c_xml.as_ptr(), VIR_DOMAIN_DEFINE_VALIDATE) };
+        if dom.is_null() {
+            return Err(last_error());
+        }
+        let dom = DomainHandle(dom);
+        info!("Defined domain '{}'", dom.name());
+        Ok(())
+    }
+
+    fn domifaddr(&self, name: &str, source: &str) -> Result<Vec<IfAddr>, String> {
+        let source = match source {
+            "lease" => VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_LEASE,
+            "agent" => VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_AGENT,
+            "arp" => VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_ARP,
+            other => return Err(format!("unknown address source '{other}'")),
+        };
+        let dom = self.lookup(name)?;
+        let mut ifaces: *mut *mut VirDomainInterface = ptr::null_mut();
+        // SAFETY: the handle is valid; libvirt allocates `ifaces`.
+        let count = unsafe { virDomainInterfaceAddresses(dom.0, &mut ifaces, source, 0) };
+        if count < 0 {
+            return Err(last_error());
+        }
+        let mut addrs: Vec<IfAddr> = Vec::new();
+        // SAFETY: libvirt returned `count` interfaces, each with `naddrs` addresses.
+        unsafe {
+            for i in 0..count as usize {
+                let iface = *ifaces.add(i);
+                let text = |p: *mut c_char| (!p.is_null()).then(|| CStr::from_ptr(p).to_string_lossy().into_owned());
+                let interface = text((*iface).name).unwrap_or_default();
+                let mac = text((*iface).hwaddr);
+                for j in 0..(*iface).naddrs as usize {
+                    let addr = &*(*iface).addrs.add(j);
+                    if addr.kind == VIR_IP_ADDR_TYPE_IPV4 && !addr.addr.is_null() {
+                        let ip = CStr::from_ptr(addr.addr).to_string_lossy().into_owned();
+                        if !addrs.iter().any(|a| a.ip == ip) {
+                            addrs.push(IfAddr {
+                                interface: interface.clone(),
+                                mac: mac.clone(),
+                                ip,
+                            });
+                        }
+                    }
+                }
+                virDomainInterfaceFree(iface);
+            }
+            free(ifaces.cast());
+        }
+        Ok(addrs)
+    }
+
+    fn lifecycle(&self, name: &str, action: &Action) -> Result<(), String> {
+        let dom = self.lookup(name)?;
+        // SAFETY: the handle is valid.
+        let rc = unsafe {
+            match action {
+                Action::CreateSnapshot(spec) => {
+                    let xml = format!(
+                        "<domainsnapshot><name>{}</name><description>{}</description></domainsnapshot>",
+                        escape_xml(&spec.name),
+                        escape_xml(&spec.description)
+                    );
+                    let xml = CString::new(xml).map_err(|e| e.to_string())?;
+                    let flags = if spec.disk_only {
+                        VIR_DOMAIN_SNAPSHOT_CREATE_DISK_ONLY | VIR_DOMAIN_SNAPSHOT_CREATE_ATOMIC
+                    } else {
+                        0
+                    };
+                    let snap = virDomainSnapshotCreateXML(dom.0, xml.as_ptr(), flags);
+                    if snap.is_null() {
+                        -1
+                    } else {
+                        drop(SnapshotHandle(snap));
+                        0
+                    }
+                }
+                Action::RevertSnapshot(snapshot) => {
+                    virDomainRevertToSnapshot(Self::lookup_snapshot(&dom, snapshot)?.0, 0)
+                }
+                Action::DeleteSnapshot(snapshot) => {
+                    virDomainSnapshotDelete(Self::lookup_snapshot(&dom, snapshot)?.0, 0)
+                }
+                // Creating a domain with a managed save image restores it.
+                Action::Start | Action::Restore => virDomainCreate(dom.0),
+                Action::Shutdown => virDomainShutdown(dom.0),
+                Action::Reboot => virDomainReboot(dom.0, 0),
+                Action::Reset => virDomainReset(dom.0, 0),
+                Action::Destroy => virDomainDestroy(dom.0),
+                Action::Suspend => virDomainSuspend(dom.0),
+                Action::Resume => virDomainResume(dom.0),
+                Action::ManagedSave => virDomainManagedSave(dom.0, 0),
+            }
+        };
+        if rc < 0 {
+            return Err(last_error());
+        }
+        Ok(())
+    }
+
+    fn snapshots(&self, name: &str) -> Result<Vec<Snapshot>, String> {
+        let dom = self.lookup(name)?;
+        let mut raw: *mut *mut VirDomainSnapshot = ptr::null_mut();
+        // SAFETY: the handle is valid; libvirt allocates `raw`.
+        let count = unsafe { virDomainListAllSnapshots(dom.0, &mut raw, 0) };
+        if count < 0 {
+            return Err(format!("unable to list snapshots: {}", last_error()));
+        }
+        // SAFETY: libvirt returned `count` valid snapshot pointers in `raw`,
+        // each of which is now owned by a `SnapshotHandle`.
+        let handles: Vec<SnapshotHandle> = unsafe {
+            let handles = (0..count as usize)
+                .map(|i| SnapshotHandle(*raw.add(i)))
+                .collect();
+            free(raw.cast());
+            handles
+        };
+        handles.iter().map(SnapshotHandle::snapshot).collect()
+    }
+
+    fn create_volume(&self, volume: &NewVolume) -> Result<(), String> {
+        let pool = self.lookup_pool(&volume.pool)?;
+        let backing = volume.backing.as_ref().map_or_else(String::new, |(path, format)| {
+            format!(
+                "<backingStore><path>{}</path><format type='{}'/></backingStore>",
+                escape_xml(path),
+                escape_xml(format)
+            )
+        });
+        let xml = format!(
+            "<volume><name>{}</name><capacity unit='bytes'>{}</capacity>\
+             <target><format type='{}'/></target>{backing}</volume>",
+            escape_xml(&volume.name),
+            volume.capacity,
+            escape_xml(&volume.format)
+        );
+        let xml = CString::new(xml).map_err(|e| e.to_string())?;
+        // SAFETY: the pool handle is valid and `xml` is NUL-terminated.
+        let vol = unsafe { virStorageVolCreateXML(pool.0, xml.as_ptr(), 0) };
+        if vol.is_null() {
+            return Err(last_error());
+        }
+        drop(VolumeHandle(vol));
+        info!("Created volume '{}' in pool '{}'", volume.name, volume.pool);
+        Ok(())
+    }
+
+    fn upload_volume(&self, pool: &str, name: &str, file: &Path) -> Result<(), String> {
+        let size = std::fs::metadata(file)
+            .map_err(|e| format!("cannot read {}: {e}", file.display()))?
+            .len();
+        self.create_volume(&NewVolume {
+            pool: pool.to_string(),
+            name: name.to_string(),
+            capacity: size,
+            format: "raw".to_string(),
+            backing: None,
+        })?;
+        let vol = self.lookup_pool(pool)?.volume(name)?;
+        self.upload(&vol, file).inspect_err(|_| {
+            if let Err(e) = vol.delete() {
+                warn!("Cannot remove the incomplete volume {name}: {e}");
+            }
+        })
+    }
+
+    fn delete_volume(&self, pool: &str, name: &str) -> Result<(), String> {
+        self.lookup_pool(pool)?.volume(name)?.delete()
+    }
+
+    fn volume_at(&self, path: &str) -> Result<(String, String), String> {
+        let c_path = CString::new(path).map_err(|e| e.to_string())?;
+        // SAFETY: the connection is open and `c_path` is NUL-terminated.
+        let vol = unsafe { virStorageVolLookupByPath(self.conn, c_path.as_ptr()) };
+        if vol.is_null() {
+            return Err(last_error());
+        }
+        let vol = VolumeHandle(vol);
+        Ok((vol.pool()?.name(), vol.name()))
+    }
+
+    fn volume_path(&self, pool: &str, name: &str) -> Result<String, String> {
+        self.lookup_pool(pool)?.volume(name)?.path()
+    }
+
+    fn volume_info(&self, pool: &str, name: &str) -> Result<VolumeInfo, String> {
+        self.lookup_pool(pool)?.volume(name)?.info()
+    }
+
+    fn clone_volume(&self, pool: &str, source: &str, name: &str) -> Result<(), String> {
+        let pool = self.lookup_pool(pool)?;
+        let source = pool.volume(source)?;
+        // Like `virsh vol-clone`: the source's XML under the new name.
+        let xml = source.xml()?;
+        let (Some(start), Some(end)) = (xml.find("<name>"), xml.find("</name>")) else {
+            return Err("the volume XML has no name".to_string());
+        };
+        let xml = format!("{}<name>{}{}", &xml[..start], escape_xml(name), &xml[end..]);
+        let xml = CString::new(xml).map_err(|e| e.to_string())?;
+        // SAFETY: both handles are valid and `xml` is NUL-terminated.
+        let vol = unsafe { virStorageVolCreateXMLFrom(pool.0, xml.as_ptr(), source.0, 0) };
+        if vol.is_null() {
+            return Err(last_error());
+        }
+        drop(VolumeHandle(vol));
+        info!("Cloned volume '{}' to '{name}' in pool '{}'", source.name(), pool.name());
+        Ok(())
+    }
+
+    fn domstats(&self) -> Result<Vec<DomainStats>, String> {
+        let mut raw: *mut *mut VirDomainStatsRecord = ptr::null_mut();
+        let stats = VIR_DOMAIN_STATS_CPU_TOTAL
+            | VIR_DOMAIN_STATS_BALLOON
+            | VIR_DOMAIN_STATS_VCPU
+            | VIR_DOMAIN_STATS_INTERFACE
+            | VIR_DOMAIN_STATS_BLOCK;
+        // SAFETY: the connection is open; libvirt allocates `raw`.
+        let count = unsafe {
+            virConnectGetAllDomainStats(self.conn, stats, &mut raw, VIR_CONNECT_GET_ALL_DOMAINS_STATS_ACTIVE)
+        };
+        if count < 0 {
+            return Err(format!("unable to get domain stats: {}", last_error()));
+        }
+        let mut result = Vec::with_capacity(count as usize);
+        // SAFETY: libvirt returned `count` records, each with `nparams` typed
+        // parameters; the list (including the domains) is freed once below.
+        unsafe {
+            for i in 0..count as usize {
+                let record = &**raw.add(i);
+                let mut stats = DomainStats {
+                    name: CStr::from_ptr(virDomainGetName(record.dom))
+                        .to_string_lossy()
+                        .into_owned(),
+                    ..DomainStats::default()
+                };
+                for j in 0..record.nparams as usize {
+                    let param = &*record.params.add(j);
+                    let value = match param.kind {
+                        VIR_TYPED_PARAM_INT => param.value.i as u64,
+                        VIR_TYPED_PARAM_UINT => param.value.ui as u64,
+                        VIR_TYPED_PARAM_LLONG => param.value.l as u64,
+                        VIR_TYPED_PARAM_ULLONG => param.value.ul,
+                        _ => continue,
+                    };
+                    let key = CStr::from_ptr(param.field.as_ptr()).to_string_lossy();
+                    stats.apply(&key, value);
+                }
+                result.push(stats);
+            }
+            virDomainStatsRecordListFree(raw);
+        }
+        Ok(result)
+    }
+
+    fn console_command(&self, name: &str) -> Result<Vec<String>, String> {
+        // Stream handling and escape keys are already done by `virsh console`.
+        Ok(["virsh", "-c", &self.uri, "console", name].map(str::to_string).to_vec())
+    }
+}
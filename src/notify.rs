@@ -0,0 +1,159 @@
+//! Messages for the status bar, with a scrollback history of recent ones.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use time::{OffsetDateTime, UtcOffset};
+
+/// Number of messages kept for the history view.
+const HISTORY_LEN: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warn",
+            Severity::Error => "error",
+        }
+    }
+
+    /// How long a message stays in the status bar.
+    fn lifetime(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(5),
+            Severity::Warning => Duration::from_secs(10),
+            Severity::Error => Duration::from_secs(20),
+        }
+    }
+}
+
+pub struct Notification {
+    pub severity: Severity,
+    pub text: String,
+    /// Local wall-clock time, `HH:MM:SS`.
+    pub time: String,
+    posted: Instant,
+}
+
+impl Notification {
+    fn is_expired(&self) -> bool {
+        self.posted.elapsed() >= self.severity.lifetime()
+    }
+}
+
+pub struct Notifications {
+    history: VecDeque<Notification>,
+    offset: UtcOffset,
+}
+
+impl Notifications {
+    /// Must be created before any other thread is spawned: `time` can only
+    /// determine the local UTC offset while the process is single-threaded.
+    pub fn new() -> Self {
+        Self {
+            history: VecDeque::new(),
+            offset: UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC),
+        }
+    }
+
+    /// Show a message in the status bar and also write it to the log.
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>) {
+        let text = text.into();
+        match severity {
+            Severity::Info => info!("{text}"),
+            Severity::Warning => warn!("{text}"),
+            Severity::Error => error!("{text}"),
+        }
+        let now = OffsetDateTime::now_utc().to_offset(self.offset);
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(Notification {
+            severity,
+            text,
+            time: format!("{:02}:{:02}:{:02}", now.hour(), now.minute(), now.second()),
+            posted: Instant::now(),
+        });
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Severity::Info, text);
+    }
+
+    pub fn warn(&mut self, text: impl Into<String>) {
+        self.push(Severity::Warning, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Severity::Error, text);
+    }
+
+    /// The newest message, unless it has expired.
+    pub fn current(&self) -> Option<&Notification> {
+        self.history.back().filter(|n| !n.is_expired())
+    }
+
+    /// All kept messages, newest first.
+    pub fn history(&self) -> impl Iterator<Item = &Notification> {
+        self.history.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretend the newest message was posted `age` ago.
+    fn age_newest(notifications: &mut Notifications, age: Duration) {
+        let newest = notifications.history.back_mut().unwrap();
+        newest.posted = Instant::now().checked_sub(age).unwrap();
+    }
+
+    #[test]
+    fn severities_stay_longer_the_worse_they_are() {
+        assert!(Severity::Info.lifetime() < Severity::Warning.lifetime());
+        assert!(Severity::Warning.lifetime() < Severity::Error.lifetime());
+        for (severity, label) in [(Severity::Info, "info"), (Severity::Warning, "warn"), (Severity::Error, "error")] {
+            let mut notifications = Notifications::new();
+            notifications.push(severity, "disk full");
+            assert_eq!(notifications.current().map(|n| n.severity.label()), Some(label));
+            age_newest(&mut notifications, severity.lifetime() - Duration::from_millis(100));
+            assert!(notifications.current().is_some(), "{label} expired early");
+            age_newest(&mut notifications, severity.lifetime());
+            assert!(notifications.current().is_none(), "{label} did not expire");
+        }
+    }
+
+    #[test]
+    fn current_is_the_newest_message() {
+        let mut notifications = Notifications::new();
+        assert!(notifications.current().is_none());
+        notifications.error("Refreshing lab failed");
+        notifications.info("Started 'web01'");
+        assert_eq!(notifications.current().map(|n| n.text.as_str()), Some("Started 'web01'"));
+        // An expired newest message hides the older one too.
+        age_newest(&mut notifications, Duration::from_secs(6));
+        assert!(notifications.current().is_none());
+        assert_eq!(notifications.history().count(), 2);
+    }
+
+    #[test]
+    fn history_keeps_the_newest() {
+        let mut notifications = Notifications::new();
+        for i in 0..=HISTORY_LEN {
+            notifications.info(format!("message {i}"));
+        }
+        let texts: Vec<_> = notifications.history().map(|n| n.text.as_str()).collect();
+        assert_eq!(texts.len(), HISTORY_LEN);
+        assert_eq!(texts[0], format!("message {HISTORY_LEN}"));
+        assert_eq!(texts[HISTORY_LEN - 1], "message 1");
+    }
+}
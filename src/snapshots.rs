@@ -0,0 +1,266 @@
+//! Snapshot panel for a single VM.
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+
+use crate::backend::{Snapshot, SnapshotSpec};
+
+/// Snapshots of one VM, shown in place of the VM table.
+pub struct SnapshotPanel {
+    pub host: String,
+    pub vm_name: String,
+    /// Snapshots in tree order with their depth; `None` until the first load.
+    snapshots: Option<Result<Vec<(usize, Snapshot)>, String>>,
+    state: TableState,
+}
+
+impl SnapshotPanel {
+    pub fn new(host: &str, vm_name: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            snapshots: None,
+            state: TableState::default(),
+        }
+    }
+
+    /// Replace the list, keeping the selected snapshot if it still exists.
+    pub fn set_snapshots(&mut self, result: Result<Vec<Snapshot>, String>) {
+        let selected = self.selected().map(|s| s.name.clone());
+        let result = result.map(tree_order);
+        let index = match &result {
+            Ok(list) if !list.is_empty() => Some(
+                selected
+                    .and_then(|name| list.iter().position(|(_, s)| s.name == name))
+                    .or_else(|| list.iter().position(|(_, s)| s.current))
+                    .unwrap_or(0),
+            ),
+            _ => None,
+        };
+        self.state.select(index);
+        self.snapshots = Some(result);
+    }
+
+    pub fn selected(&self) -> Option<&Snapshot> {
+        let Some(Ok(list)) = &self.snapshots else {
+            return None;
+        };
+        self.state.selected().and_then(|i| list.get(i)).map(|(_, s)| s)
+    }
+
+    pub fn next(&mut self) {
+        if let Some(Ok(list)) = &self.snapshots
+            && let Some(i) = self.state.selected()
+        {
+            self.state.select(Some((i + 1) % list.len()));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Some(Ok(list)) = &self.snapshots
+            && let Some(i) = self.state.selected()
+        {
+            self.state.select(Some(if i == 0 { list.len() - 1 } else { i - 1 }));
+        }
+    }
+}
+
+/// Order snapshots depth-first so children follow their parent, oldest first.
+fn tree_order(mut snapshots: Vec<Snapshot>) -> Vec<(usize, Snapshot)> {
+    snapshots.sort_by(|a, b| a.creation_time.cmp(&b.creation_time));
+    let is_root = |s: &Snapshot| {
+        s.parent
+            .as_ref()
+            .is_none_or(|p| !snapshots.iter().any(|o| &o.name == p))
+    };
+    let mut stack: Vec<(usize, &Snapshot)> = snapshots
+        .iter()
+        .rev()
+        .filter(|s| is_root(s))
+        .map(|s| (0, s))
+        .collect();
+    let mut ordered = Vec::with_capacity(snapshots.len());
+    while let Some((depth, snapshot)) = stack.pop() {
+        ordered.push((depth, snapshot.clone()));
+        stack.extend(
+            snapshots
+                .iter()
+                .rev()
+                .filter(|s| s.parent.as_ref() == Some(&snapshot.name))
+                .map(|s| (depth + 1, s)),
+        );
+    }
+    ordered
+}
+
+pub fn render(f: &mut Frame, area: Rect, panel: &mut SnapshotPanel) {
+    let title = format!(
+        " Snapshots of '{}' (j/k: navigate, c: create, r: revert, x: delete, Esc: close) ",
+        panel.vm_name
+    );
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let list = match &panel.snapshots {
+        None => {
+            f.render_widget(Paragraph::new("Loading…").block(block), area);
+            return;
+        }
+        Some(Err(e)) => {
+            let text = Paragraph::new(e.as_str()).style(Style::default().fg(Color::Red));
+            f.render_widget(text.block(block), area);
+            return;
+        }
+        Some(Ok(list)) if list.is_empty() => {
+            f.render_widget(Paragraph::new("No snapshots. Press c to create one.").block(block), area);
+            return;
+        }
+        Some(Ok(list)) => list,
+    };
+
+    let rows: Vec<Row> = list
+        .iter()
+        .map(|(depth, s)| {
+            let branch = if *depth == 0 { "" } else { "└ " };
+            let name = format!("{}{branch}{}", "  ".repeat(depth.saturating_sub(1)), s.name);
+            let mut row = Row::new(vec![
+                Cell::from(if s.current { "*" } else { "" }),
+                Cell::from(name),
+                Cell::from(s.creation_time.clone()),
+                Cell::from(s.state.clone()),
+            ]);
+            if s.current {
+                row = row.style(Style::default().fg(Color::Green));
+            }
+            row
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(1),
+        Constraint::Min(20),
+        Constraint::Length(26),
+        Constraint::Length(14),
+    ];
+    let header = Row::new(["", "Name", "Created", "State"])
+        .style(Style::default().bold())
+        .bottom_margin(1);
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(table, area, &mut panel.state);
+}
+
+/// Field of the create-snapshot form that receives key presses.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotField {
+    Name,
+    Description,
+    DiskOnly,
+}
+
+impl SnapshotField {
+    pub fn next(self) -> Self {
+        match self {
+            SnapshotField::Name => SnapshotField::Description,
+            SnapshotField::Description => SnapshotField::DiskOnly,
+            SnapshotField::DiskOnly => SnapshotField::Name,
+        }
+    }
+}
+
+pub fn render_form(f: &mut Frame, area: Rect, spec: &SnapshotSpec, field: SnapshotField) {
+    let style = |f: SnapshotField| {
+        if f == field {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        }
+    };
+    let cursor = |f: SnapshotField| if f == field { "|" } else { "" };
+    let lines = vec![
+        Line::from(vec![
+            Span::raw("Name:        "),
+            Span::styled(format!("{}{}", spec.name, cursor(SnapshotField::Name)), style(SnapshotField::Name)),
+        ]),
+        Line::from(vec![
+            Span::raw("Description: "),
+            Span::styled(
+                format!("{}{}", spec.description, cursor(SnapshotField::Description)),
+                style(SnapshotField::Description),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Disk only:   "),
+            Span::styled(
+                format!("[{}] no memory state", if spec.disk_only { "x" } else { " " }),
+                style(SnapshotField::DiskOnly),
+            ),
+        ]),
+    ];
+    let form = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" New snapshot — Tab: next field, Space: toggle, Enter: create, Esc: cancel "),
+    );
+    f.render_widget(form, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(name: &str, parent: Option<&str>, creation_time: &str) -> Snapshot {
+        Snapshot {
+            name: name.to_string(),
+            parent: parent.map(str::to_string),
+            creation_time: creation_time.to_string(),
+            state: "shutoff".to_string(),
+            current: false,
+        }
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let snapshots = vec![
+            snapshot("tls-test", Some("installed"), "2024-05-01 09:30:45 +0000"),
+            snapshot("pre-upgrade", Some("installed"), "2024-04-20 18:02:11 +0000"),
+            snapshot("upgraded", Some("pre-upgrade"), "2024-04-21 08:00:00 +0000"),
+            snapshot("installed", None, "2024-03-02 10:15:00 +0000"),
+            snapshot("fresh", None, "2024-06-01 00:00:00 +0000"),
+        ];
+        let ordered: Vec<_> = tree_order(snapshots).into_iter().map(|(depth, s)| (depth, s.name)).collect();
+        let expected = [(0, "installed"), (1, "pre-upgrade"), (2, "upgraded"), (1, "tls-test"), (0, "fresh")];
+        assert_eq!(ordered, expected.map(|(depth, name)| (depth, name.to_string())));
+    }
+
+    #[test]
+    fn orphans_are_roots() {
+        // The parent was deleted outside libvirt's bookkeeping, or is not listed.
+        let snapshots = vec![
+            snapshot("child", Some("orphan"), "2024-02-01 00:00:00 +0000"),
+            snapshot("orphan", Some("gone"), "2024-01-01 00:00:00 +0000"),
+            snapshot("lost", Some("gone"), "2023-12-01 00:00:00 +0000"),
+        ];
+        let ordered: Vec<_> = tree_order(snapshots).into_iter().map(|(depth, s)| (depth, s.name)).collect();
+        let expected = [(0, "lost"), (0, "orphan"), (1, "child")];
+        assert_eq!(ordered, expected.map(|(depth, name)| (depth, name.to_string())));
+    }
+
+    #[test]
+    fn reload_keeps_the_selection() {
+        let mut panel = SnapshotPanel::new("test:///demo", "web01");
+        let mut current = snapshot("b", Some("a"), "2");
+        current.current = true;
+        let list = vec![snapshot("a", None, "1"), current, snapshot("c", Some("a"), "3")];
+        panel.set_snapshots(Ok(list.clone()));
+        assert_eq!(panel.selected().map(|s| s.name.as_str()), Some("b"));
+        panel.next();
+        assert_eq!(panel.selected().map(|s| s.name.as_str()), Some("c"));
+        panel.set_snapshots(Ok(list));
+        assert_eq!(panel.selected().map(|s| s.name.as_str()), Some("c"));
+        panel.set_snapshots(Ok(vec![snapshot("a", None, "1")]));
+        assert_eq!(panel.selected().map(|s| s.name.as_str()), Some("a"));
+        panel.set_snapshots(Err("domain not found".to_string()));
+        assert!(panel.selected().is_none());
+    }
+}
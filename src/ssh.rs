@@ -0,0 +1,528 @@
+//! SSH into VMs, with settings remembered per VM.
+
+use std::path::PathBuf;
+
+use crossterm::event::KeyCode;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::IfAddr;
+use crate::domain::DomainConfig;
+
+/// Settings remembered for one VM; empty strings are left to ssh's defaults.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SshProfile {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub user: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Private key passed with `-i`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub identity: String,
+    /// Extra ssh arguments, split like a shell would, e.g. `-o StrictHostKeyChecking=no`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub options: String,
+    /// Network, bridge or guest interface whose address is picked when the VM has several.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub network: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredProfile {
+    host: String,
+    vm: String,
+    #[serde(flatten)]
+    profile: SshProfile,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProfilesFile {
+    #[serde(default, rename = "profile")]
+    profiles: Vec<StoredProfile>,
+}
+
+/// Remembered profiles, saved whenever one changes.
+#[derive(Default)]
+pub struct SshProfiles {
+    /// `None` if there is no home directory to save to.
+    path: Option<PathBuf>,
+    file: ProfilesFile,
+}
+
+impl SshProfiles {
+    /// `$XDG_STATE_HOME/yalv-rust/ssh.toml`, or `~/.local/state/yalv-rust/ssh.toml`.
+    fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+        Some(base.join("yalv-rust").join("ssh.toml"))
+    }
+
+    /// Read the saved profiles. A missing file is not an error; an unreadable
+    /// one is reported, and profiles then start out empty.
+    pub fn load() -> (Self, Option<String>) {
+        let mut profiles = Self {
+            path: Self::default_path(),
+            file: ProfilesFile::default(),
+        };
+        let Some(path) = profiles.path.clone().filter(|p| p.exists()) else {
+            return (profiles, None);
+        };
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml::from_str::<ProfilesFile>(&text).map_err(|e| e.to_string().trim_end().to_string()));
+        match result {
+            Ok(file) => {
+                profiles.file = file;
+                (profiles, None)
+            }
+            Err(e) => (profiles, Some(format!("Cannot read SSH profiles from {}: {e}", path.display()))),
+        }
+    }
+
+    pub fn get(&self, host: &str, vm: &str) -> Option<&SshProfile> {
+        self.file
+            .profiles
+            .iter()
+            .find(|p| p.host == host && p.vm == vm)
+            .map(|p| &p.profile)
+    }
+
+    /// Remember `profile` for the VM and write the file.
+    pub fn set(&mut self, host: &str, vm: &str, profile: SshProfile) -> Result<(), String> {
+        match self.file.profiles.iter_mut().find(|p| p.host == host && p.vm == vm) {
+            Some(stored) => stored.profile = profile,
+            None => self.file.profiles.push(StoredProfile {
+                host: host.to_string(),
+                vm: vm.to_string(),
+                profile,
+            }),
+        }
+        let Some(path) = &self.path else {
+            return Err("no home directory to save to".to_string());
+        };
+        let text = toml::to_string(&self.file).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        std::fs::write(path, text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// An address of the VM with the network it is on.
+pub struct Address {
+    pub ip: String,
+    /// Network or bridge of the NIC with the address's MAC, else the interface name.
+    pub network: String,
+}
+
+/// Label `addrs` with their network.
+pub fn addresses(addrs: Vec<IfAddr>, config: Option<&DomainConfig>) -> Vec<Address> {
+    addrs
+        .into_iter()
+        .map(|a| {
+            let nic = config.and_then(|c| {
+                c.interfaces.iter().find(|i| {
+                    i.mac.as_deref().zip(a.mac.as_deref()).is_some_and(|(x, y)| x.eq_ignore_ascii_case(y))
+                })
+            });
+            let network = nic.and_then(|i| i.source.clone()).unwrap_or(a.interface);
+            Address { ip: a.ip, network }
+        })
+        .collect()
+}
+
+/// `-J` target for VMs behind a remote `qemu+ssh://` connection, e.g. `root@lab1:2222`.
+pub fn proxy_jump(uri: &str) -> Option<String> {
+    let (scheme, rest) = uri.split_once("://")?;
+    if !scheme.ends_with("+ssh") {
+        return None;
+    }
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let host = crate::viewer::display_host(uri);
+    if authority.is_empty() || matches!(host, "localhost" | "127.0.0.1" | "::1") {
+        return None;
+    }
+    Some(authority.to_string())
+}
+
+/// Field of the SSH form that receives key presses.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SshField {
+    User,
+    Address,
+    Port,
+    Identity,
+    Options,
+}
+
+impl SshField {
+    fn next(self) -> Self {
+        match self {
+            SshField::User => SshField::Address,
+            SshField::Address => SshField::Port,
+            SshField::Port => SshField::Identity,
+            SshField::Identity => SshField::Options,
+            SshField::Options => SshField::User,
+        }
+    }
+}
+
+/// The SSH prompt, pre-filled from the VM's profile.
+pub struct SshForm {
+    pub host: String,
+    pub vm_name: String,
+    addresses: Vec<Address>,
+    /// Index into `addresses`.
+    address: usize,
+    /// `address` is on the profile's network, or the only one.
+    address_known: bool,
+    user: String,
+    port: String,
+    identity: String,
+    options: String,
+    field: SshField,
+    jump: Option<String>,
+}
+
+impl SshForm {
+    /// `addresses` must not be empty.
+    pub fn new(host: &str, vm_name: &str, addresses: Vec<Address>, profile: &SshProfile) -> Self {
+        let preferred = addresses
+            .iter()
+            .position(|a| !profile.network.is_empty() && a.network == profile.network);
+        let address_known = preferred.is_some() || addresses.len() == 1;
+        // With several addresses to choose from, start on the chooser.
+        let field = if !address_known {
+            SshField::Address
+        } else {
+            SshField::User
+        };
+        Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            addresses,
+            address: preferred.unwrap_or(0),
+            address_known,
+            user: profile.user.clone(),
+            port: profile.port.map(|p| p.to_string()).unwrap_or_default(),
+            identity: profile.identity.clone(),
+            options: profile.options.clone(),
+            field,
+            jump: proxy_jump(host),
+        }
+    }
+
+    /// Whether the user should pick one of several addresses before connecting.
+    pub fn needs_choice(&self) -> bool {
+        !self.address_known
+    }
+
+    /// Whether ssh goes through another host, so the address may not be reachable from here.
+    pub fn uses_proxy(&self) -> bool {
+        self.jump.is_some() || ["-J", "ProxyJump", "ProxyCommand"].iter().any(|o| self.options.contains(o))
+    }
+
+    /// Addresses to wait for SSH on: the chosen one, or all of them until one is chosen.
+    pub fn candidates(&self) -> Vec<String> {
+        if self.address_known {
+            return vec![self.target().0.to_string()];
+        }
+        self.addresses.iter().map(|a| a.ip.clone()).collect()
+    }
+
+    /// Settle on the address `ip`, e.g. the first that answered.
+    pub fn choose(&mut self, ip: &str) {
+        if let Some(index) = self.addresses.iter().position(|a| a.ip == ip) {
+            self.address = index;
+            self.address_known = true;
+            if self.field == SshField::Address {
+                self.field = SshField::User;
+            }
+        }
+    }
+
+    /// The chosen address and port.
+    pub fn target(&self) -> (&str, u16) {
+        let ip = self.addresses.get(self.address).map_or("", |a| a.ip.as_str());
+        (ip, self.port.trim().parse().unwrap_or(22))
+    }
+
+    /// The profile to remember, including the network of the chosen address.
+    pub fn profile(&self) -> Result<SshProfile, String> {
+        let port = self.port.trim();
+        let port = if port.is_empty() {
+            None
+        } else {
+            Some(
+                port.parse::<u16>()
+                    .ok()
+                    .filter(|&p| p != 0)
+                    .ok_or_else(|| format!("SSH port '{port}' is not a number from 1 to 65535"))?,
+            )
+        };
+        Ok(SshProfile {
+            user: self.user.trim().to_string(),
+            port,
+            identity: self.identity.trim().to_string(),
+            options: self.options.trim().to_string(),
+            network: self.addresses.get(self.address).map(|a| a.network.clone()).unwrap_or_default(),
+        })
+    }
+
+    /// The ssh command line, e.g. `ssh -J root@lab1 -p 2222 admin@10.0.0.5`.
+    pub fn command(&self) -> Result<Vec<String>, String> {
+        let profile = self.profile()?;
+        let options = shlex::split(&profile.options).ok_or("SSH options have unbalanced quotes")?;
+        let mut args = vec!["ssh".to_string()];
+        let own_proxy = options
+            .iter()
+            .any(|o| o.starts_with("-J") || o.contains("ProxyJump") || o.contains("ProxyCommand"));
+        if let Some(jump) = self.jump.as_ref().filter(|_| !own_proxy) {
+            args.extend(["-J".to_string(), jump.clone()]);
+        }
+        if let Some(port) = profile.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if !profile.identity.is_empty() {
+            args.extend(["-i".to_string(), profile.identity.clone()]);
+        }
+        args.extend(options);
+        let ip = &self.addresses.get(self.address).ok_or("no address to connect to")?.ip;
+        args.push(if profile.user.is_empty() {
+            ip.clone()
+        } else {
+            format!("{}@{ip}", profile.user)
+        });
+        Ok(args)
+    }
+
+    /// Edit the form; Enter and Esc are handled by the caller.
+    pub fn handle_key(&mut self, key: KeyCode) {
+        let count = self.addresses.len();
+        let text = match self.field {
+            SshField::User => &mut self.user,
+            SshField::Port => &mut self.port,
+            SshField::Identity => &mut self.identity,
+            SshField::Options => &mut self.options,
+            SshField::Address => {
+                match key {
+                    KeyCode::Tab => self.field = self.field.next(),
+                    KeyCode::Right | KeyCode::Down | KeyCode::Char(' ') => self.address = (self.address + 1) % count,
+                    KeyCode::Left | KeyCode::Up => self.address = (self.address + count - 1) % count,
+                    _ => {}
+                }
+                return;
+            }
+        };
+        match key {
+            KeyCode::Tab => self.field = self.field.next(),
+            KeyCode::Backspace => {
+                text.pop();
+            }
+            KeyCode::Char(c) if self.field != SshField::Port || c.is_ascii_digit() => text.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Height of `render_form`, borders included.
+pub const FORM_HEIGHT: u16 = 8;
+
+pub fn render_form(f: &mut Frame, area: Rect, form: &SshForm) {
+    let style = |field: SshField| {
+        if field == form.field {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        }
+    };
+    let cursor = |field: SshField| if field == form.field { "|" } else { "" };
+    let text_line = |label: &'static str, field: SshField, value: &str, hint: &'static str| {
+        Line::from(vec![
+            Span::raw(label),
+            Span::styled(format!("{value}{}", cursor(field)), style(field)),
+            Span::styled(hint, Style::default().fg(Color::DarkGray)),
+        ])
+    };
+    let address = form
+        .addresses
+        .get(form.address)
+        .map_or_else(String::new, |a| format!("{} ({})", a.ip, a.network));
+    let choices = if form.addresses.len() > 1 {
+        format!("  {}/{}, ←/→: choose", form.address + 1, form.addresses.len())
+    } else {
+        String::new()
+    };
+    let command = match form.command() {
+        Ok(args) => shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" ")),
+        Err(e) => e,
+    };
+    let lines = vec![
+        text_line("User:     ", SshField::User, &form.user, ""),
+        Line::from(vec![
+            Span::raw("Address:  "),
+            Span::styled(address, style(SshField::Address)),
+            Span::styled(choices, Style::default().fg(Color::DarkGray)),
+        ]),
+        text_line("Port:     ", SshField::Port, &form.port, "  (default 22)"),
+        text_line("Identity: ", SshField::Identity, &form.identity, "  (private key file)"),
+        text_line("Options:  ", SshField::Options, &form.options, ""),
+        Line::styled(format!("$ {command}"), Style::default().fg(Color::DarkGray)),
+    ];
+    let form_widget = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" SSH to {} — Tab: next field, Enter: connect, Esc: cancel ", form.vm_name)),
+    );
+    f.render_widget(form_widget, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(host: &str, profile: &SshProfile) -> SshForm {
+        let addresses = vec![Address {
+            ip: "10.0.0.5".to_string(),
+            network: "default".to_string(),
+        }];
+        SshForm::new(host, "web01", addresses, profile)
+    }
+
+    #[test]
+    fn jump_host_from_uri() {
+        assert_eq!(proxy_jump("qemu+ssh://root@lab1:2222/system").as_deref(), Some("root@lab1:2222"));
+        assert_eq!(proxy_jump("qemu+ssh://lab1/system?keyfile=/root/.ssh/lab").as_deref(), Some("lab1"));
+        assert_eq!(proxy_jump("qemu+ssh://[fd00::1]/system").as_deref(), Some("[fd00::1]"));
+        assert_eq!(proxy_jump("qemu+ssh://localhost/system"), None);
+        assert_eq!(proxy_jump("qemu+ssh://root@127.0.0.1/system"), None);
+        assert_eq!(proxy_jump("qemu:///system"), None);
+        assert_eq!(proxy_jump("qemu+tcp://lab1/system"), None);
+        assert_eq!(proxy_jump("test:///default"), None);
+    }
+
+    #[test]
+    fn command_jumps_through_the_host() {
+        let profile = SshProfile {
+            user: "admin".to_string(),
+            port: Some(2222),
+            identity: "~/.ssh/lab".to_string(),
+            ..SshProfile::default()
+        };
+        let lab1 = form("qemu+ssh://root@lab1:2222/system", &profile);
+        assert!(lab1.uses_proxy());
+        assert_eq!(
+            lab1.command().unwrap(),
+            ["ssh", "-J", "root@lab1:2222", "-p", "2222", "-i", "~/.ssh/lab", "admin@10.0.0.5"]
+        );
+        assert_eq!(form("qemu:///system", &SshProfile::default()).command().unwrap(), ["ssh", "10.0.0.5"]);
+    }
+
+    #[test]
+    fn own_proxy_option_wins() {
+        for options in ["-J bastion", "-Jbastion", "-o ProxyJump=bastion", "-o 'ProxyCommand=ssh -W %h:%p bastion'"] {
+            let profile = SshProfile {
+                options: options.to_string(),
+                ..SshProfile::default()
+            };
+            let args = form("qemu+ssh://root@lab1/system", &profile).command().unwrap();
+            assert!(!args.iter().any(|a| a == "root@lab1"), "{options}: {args:?}");
+            assert_eq!(args.last().map(String::as_str), Some("10.0.0.5"));
+        }
+    }
+
+    #[test]
+    fn bad_port() {
+        let mut form = form("qemu:///system", &SshProfile::default());
+        for port in ["0", "65536", "22a", "-1"] {
+            form.port = port.to_string();
+            assert_eq!(
+                form.command().err(),
+                Some(format!("SSH port '{port}' is not a number from 1 to 65535")),
+                "{port}"
+            );
+        }
+        form.port = " 2222 ".to_string();
+        assert_eq!(form.profile().unwrap().port, Some(2222));
+        assert_eq!(form.target(), ("10.0.0.5", 2222));
+        // Only digits can be typed into the port field.
+        form.port.clear();
+        form.field = SshField::Port;
+        for c in ['2', 'x', '2'] {
+            form.handle_key(KeyCode::Char(c));
+        }
+        assert_eq!(form.port, "22");
+    }
+
+    fn if_addr(interface: &str, mac: Option<&str>, ip: &str) -> IfAddr {
+        IfAddr {
+            interface: interface.to_string(),
+            mac: mac.map(str::to_string),
+            ip: ip.to_string(),
+        }
+    }
+
+    #[test]
+    fn addresses_are_labelled_with_the_nic_network() {
+        let config = DomainConfig::parse(include_str!("../fixtures/build-bridge-macvtap.xml")).unwrap();
+        let addrs = vec![
+            if_addr("eth0", Some("52:54:00:6C:2A:03"), "192.168.1.40"),
+            if_addr("eth1", Some("52:54:00:6c:2a:13"), "10.20.0.40"),
+            if_addr("docker0", Some("02:42:4f:1a:00:01"), "172.17.0.1"),
+            if_addr("vnet3", None, "192.168.1.41"),
+        ];
+        let labelled: Vec<_> = addresses(addrs.clone(), Some(&config))
+            .into_iter()
+            .map(|a| (a.ip, a.network))
+            .collect();
+        let expected = [
+            ("192.168.1.40", "br0"),
+            ("10.20.0.40", "enp5s0f1"),
+            ("172.17.0.1", "docker0"),
+            ("192.168.1.41", "vnet3"),
+        ];
+        assert_eq!(labelled, expected.map(|(ip, network)| (ip.to_string(), network.to_string())));
+        // Without the config only interface names are known.
+        let networks: Vec<_> = addresses(addrs, None).into_iter().map(|a| a.network).collect();
+        assert_eq!(networks, ["eth0", "eth1", "docker0", "vnet3"]);
+    }
+
+    #[test]
+    fn profile_network_picks_the_address() {
+        let addresses = || {
+            [("192.168.122.11", "default"), ("172.17.0.1", "docker0"), ("10.20.0.11", "storage")]
+                .into_iter()
+                .map(|(ip, network)| Address {
+                    ip: ip.to_string(),
+                    network: network.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let profile = |network: &str| SshProfile {
+            network: network.to_string(),
+            ..SshProfile::default()
+        };
+        let storage = SshForm::new("qemu:///system", "db01", addresses(), &profile("storage"));
+        assert!(!storage.needs_choice());
+        assert!(storage.field == SshField::User);
+        assert_eq!(storage.target(), ("10.20.0.11", 22));
+
+        // A network the VM is no longer on, or none, asks which address to use.
+        for network in ["gone", ""] {
+            let mut form = SshForm::new("qemu:///system", "db01", addresses(), &profile(network));
+            assert!(form.needs_choice(), "{network}");
+            assert!(form.field == SshField::Address);
+            assert_eq!(form.target().0, "192.168.122.11");
+            form.handle_key(KeyCode::Left);
+            assert_eq!(form.target().0, "10.20.0.11");
+            assert_eq!(form.profile().unwrap().network, "storage");
+        }
+
+        let only = SshForm::new("qemu:///system", "db01", addresses().into_iter().skip(1).take(1).collect(), &profile(""));
+        assert!(!only.needs_choice());
+    }
+}
@@ -0,0 +1,176 @@
+//! Rates derived from successive `domstats` samples, with a rolling history.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::backend::DomainStats;
+
+/// Number of rate samples kept per VM (three minutes at the default refresh interval).
+const HISTORY_LEN: usize = 60;
+
+/// Resource usage between two samples.
+#[derive(Clone, Copy, Default)]
+pub struct Rates {
+    /// Share of the VM's vCPUs in use, 0-100.
+    pub cpu_percent: f64,
+    pub rss_kib: Option<u64>,
+    pub balloon_kib: Option<u64>,
+    /// Bytes per second.
+    pub disk_read: f64,
+    pub disk_write: f64,
+    pub net_rx: f64,
+    pub net_tx: f64,
+}
+
+#[derive(Default)]
+pub struct StatsHistory {
+    last: Option<(Instant, DomainStats)>,
+    rates: VecDeque<Rates>,
+}
+
+impl StatsHistory {
+    /// Add a sample taken at `at`. Rates need two samples, so the first one only
+    /// primes the history.
+    pub fn push(&mut self, at: Instant, sample: DomainStats) {
+        if let Some((prev_at, prev)) = &self.last {
+            let secs = at.duration_since(*prev_at).as_secs_f64();
+            if secs > 0.0 {
+                // Counters restart from zero when the domain is restarted.
+                let per_sec = |now: u64, before: u64| now.saturating_sub(before) as f64 / secs;
+                let vcpus = sample.vcpus.max(1) as f64;
+                let rates = Rates {
+                    cpu_percent: (per_sec(sample.cpu_time, prev.cpu_time) / 1e9 / vcpus * 100.0)
+                        .min(100.0),
+                    rss_kib: sample.rss_kib,
+                    balloon_kib: sample.balloon_kib,
+                    disk_read: per_sec(sample.block_read, prev.block_read),
+                    disk_write: per_sec(sample.block_write, prev.block_write),
+                    net_rx: per_sec(sample.net_rx, prev.net_rx),
+                    net_tx: per_sec(sample.net_tx, prev.net_tx),
+                };
+                if self.rates.len() == HISTORY_LEN {
+                    self.rates.pop_front();
+                }
+                self.rates.push_back(rates);
+            }
+        }
+        self.last = Some((at, sample));
+    }
+
+    pub fn latest(&self) -> Option<&Rates> {
+        self.rates.back()
+    }
+
+    /// The last `len` values of `f`, oldest first, for a sparkline.
+    pub fn series(&self, len: usize, f: impl Fn(&Rates) -> f64) -> Vec<u64> {
+        let skip = self.rates.len().saturating_sub(len);
+        self.rates.iter().skip(skip).map(|r| f(r) as u64).collect()
+    }
+}
+
+/// Format a byte rate compactly, e.g. `1.2M/s`.
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+    let mut value = bytes_per_sec;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 || value >= 100.0 {
+        format!("{value:.0}{}/s", UNITS[unit])
+    } else {
+        format!("{value:.1}{}/s", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// `cpu_secs` of CPU time and `disk` bytes read so far, on `vcpus` vCPUs.
+    fn sample(cpu_secs: f64, vcpus: u32, disk: u64) -> DomainStats {
+        DomainStats {
+            cpu_time: (cpu_secs * 1e9) as u64,
+            vcpus,
+            rss_kib: Some(1024),
+            block_read: disk,
+            net_rx: disk / 2,
+            ..DomainStats::default()
+        }
+    }
+
+    #[test]
+    fn first_sample_only_primes() {
+        let mut history = StatsHistory::default();
+        history.push(Instant::now(), sample(5.0, 2, 1000));
+        assert!(history.latest().is_none());
+        assert!(history.series(10, |r| r.cpu_percent).is_empty());
+    }
+
+    #[test]
+    fn rates() {
+        let t0 = Instant::now();
+        let mut history = StatsHistory::default();
+        history.push(t0, sample(10.0, 2, 0));
+        // One of two vCPUs busy for the 2 s between the samples.
+        history.push(t0 + Duration::from_secs(2), sample(12.0, 2, 4096));
+        let rates = history.latest().unwrap();
+        assert_eq!(rates.cpu_percent, 50.0);
+        assert_eq!((rates.disk_read, rates.net_rx), (2048.0, 1024.0));
+        assert_eq!((rates.disk_write, rates.net_tx), (0.0, 0.0));
+        assert_eq!(rates.rss_kib, Some(1024));
+    }
+
+    #[test]
+    fn cpu_is_clamped() {
+        let t0 = Instant::now();
+        let mut history = StatsHistory::default();
+        history.push(t0, sample(0.0, 1, 0));
+        // More CPU time than the vCPUs can use, e.g. emulator threads.
+        history.push(t0 + Duration::from_secs(1), sample(3.0, 2, 0));
+        assert_eq!(history.latest().unwrap().cpu_percent, 100.0);
+        // No vCPU count reported: taken as one.
+        history.push(t0 + Duration::from_secs(2), sample(3.5, 0, 0));
+        assert_eq!(history.latest().unwrap().cpu_percent, 50.0);
+    }
+
+    #[test]
+    fn counter_reset() {
+        let t0 = Instant::now();
+        let mut history = StatsHistory::default();
+        history.push(t0, sample(100.0, 2, 1 << 30));
+        // The domain was restarted; its counters start over.
+        history.push(t0 + Duration::from_secs(1), sample(0.5, 2, 100));
+        let rates = history.latest().unwrap();
+        assert_eq!((rates.cpu_percent, rates.disk_read, rates.net_rx), (0.0, 0.0, 0.0));
+        history.push(t0 + Duration::from_secs(2), sample(1.5, 2, 612));
+        let rates = history.latest().unwrap();
+        assert_eq!((rates.cpu_percent, rates.disk_read), (50.0, 512.0));
+    }
+
+    #[test]
+    fn same_instant_is_skipped() {
+        let t0 = Instant::now();
+        let mut history = StatsHistory::default();
+        history.push(t0, sample(0.0, 1, 0));
+        history.push(t0, sample(1.0, 1, 0));
+        assert!(history.latest().is_none());
+    }
+
+    #[test]
+    fn history_window() {
+        let t0 = Instant::now();
+        let mut history = StatsHistory::default();
+        for i in 0..=HISTORY_LEN as u64 + 5 {
+            // A growing disk rate: `i` bytes per second in the i-th interval.
+            history.push(t0 + Duration::from_secs(i), sample(0.0, 1, i * (i + 1) / 2));
+        }
+        let last = HISTORY_LEN as u64 + 5;
+        let series = history.series(usize::MAX, |r| r.disk_read);
+        assert_eq!(series.len(), HISTORY_LEN);
+        assert_eq!(series.first(), Some(&6));
+        assert_eq!(history.series(3, |r| r.disk_read), [last - 2, last - 1, last]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Graphical consoles (VNC/SPICE) opened in an external viewer.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use base64::Engine;
+use log::info;
+
+/// Used when the config file sets no `viewer`; virt-viewer tunnels remote displays itself.
+pub const DEFAULT_VIEWER: &str = "virt-viewer --connect {connect} {name}";
+
+/// Split a `viewer` setting into program and arguments, honouring shell quotes.
+pub fn parse_command(text: &str) -> Result<Vec<String>, String> {
+    match shlex::split(text) {
+        Some(words) if !words.is_empty() => Ok(words),
+        Some(_) => Err("the command is empty".to_string()),
+        None => Err(format!("unbalanced quotes in '{text}'")),
+    }
+}
+
+/// The host name in a libvirt URI, without user or port; `localhost` for local URIs.
+pub fn display_host(uri: &str) -> &str {
+    let host = crate::host_label(uri);
+    match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.split(':').next().unwrap_or(host),
+    }
+}
+
+/// Start `command` in the background with `{connect}`, `{name}` and `{display}` filled in.
+///
+/// The viewer is not waited for; it keeps running after yalv-rust exits.
+pub fn launch(command: &[String], connect: &str, name: &str, display: Option<&str>) -> Result<(), String> {
+    let args = fill_in(command, connect, name, display)?;
+    let (program, args) = args.split_first().ok_or("no viewer command")?;
+    info!("Starting viewer: {program} {}", args.join(" "));
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run {program}: {e}"))?;
+    let program = program.clone();
+    thread::spawn(move || match child.wait() {
+        Ok(status) => info!("Viewer {program} exited with {status}"),
+        Err(e) => info!("Waiting for viewer {program} failed: {e}"),
+    });
+    Ok(())
+}
+
+/// `command` with its placeholders replaced; fails if it needs a display that has no port.
+fn fill_in(command: &[String], connect: &str, name: &str, display: Option<&str>) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for word in command {
+        if word.contains("{display}") && display.is_none() {
+            return Err("no display port is assigned".to_string());
+        }
+        args.push(
+            word.replace("{connect}", connect)
+                .replace("{name}", name)
+                .replace("{display}", display.unwrap_or_default()),
+        );
+    }
+    Ok(args)
+}
+
+/// Ask the terminal to put `text` on the clipboard (OSC 52). Terminals that do
+/// not support it ignore the sequence.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{encoded}\x07")?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_hosts() {
+        let cases = [
+            ("qemu:///system", "localhost"),
+            ("qemu+ssh://root@lab1/system", "lab1"),
+            ("qemu+ssh://lab1:2222/system", "lab1"),
+            ("qemu+ssh://admin@[fd00::1]:2222/system", "fd00::1"),
+            ("qemu+tls://[fd00::2]/system", "fd00::2"),
+        ];
+        for (uri, host) in cases {
+            assert_eq!(display_host(uri), host, "{uri}");
+        }
+    }
+
+    #[test]
+    fn placeholders() {
+        let command = parse_command("remote-viewer --title '{name} on {connect}' {display}").unwrap();
+        assert_eq!(
+            fill_in(&command, "qemu+ssh://lab1/system", "web01", Some("spice://lab1:5900")).unwrap(),
+            ["remote-viewer", "--title", "web01 on qemu+ssh://lab1/system", "spice://lab1:5900"]
+        );
+        assert_eq!(
+            fill_in(&command, "qemu:///system", "web01", None).err().as_deref(),
+            Some("no display port is assigned")
+        );
+        let default = parse_command(DEFAULT_VIEWER).unwrap();
+        assert_eq!(
+            fill_in(&default, "qemu:///system", "db01", None).unwrap(),
+            ["virt-viewer", "--connect", "qemu:///system", "db01"]
+        );
+        assert_eq!(parse_command("  ").err().as_deref(), Some("the command is empty"));
+        assert!(parse_command("viewer 'open").is_err());
+    }
+}
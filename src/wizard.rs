@@ -0,0 +1,496 @@
+//! The VM creation wizard, shown in place of the VM table.
+
+use crossterm::event::KeyCode;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+
+use crate::cloudinit;
+use crate::create::{CloudInit, Firmware, Image, VmSpec};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Machine,
+    Storage,
+    CloudInit,
+    /// The dry run, before anything is created.
+    Review,
+}
+
+impl Page {
+    fn title(self) -> &'static str {
+        match self {
+            Page::Machine => "Machine",
+            Page::Storage => "Storage and network",
+            Page::CloudInit => "cloud-init",
+            Page::Review => "Review",
+        }
+    }
+}
+
+const PAGES: [Page; 4] = [Page::Machine, Page::Storage, Page::CloudInit, Page::Review];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Vcpus,
+    Memory,
+    OsVariant,
+    Firmware,
+    Start,
+    Pool,
+    Disk,
+    Image,
+    Network,
+    CloudInit,
+    User,
+    SshKey,
+    UserData,
+}
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::Name => "Name:       ",
+            Field::Vcpus => "vCPUs:      ",
+            Field::Memory => "Memory:     ",
+            Field::OsVariant => "OS variant: ",
+            Field::Firmware => "Firmware:   ",
+            Field::Start => "Start:      ",
+            Field::Pool => "Pool:       ",
+            Field::Disk => "Disk size:  ",
+            Field::Image => "Image:      ",
+            Field::Network => "Network:    ",
+            Field::CloudInit => "cloud-init: ",
+            Field::User => "User:       ",
+            Field::SshKey => "SSH key:    ",
+            Field::UserData => "User-data:  ",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Field::Name | Field::Vcpus | Field::User => "",
+            Field::Memory => "  MiB",
+            Field::OsVariant => "  e.g. ubuntu24.04, debian12 or a libosinfo id; optional",
+            Field::Firmware => "  ←/→: choose",
+            Field::Start => "  ←/→: start the VM once it is defined",
+            Field::Pool => "  storage pool for the disk and the seed",
+            Field::Disk => "  GiB",
+            Field::Image => "  path on the host: base image to put the disk on, or installer .iso; empty for a blank disk",
+            Field::Network => "  libvirt network, or bridge:NAME",
+            Field::CloudInit => "  ←/→: build a NoCloud seed ISO",
+            Field::SshKey => "  public key file",
+            Field::UserData => "  template file; empty for the built-in one",
+        }
+    }
+
+    /// Text fields, as opposed to choices.
+    fn editable(self) -> bool {
+        !matches!(self, Field::Firmware | Field::Start | Field::CloudInit)
+    }
+
+    fn numeric(self) -> bool {
+        matches!(self, Field::Vcpus | Field::Memory | Field::Disk)
+    }
+}
+
+/// The answers so far, and where the user is.
+pub struct CreateForm {
+    pub host: String,
+    page: Page,
+    field: Field,
+    name: String,
+    vcpus: String,
+    memory: String,
+    os_variant: String,
+    firmware: Firmware,
+    start: bool,
+    pool: String,
+    disk: String,
+    image: String,
+    network: String,
+    cloud_init: bool,
+    user: String,
+    ssh_key: String,
+    user_data: String,
+    /// Spec and dry run shown on the review page.
+    review: Option<(VmSpec, Vec<String>)>,
+    /// First line of the dry run shown.
+    offset: usize,
+    /// Lines that fit in the last render, for paging.
+    height: usize,
+}
+
+impl CreateForm {
+    /// A form for a VM on `host`, with `user` as the cloud-init user.
+    pub fn new(host: &str, user: &str) -> Self {
+        let ssh_key = cloudinit::default_ssh_key();
+        Self {
+            host: host.to_string(),
+            page: Page::Machine,
+            field: Field::Name,
+            name: String::new(),
+            vcpus: "2".to_string(),
+            memory: "2048".to_string(),
+            os_variant: String::new(),
+            firmware: Firmware::Bios,
+            start: true,
+            pool: "default".to_string(),
+            disk: "20".to_string(),
+            image: String::new(),
+            network: "default".to_string(),
+            cloud_init: ssh_key.is_some(),
+            user: user.to_string(),
+            ssh_key: ssh_key.unwrap_or_default(),
+            user_data: String::new(),
+            review: None,
+            offset: 0,
+            height: 0,
+        }
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        match self.page {
+            Page::Machine => vec![
+                Field::Name,
+                Field::Vcpus,
+                Field::Memory,
+                Field::OsVariant,
+                Field::Firmware,
+                Field::Start,
+            ],
+            Page::Storage => vec![Field::Pool, Field::Disk, Field::Image, Field::Network],
+            Page::CloudInit if self.cloud_init => {
+                vec![Field::CloudInit, Field::User, Field::SshKey, Field::UserData]
+            }
+            Page::CloudInit => vec![Field::CloudInit],
+            Page::Review => Vec::new(),
+        }
+    }
+
+    fn text(&mut self, field: Field) -> Option<&mut String> {
+        match field {
+            Field::Name => Some(&mut self.name),
+            Field::Vcpus => Some(&mut self.vcpus),
+            Field::Memory => Some(&mut self.memory),
+            Field::OsVariant => Some(&mut self.os_variant),
+            Field::Pool => Some(&mut self.pool),
+            Field::Disk => Some(&mut self.disk),
+            Field::Image => Some(&mut self.image),
+            Field::Network => Some(&mut self.network),
+            Field::User => Some(&mut self.user),
+            Field::SshKey => Some(&mut self.ssh_key),
+            Field::UserData => Some(&mut self.user_data),
+            Field::Firmware | Field::Start | Field::CloudInit => None,
+        }
+    }
+
+    fn value(&self, field: Field) -> String {
+        let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+        match field {
+            Field::Name => self.name.clone(),
+            Field::Vcpus => self.vcpus.clone(),
+            Field::Memory => self.memory.clone(),
+            Field::OsVariant => self.os_variant.clone(),
+            Field::Firmware => self.firmware.label().to_string(),
+            Field::Start => yes_no(self.start),
+            Field::Pool => self.pool.clone(),
+            Field::Disk => self.disk.clone(),
+            Field::Image => self.image.clone(),
+            Field::Network => self.network.clone(),
+            Field::CloudInit => yes_no(self.cloud_init),
+            Field::User => self.user.clone(),
+            Field::SshKey => self.ssh_key.clone(),
+            Field::UserData => self.user_data.clone(),
+        }
+    }
+
+    /// The spec made of the answers; reads the SSH key and user-data template.
+    fn spec(&self) -> Result<VmSpec, String> {
+        let number = |text: &str, what: &str| {
+            text.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("{what} must be a number"))
+        };
+        let cloud_init = if self.cloud_init {
+            Some(CloudInit {
+                user: self.user.trim().to_string(),
+                ssh_keys: cloudinit::read_keys(self.ssh_key.trim())?,
+                template: cloudinit::read_template(&self.user_data)?,
+            })
+        } else {
+            None
+        };
+        Ok(VmSpec {
+            name: self.name.trim().to_string(),
+            vcpus: u32::try_from(number(&self.vcpus, "vCPUs")?).map_err(|_| "too many vCPUs".to_string())?,
+            memory_mib: number(&self.memory, "Memory")?,
+            disk_gib: number(&self.disk, "Disk size")?,
+            pool: self.pool.trim().to_string(),
+            image: Image::parse(&self.image),
+            network: self.network.trim().to_string(),
+            os_variant: self.os_variant.trim().to_string(),
+            firmware: self.firmware,
+            cloud_init,
+            start: self.start,
+        })
+    }
+
+    /// Go to the next page, or fail with what is wrong on this one. The
+    /// review page shows the dry run against `uri`.
+    pub fn next_page(&mut self, uri: &str) -> Result<(), String> {
+        let next = match self.page {
+            Page::Machine => {
+                if self.name.trim().is_empty() {
+                    return Err("The VM needs a name".to_string());
+                }
+                Page::Storage
+            }
+            Page::Storage => Page::CloudInit,
+            Page::CloudInit => {
+                let spec = self.spec()?;
+                let text = spec.dry_run(uri)?;
+                self.review = Some((spec, text.lines().map(str::to_string).collect()));
+                self.offset = 0;
+                Page::Review
+            }
+            Page::Review => return Ok(()),
+        };
+        self.page = next;
+        self.field = self.fields().first().copied().unwrap_or(Field::Name);
+        Ok(())
+    }
+
+    /// Go back a page; false on the first one.
+    pub fn previous_page(&mut self) -> bool {
+        let index = PAGES.iter().position(|&p| p == self.page).unwrap_or(0);
+        if index == 0 {
+            return false;
+        }
+        self.page = PAGES[index - 1];
+        self.field = self.fields().first().copied().unwrap_or(Field::Name);
+        true
+    }
+
+    /// The reviewed spec, once on the last page.
+    pub fn reviewed(&self) -> Option<&VmSpec> {
+        self.review.as_ref().filter(|_| self.page == Page::Review).map(|(spec, _)| spec)
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let count = self.review.as_ref().map_or(0, |(_, lines)| lines.len());
+        let max = count.saturating_sub(self.height.max(1));
+        self.offset = self.offset.saturating_add_signed(delta).min(max);
+    }
+
+    /// Edit the form; Enter and Esc are handled by the caller.
+    pub fn handle_key(&mut self, key: KeyCode) {
+        if self.page == Page::Review {
+            let page = self.height.max(1) as isize;
+            match key {
+                KeyCode::Down | KeyCode::Char('j') => self.scroll(1),
+                KeyCode::Up | KeyCode::Char('k') => self.scroll(-1),
+                KeyCode::PageDown | KeyCode::Char(' ') => self.scroll(page),
+                KeyCode::PageUp => self.scroll(-page),
+                _ => {}
+            }
+            return;
+        }
+        let fields = self.fields();
+        let index = fields.iter().position(|&f| f == self.field).unwrap_or(0);
+        match key {
+            KeyCode::Tab | KeyCode::Down => self.field = fields[(index + 1) % fields.len()],
+            KeyCode::BackTab | KeyCode::Up => self.field = fields[(index + fields.len() - 1) % fields.len()],
+            KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') if !self.field.editable() => match self.field {
+                Field::Firmware => {
+                    self.firmware = match self.firmware {
+                        Firmware::Bios => Firmware::Uefi,
+                        Firmware::Uefi => Firmware::Bios,
+                    }
+                }
+                Field::Start => self.start = !self.start,
+                Field::CloudInit => self.cloud_init = !self.cloud_init,
+                _ => {}
+            },
+            KeyCode::Backspace => {
+                if let Some(text) = self.text(self.field) {
+                    text.pop();
+                }
+            }
+            KeyCode::Char(c) if !self.field.numeric() || c.is_ascii_digit() => {
+                if let Some(text) = self.text(self.field) {
+                    text.push(c);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn render(f: &mut Frame, area: Rect, form: &mut CreateForm) {
+    let step = PAGES.iter().position(|&p| p == form.page).unwrap_or(0) + 1;
+    let hints = match form.page {
+        Page::Review => "j/k: scroll, Enter: create, Esc: back",
+        _ if step == 1 => "Tab: next field, Enter: next step, Esc: cancel",
+        _ => "Tab: next field, Enter: next step, Esc: back",
+    };
+    let title = format!(
+        " New VM on {} — {step}/{}: {} ({hints}) ",
+        crate::host_label(&form.host),
+        PAGES.len(),
+        form.page.title()
+    );
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    if let Some((_, lines)) = form.review.as_ref().filter(|_| form.page == Page::Review) {
+        form.height = block.inner(area).height as usize;
+        let text: Vec<Line> = lines
+            .iter()
+            .skip(form.offset)
+            .take(form.height)
+            .map(|line| {
+                if line.starts_with('#') {
+                    Line::styled(line.as_str(), Style::default().fg(Color::Cyan))
+                } else {
+                    Line::raw(line.as_str())
+                }
+            })
+            .collect();
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    }
+
+    let dim = Style::default().fg(Color::DarkGray);
+    let lines: Vec<Line> = form
+        .fields()
+        .into_iter()
+        .map(|field| {
+            let selected = field == form.field;
+            let style = if selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let cursor = if selected && field.editable() { "|" } else { "" };
+            Line::from(vec![
+                Span::raw(field.label()),
+                Span::styled(format!("{}{cursor}", form.value(field)), style),
+                Span::styled(field.hint(), dim),
+            ])
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Backend, FakeBackend};
+    use crate::create;
+
+    fn form() -> CreateForm {
+        CreateForm {
+            name: "web02".to_string(),
+            cloud_init: false,
+            ..CreateForm::new("test:///default", "admin")
+        }
+    }
+
+    fn next(form: &mut CreateForm) -> String {
+        form.next_page("test:///default").err().unwrap_or_default()
+    }
+
+    #[test]
+    fn pages_check_their_answers() {
+        let mut form = CreateForm {
+            name: " ".to_string(),
+            ..form()
+        };
+        assert_eq!(next(&mut form), "The VM needs a name");
+        assert!(form.page == Page::Machine);
+        form.name = "web02".to_string();
+        form.memory = "lots".to_string();
+        assert_eq!(next(&mut form), "");
+        assert_eq!(next(&mut form), "");
+        assert!(form.page == Page::CloudInit);
+        assert_eq!(next(&mut form), "Memory must be a number");
+        form.memory = "2048".to_string();
+        form.disk = String::new();
+        assert_eq!(next(&mut form), "Disk size must be a number");
+        form.disk = "20".to_string();
+        form.cloud_init = true;
+        form.ssh_key = "/nonexistent/id_ed25519.pub".to_string();
+        assert!(next(&mut form).starts_with("cannot read /nonexistent/id_ed25519.pub"));
+        let key = std::env::temp_dir().join(format!("yalv-rust-wizard-{}.pub", std::process::id()));
+        std::fs::write(&key, "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIA admin@laptop\n").unwrap();
+        form.ssh_key = key.display().to_string();
+        form.user = "admin: true".to_string();
+        assert!(next(&mut form).contains("not a valid user name"));
+        form.user = "admin".to_string();
+        let result = form.next_page("test:///default");
+        std::fs::remove_file(&key).unwrap();
+        assert_eq!(result, Ok(()));
+        assert!(form.page == Page::Review);
+    }
+
+    #[test]
+    fn esc_goes_back_a_page() {
+        let mut form = form();
+        next(&mut form);
+        next(&mut form);
+        next(&mut form);
+        assert!(form.page == Page::Review);
+        for page in [Page::CloudInit, Page::Storage, Page::Machine] {
+            assert!(form.previous_page());
+            assert!(form.page == page);
+        }
+        assert!(!form.previous_page());
+        assert!(form.page == Page::Machine);
+        assert!(form.field == Field::Name);
+        assert_eq!(form.name, "web02");
+    }
+
+    #[test]
+    fn numeric_fields_take_digits_only() {
+        let mut form = form();
+        form.handle_key(KeyCode::Tab);
+        assert!(form.field == Field::Vcpus);
+        for key in [KeyCode::Backspace, KeyCode::Char('x'), KeyCode::Char('4'), KeyCode::Char('-')] {
+            form.handle_key(key);
+        }
+        assert_eq!(form.vcpus, "4");
+        form.handle_key(KeyCode::BackTab);
+        form.handle_key(KeyCode::Char('x'));
+        assert_eq!(form.name, "web02x");
+    }
+
+    #[test]
+    fn review_is_only_on_the_last_page() {
+        let mut form = form();
+        assert!(form.reviewed().is_none());
+        next(&mut form);
+        next(&mut form);
+        assert!(form.reviewed().is_none());
+        next(&mut form);
+        assert!(form.reviewed().is_some_and(|spec| spec.name == "web02" && spec.cloud_init.is_none()));
+        // Going back keeps the dry run but does not count as reviewed.
+        form.previous_page();
+        assert!(form.review.is_some());
+        assert!(form.reviewed().is_none());
+    }
+
+    #[test]
+    fn reviewed_spec_creates_the_vm() {
+        let mut form = form();
+        form.handle_key(KeyCode::Down);
+        form.handle_key(KeyCode::Backspace);
+        form.handle_key(KeyCode::Char('1'));
+        for _ in 0..3 {
+            next(&mut form);
+        }
+        let backend = FakeBackend::new(Vec::new());
+        create::create(&backend, form.reviewed().unwrap()).unwrap();
+        assert!(backend.volume_info("default", "web02.qcow2").is_ok());
+        assert!(backend.dumpxml("web02", true).unwrap().contains("<vcpu>1</vcpu>"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Background job execution so slow libvirt calls never block the UI thread.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job<T> = Box<dyn FnOnce() -> Option<T> + Send>;
+
+/// A fixed pool of threads running jobs and collecting their results.
+///
+/// A job returning `None` produces no result (e.g. because it was cancelled).
+pub struct Worker<T> {
+    jobs: Sender<Job<T>>,
+    result_tx: Sender<T>,
+    results: Receiver<T>,
+}
+
+/// Run jobs from `job_rx` until it is closed, sending their results to `result_tx`.
+fn start_thread<T: Send + 'static>(job_rx: Arc<Mutex<Receiver<Job<T>>>>, result_tx: Sender<T>) {
+    thread::spawn(move || {
+        loop {
+            let job = match job_rx.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            if let Some(result) = job()
+                && result_tx.send(result).is_err()
+            {
+                return;
+            }
+        }
+    });
+}
+
+impl<T: Send + 'static> Worker<T> {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job<T>>();
+        let (result_tx, results) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..threads {
+            start_thread(Arc::clone(&job_rx), result_tx.clone());
+        }
+        Self { jobs, result_tx, results }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() -> Option<T> + Send + 'static) {
+        let _ = self.jobs.send(Box::new(job));
+    }
+
+    /// A queue with a thread of its own, whose results arrive with the pool's.
+    /// Its jobs neither wait for nor hold up the pool's; the thread exits
+    /// once the queue is dropped and its jobs are done.
+    pub fn queue(&self) -> Queue<T> {
+        let (jobs, job_rx) = mpsc::channel::<Job<T>>();
+        start_thread(Arc::new(Mutex::new(job_rx)), self.result_tx.clone());
+        Queue { jobs }
+    }
+
+    /// Return a finished result without blocking.
+    pub fn try_recv(&self) -> Option<T> {
+        self.results.try_recv().ok()
+    }
+}
+
+/// Jobs run one after another on a dedicated thread; see [`Worker::queue`].
+pub struct Queue<T> {
+    jobs: Sender<Job<T>>,
+}
+
+impl<T> Queue<T> {
+    pub fn spawn(&self, job: impl FnOnce() -> Option<T> + Send + 'static) {
+        let _ = self.jobs.send(Box::new(job));
+    }
+}
+
+/// Shared flag telling a queued or running job that its result is no longer wanted.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Apply `f` to every item using at most `limit` threads, preserving order.
+pub fn parallel_map<T, R>(items: &[T], limit: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..limit.clamp(1, items.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        return;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn recv(worker: &Worker<&'static str>) -> &'static str {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(result) = worker.try_recv() {
+                return result;
+            }
+            assert!(Instant::now() < deadline, "no result");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn queue_does_not_wait_for_the_pool() {
+        let worker = Worker::new(1);
+        let (release, blocked) = mpsc::channel::<()>();
+        worker.spawn(move || blocked.recv().ok().map(|()| "pool"));
+        worker.queue().spawn(|| Some("queue"));
+        assert_eq!(recv(&worker), "queue");
+        release.send(()).unwrap();
+        assert_eq!(recv(&worker), "pool");
+    }
+
+    #[test]
+    fn parallel_map_keeps_the_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let squares = parallel_map(&items, 4, |&i| {
+            thread::sleep(Duration::from_millis(20 - i));
+            i * i
+        });
+        assert_eq!(squares, items.iter().map(|i| i * i).collect::<Vec<_>>());
+    }
+}
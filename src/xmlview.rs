@@ -0,0 +1,533 @@
+//! Full-screen view of a domain's XML with folding, coloring and search.
+
+use std::collections::HashSet;
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+
+use crate::domain::{self, Element, escape_xml};
+
+/// What a piece of a line is, for coloring.
+#[derive(Clone, Copy)]
+enum Part {
+    Punct,
+    Tag,
+    AttrName,
+    AttrValue,
+    Text,
+}
+
+/// One line of the pretty-printed document.
+struct XmlLine {
+    depth: usize,
+    parts: Vec<(Part, String)>,
+    /// Line of the enclosing element's start tag.
+    parent: Option<usize>,
+    /// The other tag of an element spanning several lines: the end tag's line
+    /// for a start tag, and the other way round.
+    pair: Option<usize>,
+}
+
+impl XmlLine {
+    fn text(&self) -> String {
+        self.parts.iter().map(|(_, s)| s.as_str()).collect()
+    }
+
+    fn opens(&self, index: usize) -> bool {
+        self.pair.is_some_and(|end| end > index)
+    }
+}
+
+/// The XML of one VM, shown in place of the whole UI.
+pub struct XmlView {
+    pub host: String,
+    pub vm_name: String,
+    /// Showing the configuration for the next boot (`dumpxml --inactive`).
+    pub inactive: bool,
+    /// `None` until loaded.
+    lines: Option<Result<Vec<XmlLine>, String>>,
+    /// Start tag lines whose element is collapsed.
+    folded: HashSet<usize>,
+    /// Selected line; always a visible one.
+    cursor: usize,
+    /// First visible line shown, counted in visible lines.
+    offset: usize,
+    /// Lines that fit in the last render, for paging.
+    height: usize,
+    pub query: String,
+}
+
+impl XmlView {
+    pub fn new(host: &str, vm_name: &str, inactive: bool) -> Self {
+        Self {
+            host: host.to_string(),
+            vm_name: vm_name.to_string(),
+            inactive,
+            lines: None,
+            folded: HashSet::new(),
+            cursor: 0,
+            offset: 0,
+            height: 0,
+            query: String::new(),
+        }
+    }
+
+    /// Show `result`, e.g. after switching between live and inactive XML.
+    pub fn set_xml(&mut self, result: Result<String, String>) {
+        let lines = result.and_then(|xml| {
+            let root = domain::parse_tree(&xml).map_err(|e| format!("unable to parse the XML: {e}"))?;
+            let mut lines = Vec::new();
+            push_element(&mut lines, &root, 0, None);
+            Ok(lines)
+        });
+        let count = lines.as_ref().map_or(0, Vec::len);
+        self.lines = Some(lines);
+        self.folded.clear();
+        self.cursor = self.cursor.min(count.saturating_sub(1));
+    }
+
+    /// Drop the shown XML, e.g. while another one loads.
+    pub fn clear(&mut self) {
+        self.lines = None;
+    }
+
+    fn lines(&self) -> &[XmlLine] {
+        match &self.lines {
+            Some(Ok(lines)) => lines,
+            _ => &[],
+        }
+    }
+
+    /// Indexes of the lines not hidden in a folded element.
+    fn visible(&self) -> Vec<usize> {
+        let lines = self.lines();
+        let mut visible = Vec::with_capacity(lines.len());
+        let mut i = 0;
+        while i < lines.len() {
+            visible.push(i);
+            i = match lines[i].pair {
+                Some(end) if end > i && self.folded.contains(&i) => end + 1,
+                _ => i + 1,
+            };
+        }
+        visible
+    }
+
+    /// Move the cursor by `delta` visible lines.
+    pub fn scroll(&mut self, delta: isize) {
+        let visible = self.visible();
+        let Some(position) = visible.iter().position(|&i| i == self.cursor) else {
+            return;
+        };
+        let target = position.saturating_add_signed(delta).min(visible.len() - 1);
+        self.cursor = visible[target];
+    }
+
+    pub fn page(&mut self, down: bool) {
+        let delta = self.height.max(1) as isize;
+        self.scroll(if down { delta } else { -delta });
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.visible().last().copied().unwrap_or(0);
+    }
+
+    /// Start tag line of the element the cursor is on, if it spans several lines.
+    fn element_at_cursor(&self) -> Option<usize> {
+        let line = self.lines().get(self.cursor)?;
+        match line.pair {
+            Some(start) if start < self.cursor => Some(start),
+            Some(_) => Some(self.cursor),
+            None => None,
+        }
+    }
+
+    /// Collapse (`Some(true)`), expand or toggle the element at the cursor.
+    pub fn fold(&mut self, fold: Option<bool>) {
+        let Some(start) = self.element_at_cursor() else {
+            return;
+        };
+        let fold = fold.unwrap_or(!self.folded.contains(&start));
+        if fold {
+            self.folded.insert(start);
+            self.cursor = start;
+        } else {
+            self.folded.remove(&start);
+        }
+    }
+
+    /// Collapse every element below the root.
+    pub fn fold_all(&mut self) {
+        let lines = self.lines();
+        let starts: Vec<usize> = (0..lines.len())
+            .filter(|&i| lines[i].depth > 0 && lines[i].opens(i))
+            .collect();
+        self.folded.extend(starts);
+        // Move up to the outermost collapsed element containing the cursor.
+        let mut i = self.cursor;
+        while let Some(parent) = self.lines().get(i).and_then(|l| l.parent) {
+            if self.folded.contains(&parent) {
+                self.cursor = parent;
+            }
+            i = parent;
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folded.clear();
+    }
+
+    /// Move to the next (or previous) line containing `query`, expanding the
+    /// elements around it; the cursor line itself counts when `here`.
+    pub fn find(&mut self, forward: bool, here: bool) -> bool {
+        let query = self.query.to_ascii_lowercase();
+        let lines = self.lines();
+        if query.is_empty() || lines.is_empty() {
+            return false;
+        }
+        let count = lines.len();
+        let skip = if here { 0 } else { 1 };
+        let found = (skip..count + skip)
+            .map(|step| {
+                if forward {
+                    (self.cursor + step) % count
+                } else {
+                    (self.cursor + count - step % count) % count
+                }
+            })
+            // End tags only repeat the name of their start tag.
+            .filter(|&i| lines[i].pair.is_none_or(|start| start > i))
+            .find(|&i| lines[i].text().to_ascii_lowercase().contains(&query));
+        let Some(index) = found else {
+            return false;
+        };
+        let mut parent = lines[index].parent;
+        while let Some(p) = parent {
+            self.folded.remove(&p);
+            parent = self.lines()[p].parent;
+        }
+        self.cursor = index;
+        true
+    }
+}
+
+fn push_element(lines: &mut Vec<XmlLine>, elem: &Element, depth: usize, parent: Option<usize>) {
+    let mut parts = vec![(Part::Punct, "<".to_string()), (Part::Tag, elem.name.clone())];
+    for (name, value) in &elem.attrs {
+        parts.push((Part::Punct, " ".to_string()));
+        parts.push((Part::AttrName, name.clone()));
+        parts.push((Part::Punct, "=".to_string()));
+        parts.push((Part::AttrValue, format!("'{}'", escape_xml(value))));
+    }
+    let end_tag = || {
+        vec![
+            (Part::Punct, "</".to_string()),
+            (Part::Tag, elem.name.clone()),
+            (Part::Punct, ">".to_string()),
+        ]
+    };
+    let start = lines.len();
+    if elem.children.is_empty() {
+        match elem.text() {
+            Some(text) => {
+                parts.push((Part::Punct, ">".to_string()));
+                parts.push((Part::Text, escape_xml(text)));
+                parts.extend(end_tag());
+            }
+            None => parts.push((Part::Punct, "/>".to_string())),
+        }
+        lines.push(XmlLine {
+            depth,
+            parts,
+            parent,
+            pair: None,
+        });
+        return;
+    }
+    parts.push((Part::Punct, ">".to_string()));
+    lines.push(XmlLine {
+        depth,
+        parts,
+        parent,
+        pair: None,
+    });
+    if let Some(text) = elem.text() {
+        lines.push(XmlLine {
+            depth: depth + 1,
+            parts: vec![(Part::Text, escape_xml(text))],
+            parent: Some(start),
+            pair: None,
+        });
+    }
+    for child in &elem.children {
+        push_element(lines, child, depth + 1, Some(start));
+    }
+    lines[start].pair = Some(lines.len());
+    lines.push(XmlLine {
+        depth,
+        parts: end_tag(),
+        parent,
+        pair: Some(start),
+    });
+}
+
+fn part_style(part: Part) -> Style {
+    match part {
+        Part::Punct => Style::default().fg(Color::DarkGray),
+        Part::Tag => Style::default().fg(Color::Cyan),
+        Part::AttrName => Style::default().fg(Color::Yellow),
+        Part::AttrValue => Style::default().fg(Color::Green),
+        Part::Text => Style::default(),
+    }
+}
+
+/// Byte ranges of `query` in `text`, ignoring ASCII case.
+fn match_ranges(text: &str, query: &str) -> Vec<(usize, usize)> {
+    let (text, query) = (text.to_ascii_lowercase(), query.to_ascii_lowercase());
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    while !query.is_empty()
+        && let Some(found) = text[pos..].find(&query)
+    {
+        ranges.push((pos + found, pos + found + query.len()));
+        pos += found + query.len();
+    }
+    ranges
+}
+
+/// Colored spans of `parts`, with the matches of `query` highlighted.
+fn spans(parts: &[(Part, String)], query: &str) -> Vec<Span<'static>> {
+    let text: String = parts.iter().map(|(_, s)| s.as_str()).collect();
+    let ranges = match_ranges(&text, query);
+    let matched = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (part, s) in parts {
+        let end = start + s.len();
+        let mut pos = start;
+        for &(a, b) in &ranges {
+            let (a, b) = (a.max(pos), b.min(end));
+            if a >= b {
+                continue;
+            }
+            if a > pos {
+                spans.push(Span::styled(text[pos..a].to_string(), part_style(*part)));
+            }
+            spans.push(Span::styled(text[a..b].to_string(), matched));
+            pos = b;
+        }
+        if pos < end {
+            spans.push(Span::styled(text[pos..end].to_string(), part_style(*part)));
+        }
+        start = end;
+    }
+    spans
+}
+
+pub fn render(f: &mut Frame, area: Rect, view: &mut XmlView) {
+    let title = format!(
+        " XML of '{}' ({}) — Enter: fold, -/+: fold/unfold all, /: search, n/N: next/previous, i: {}, e: edit, Esc: close ",
+        view.vm_name,
+        if view.inactive { "inactive" } else { "live" },
+        if view.inactive { "live" } else { "inactive" },
+    );
+    let block = Block::default().borders(Borders::ALL).title(title);
+    match &view.lines {
+        None => {
+            f.render_widget(Paragraph::new("Loading…").block(block), area);
+            return;
+        }
+        Some(Err(e)) => {
+            let text = Paragraph::new(e.as_str()).style(Style::default().fg(Color::Red));
+            f.render_widget(text.block(block), area);
+            return;
+        }
+        Some(Ok(_)) => {}
+    }
+
+    let inner = block.inner(area);
+    view.height = inner.height as usize;
+    let visible = view.visible();
+    let position = visible.iter().position(|&i| i == view.cursor).unwrap_or(0);
+    if position < view.offset {
+        view.offset = position;
+    } else if position >= view.offset + view.height {
+        view.offset = position + 1 - view.height;
+    }
+
+    let lines = view.lines();
+    let text: Vec<Line> = visible
+        .iter()
+        .skip(view.offset)
+        .take(view.height)
+        .map(|&i| {
+            let line = &lines[i];
+            let folded = view.folded.contains(&i);
+            let marker = match () {
+                _ if !line.opens(i) => "  ",
+                _ if folded => "▸ ",
+                _ => "▾ ",
+            };
+            let mut spans = vec![Span::raw(format!("{}{marker}", "  ".repeat(line.depth)))];
+            spans.extend(self::spans(&line.parts, &view.query));
+            if folded && let Some(end) = line.pair {
+                spans.push(Span::styled("…", Style::default().fg(Color::DarkGray)));
+                spans.extend(self::spans(&lines[end].parts, &view.query));
+            }
+            let mut line = Line::from(spans);
+            if i == view.cursor {
+                line = line.style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            line
+        })
+        .collect();
+    f.render_widget(Paragraph::new(text).block(block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = "<domain type='kvm'><name>vm</name>\
+        <os><type arch='x86_64'>hvm</type><boot dev='hd'/></os>\
+        <devices><disk type='file' device='disk'><source file='/images/vm.qcow2'/><target dev='vda'/></disk>\
+        <interface type='network'><source network='default'/></interface></devices></domain>";
+
+    fn view() -> XmlView {
+        let mut view = XmlView::new("test:///demo", "vm", false);
+        view.set_xml(Ok(XML.to_string()));
+        view
+    }
+
+    /// The visible lines, indented by depth.
+    fn shown(view: &XmlView) -> Vec<String> {
+        let lines = view.lines();
+        view.visible()
+            .into_iter()
+            .map(|i| format!("{}{}", "  ".repeat(lines[i].depth), lines[i].text()))
+            .collect()
+    }
+
+    #[test]
+    fn lines_and_pairs() {
+        let view = view();
+        assert_eq!(
+            shown(&view),
+            [
+                "<domain type='kvm'>",
+                "  <name>vm</name>",
+                "  <os>",
+                "    <type arch='x86_64'>hvm</type>",
+                "    <boot dev='hd'/>",
+                "  </os>",
+                "  <devices>",
+                "    <disk type='file' device='disk'>",
+                "      <source file='/images/vm.qcow2'/>",
+                "      <target dev='vda'/>",
+                "    </disk>",
+                "    <interface type='network'>",
+                "      <source network='default'/>",
+                "    </interface>",
+                "  </devices>",
+                "</domain>",
+            ]
+        );
+        let lines = view.lines();
+        let pairs: Vec<_> = lines.iter().map(|l| l.pair).collect();
+        assert_eq!(pairs[0], Some(15));
+        assert_eq!((pairs[2], pairs[5]), (Some(5), Some(2)));
+        assert_eq!((pairs[7], pairs[10]), (Some(10), Some(7)));
+        assert_eq!((pairs[1], pairs[8]), (None, None));
+        let parents: Vec<_> = lines.iter().map(|l| l.parent).collect();
+        assert_eq!(parents[..4], [None, Some(0), Some(0), Some(2)]);
+        assert_eq!((parents[9], parents[10], parents[12]), (Some(7), Some(6), Some(11)));
+    }
+
+    #[test]
+    fn fold_the_element_at_the_cursor() {
+        let mut view = view();
+        // On an end tag, its element folds and the cursor moves to the start tag.
+        view.cursor = 10;
+        view.fold(None);
+        assert_eq!(view.cursor, 7);
+        assert_eq!(view.visible(), [0, 1, 2, 3, 4, 5, 6, 7, 11, 12, 13, 14, 15]);
+        view.scroll(1);
+        assert_eq!(view.cursor, 11);
+        view.scroll(-1);
+        view.fold(None);
+        assert_eq!(view.visible().len(), 16);
+        // Lines without an end tag of their own fold nothing.
+        view.cursor = 8;
+        view.fold(Some(true));
+        assert!(view.folded.is_empty());
+    }
+
+    #[test]
+    fn fold_and_unfold_all() {
+        let mut view = view();
+        view.cursor = 9;
+        view.fold_all();
+        assert_eq!(shown(&view), ["<domain type='kvm'>", "  <name>vm</name>", "  <os>", "  <devices>", "</domain>"]);
+        // The cursor moved up to the outermost folded element around it.
+        assert_eq!(view.cursor, 6);
+        view.end();
+        assert_eq!(view.cursor, 15);
+        view.unfold_all();
+        assert_eq!(view.visible().len(), 16);
+    }
+
+    #[test]
+    fn find_wraps_around_and_unfolds() {
+        let mut view = view();
+        view.fold_all();
+        view.query = "DEV=".to_string();
+        assert!(view.find(true, false));
+        assert_eq!(view.cursor, 4);
+        assert!(view.visible().contains(&4));
+        assert!(view.find(true, false));
+        assert_eq!(view.cursor, 9);
+        assert!(!view.folded.contains(&6) && !view.folded.contains(&7));
+        assert!(view.find(true, false));
+        assert_eq!(view.cursor, 4);
+        assert!(view.find(false, false));
+        assert_eq!(view.cursor, 9);
+        // `here` keeps a match on the cursor line.
+        assert!(view.find(true, true));
+        assert_eq!(view.cursor, 9);
+        // End tags are skipped: the only match is the start tag itself.
+        view.query = "interface".to_string();
+        assert!(view.find(true, false));
+        assert_eq!(view.cursor, 11);
+        assert!(view.find(true, false));
+        assert_eq!(view.cursor, 11);
+        view.query = "nowhere".to_string();
+        assert!(!view.find(true, false));
+        assert_eq!(view.cursor, 11);
+    }
+
+    #[test]
+    fn fixture_folds_to_its_sections() {
+        let mut view = XmlView::new("test:///demo", "web01", false);
+        view.set_xml(Ok(include_str!("../fixtures/web01-uefi.xml").to_string()));
+        view.fold_all();
+        let sections = shown(&view);
+        assert!(sections.contains(&"  <devices>".to_string()), "{sections:?}");
+        assert!(view.visible().iter().all(|&i| view.lines()[i].depth <= 1));
+        view.query = "OVMF_CODE".to_string();
+        assert!(view.find(true, false));
+        assert!(view.lines()[view.cursor].text().starts_with("<loader"));
+    }
+
+    #[test]
+    fn match_ranges_ignore_case() {
+        assert_eq!(match_ranges("<source dev='vda'/>", "DEV"), [(8, 11)]);
+        assert_eq!(match_ranges("aaaa", "aa"), [(0, 2), (2, 4)]);
+        assert_eq!(match_ranges("<disk type='file'>", "e"), [(9, 10), (15, 16)]);
+        assert!(match_ranges("<disk/>", "").is_empty());
+        assert!(match_ranges("<disk/>", "cdrom").is_empty());
+    }
+}